@@ -0,0 +1,409 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value as JsonValue;
+
+use crate::device::{self, CurrentLimits, PositionPidParams, Supply, VelocityPidParams, VoltageLimits, ROBOCLAW};
+use crate::motor::Motor;
+use crate::settings;
+use crate::sim;
+
+// Device configuration backup: everything the device layer can read back, in one
+// versioned JSON document, so a tuned controller can be cloned onto a replacement board.
+
+pub const BACKUP_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MotorConfig {
+    pub velocity_pid: VelocityPidParams, // includes QPPS
+    pub position_pid: PositionPidParams, // includes the position limits
+    pub encoder_mode: u8,
+    pub current_limits: CurrentLimits,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceConfigBackup {
+    pub version: u32,
+    pub created_ms: u64, // unix time
+    pub address: u8,
+    pub simulation: bool,
+    pub config_word: u16,
+    pub main_battery_limits: VoltageLimits,
+    pub logic_battery_limits: VoltageLimits,
+    pub motors: [MotorConfig; 2],
+}
+
+impl DeviceConfigBackup {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let version = serde_json::from_str::<JsonValue>(&text)
+            .map_err(|e| format!("Invalid backup {}: {}", path, e))?
+            .get("version")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| format!("Backup {} has no version", path))?;
+        if version > BACKUP_VERSION as u64 {
+            return Err(format!("Backup {} is version {}, this build reads up to {}", path, version, BACKUP_VERSION));
+        }
+        serde_json::from_str(&text).map_err(|e| format!("Invalid backup {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize backup: {}", e))?;
+        std::fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path, e))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestoreOptions {
+    pub save_eeprom: bool,
+    // The config word carries the serial mode, baud rate and address; writing it can
+    // cut the connection, so it is only restored on request (and last).
+    pub include_config_word: bool,
+    // Applying selected fields: also apply fields classified as not safe
+    pub allow_unsafe: bool,
+}
+
+// Outcome of one restored field
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldResult {
+    pub field: String,
+    pub expected: JsonValue,
+    pub actual: Option<JsonValue>,
+    pub verified: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreReport {
+    pub fields: Vec<FieldResult>,
+    pub verified: bool,
+    pub eeprom_saved: bool,
+    pub eeprom_error: Option<String>, // the fields were written but the EEPROM save failed
+}
+
+pub fn read_motor_config(motor: Motor) -> Result<MotorConfig, String> {
+    let (m1_mode, m2_mode) = device::read_encoder_modes_sync()?;
+    Ok(MotorConfig {
+        velocity_pid: device::read_velocity_pid_sync(motor)?,
+        position_pid: device::read_position_pid_sync(motor)?,
+        encoder_mode: motor.pick(m1_mode, m2_mode),
+        current_limits: device::read_current_limits_sync(motor)?,
+    })
+}
+
+pub fn backup_device_config_sync() -> Result<DeviceConfigBackup, String> {
+    let address = {
+        let guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
+        guard.as_ref().ok_or("Roboclaw not initialized")?.addr
+    };
+    let created_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    Ok(DeviceConfigBackup {
+        version: BACKUP_VERSION,
+        created_ms,
+        address,
+        simulation: sim::is_simulation_enabled(),
+        config_word: device::read_config_sync()?,
+        main_battery_limits: device::read_voltage_limits_sync(Supply::Main)?,
+        logic_battery_limits: device::read_voltage_limits_sync(Supply::Logic)?,
+        motors: [read_motor_config(Motor::M1)?, read_motor_config(Motor::M2)?],
+    })
+}
+
+// Write one field group, read it back and compare. Groups not `wanted` are skipped.
+fn restore_field<T: Serialize + PartialEq>(
+    fields: &mut Vec<FieldResult>,
+    wanted: &dyn Fn(&str) -> bool,
+    field: String,
+    expected: &T,
+    write: impl FnOnce() -> Result<(), String>,
+    read: impl FnOnce() -> Result<T, String>,
+) {
+    if !wanted(&field) { return; }
+    let to_json = |v: &T| serde_json::to_value(v).unwrap_or(JsonValue::Null);
+    let (actual, verified, error) = match write().and_then(|_| read()) {
+        Ok(v) => {
+            let ok = &v == expected;
+            let error = if ok { None } else { Some("Read-back value differs".to_string()) };
+            (Some(to_json(&v)), ok, error)
+        }
+        Err(e) => (None, false, Some(e)),
+    };
+    println!("[BACKUP] {}: {}", field, if verified { "ok" } else { "FAILED" });
+    fields.push(FieldResult { field, expected: to_json(expected), actual, verified, error });
+}
+
+// Write the `wanted` field groups of `config` with read-back verification. Groups are
+// independent: a failure is reported and the rest are still attempted. EEPROM is only
+// written when every group verified, so a half-restored board does not keep its state
+// across power cycles, and a failed save is reported alongside the field results. The
+// config word goes last since it can drop the link.
+fn restore_groups(config: &DeviceConfigBackup, wanted: &dyn Fn(&str) -> bool, save_eeprom: bool) -> Result<RestoreReport, String> {
+    if config.version > BACKUP_VERSION {
+        return Err(format!("Backup version {} is newer than supported ({})", config.version, BACKUP_VERSION));
+    }
+    let mut fields = Vec::new();
+    for m in Motor::ALL {
+        let cfg = &config.motors[m.index()];
+        restore_field(&mut fields, wanted, format!("{}.velocity_pid", m), &cfg.velocity_pid,
+            || device::set_velocity_pid_sync(m, cfg.velocity_pid.clone()),
+            || device::read_velocity_pid_sync(m));
+        restore_field(&mut fields, wanted, format!("{}.position_pid", m), &cfg.position_pid,
+            || device::set_position_pid_sync(m, cfg.position_pid.clone()),
+            || device::read_position_pid_sync(m));
+        restore_field(&mut fields, wanted, format!("{}.encoder_mode", m), &cfg.encoder_mode,
+            || device::set_encoder_mode_sync(m, cfg.encoder_mode),
+            || device::read_encoder_modes_sync().map(|(m1, m2)| m.pick(m1, m2)));
+        restore_field(&mut fields, wanted, format!("{}.current_limits", m), &cfg.current_limits,
+            || device::set_current_limits_sync(m, cfg.current_limits),
+            || device::read_current_limits_sync(m));
+    }
+    for (supply, limits) in [(Supply::Main, &config.main_battery_limits), (Supply::Logic, &config.logic_battery_limits)] {
+        restore_field(&mut fields, wanted, supply.pick("main_battery_limits", "logic_battery_limits").to_string(), limits,
+            || device::set_voltage_limits_sync(supply, *limits),
+            || device::read_voltage_limits_sync(supply));
+    }
+    restore_field(&mut fields, wanted, "config_word".to_string(), &config.config_word,
+        || device::set_config_sync(config.config_word),
+        device::read_config_sync);
+
+    let verified = fields.iter().all(|f| f.verified);
+    let mut eeprom_error = None;
+    if save_eeprom && verified {
+        if let Err(e) = device::write_settings_eeprom_sync() {
+            println!("[BACKUP] EEPROM save: FAILED");
+            eeprom_error = Some(e);
+        }
+    }
+    let eeprom_saved = save_eeprom && verified && eeprom_error.is_none();
+    Ok(RestoreReport { fields, verified, eeprom_saved, eeprom_error })
+}
+
+pub fn restore_device_config_sync(backup: &DeviceConfigBackup, options: &RestoreOptions) -> Result<RestoreReport, String> {
+    restore_groups(backup, &|group| group != "config_word" || options.include_config_word, options.save_eeprom)
+}
+
+// Diffable leaf fields and their units: per motor (prefixed "M1."/"M2.") and controller-wide
+const MOTOR_FIELDS: [(&str, &str); 14] = [
+    ("velocity_pid.p", "16.16 fixed point"),
+    ("velocity_pid.i", "16.16 fixed point"),
+    ("velocity_pid.d", "16.16 fixed point"),
+    ("velocity_pid.qpps", "counts/s"),
+    ("position_pid.p", "16.16 fixed point"),
+    ("position_pid.i", "16.16 fixed point"),
+    ("position_pid.d", "16.16 fixed point"),
+    ("position_pid.max_i", "16.16 fixed point"),
+    ("position_pid.deadzone", "counts"),
+    ("position_pid.min", "counts"),
+    ("position_pid.max", "counts"),
+    ("encoder_mode", "bit flags"),
+    ("current_limits.max", "10 mA"),
+    ("current_limits.min", "10 mA"),
+];
+const DEVICE_FIELDS: [(&str, &str); 5] = [
+    ("main_battery_limits.min", "0.1 V"),
+    ("main_battery_limits.max", "0.1 V"),
+    ("logic_battery_limits.min", "0.1 V"),
+    ("logic_battery_limits.max", "0.1 V"),
+    ("config_word", "bit flags"),
+];
+
+// A field that differs between the live controller and the saved configuration
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldDiff {
+    pub field: String, // e.g. "M1.velocity_pid.qpps"
+    pub live: i64,
+    pub saved: i64,
+    pub unit: &'static str,
+    pub safe_to_apply: bool,
+    pub reason: Option<String>, // why applying it needs care
+}
+
+// All leaf fields as (name, unit, JSON pointer into a serialized DeviceConfigBackup)
+fn leaf_fields() -> Vec<(String, &'static str, String)> {
+    let mut out = Vec::new();
+    for m in Motor::ALL {
+        for (path, unit) in MOTOR_FIELDS {
+            out.push((format!("{}.{}", m, path), unit, format!("/motors/{}/{}", m.index(), path.replace('.', "/"))));
+        }
+    }
+    for (path, unit) in DEVICE_FIELDS {
+        out.push((path.to_string(), unit, format!("/{}", path.replace('.', "/"))));
+    }
+    out
+}
+
+// Restore group a leaf belongs to ("M1.velocity_pid.p" -> "M1.velocity_pid")
+fn group_of(field: &str) -> &str {
+    let rel = field.strip_prefix("M1.").or_else(|| field.strip_prefix("M2.")).unwrap_or(field);
+    match rel.rfind('.') {
+        Some(i) => &field[..field.len() - rel.len() + i],
+        None => field,
+    }
+}
+
+// Changes that can lose the link, invert feedback or loosen a protection are not safe to apply blindly
+fn classify(field: &str, live: i64, saved: i64) -> Option<String> {
+    let rel = field.strip_prefix("M1.").or_else(|| field.strip_prefix("M2.")).unwrap_or(field);
+    match rel {
+        "config_word" => Some("Changes control mode, baud rate or address; the connection may drop".into()),
+        "encoder_mode" => Some("Changes encoder type or direction; inverted feedback can run the motor away".into()),
+        // qpps scales every speed command and the velocity loop, so it counts as tuning
+        "velocity_pid.p" | "velocity_pid.i" | "velocity_pid.d" | "velocity_pid.qpps" => Some("Retunes the velocity loop".into()),
+        "position_pid.p" | "position_pid.i" | "position_pid.d" | "position_pid.max_i" => Some("Retunes the position loop".into()),
+        "current_limits.max" if saved > live => Some("Raises the current limit".into()),
+        "main_battery_limits.min" | "logic_battery_limits.min" if saved < live => Some("Lowers the undervoltage cutoff".into()),
+        "main_battery_limits.max" | "logic_battery_limits.max" if saved > live => Some("Raises the overvoltage cutoff".into()),
+        "position_pid.min" if saved < live => Some("Widens the position range".into()),
+        "position_pid.max" if saved > live => Some("Widens the position range".into()),
+        _ => None,
+    }
+}
+
+fn to_json(config: &DeviceConfigBackup) -> Result<JsonValue, String> {
+    serde_json::to_value(config).map_err(|e| format!("Failed to encode config: {}", e))
+}
+
+// Per-field differences between two configurations
+pub fn diff_configs(live: &DeviceConfigBackup, saved: &DeviceConfigBackup) -> Result<Vec<FieldDiff>, String> {
+    let (live_json, saved_json) = (to_json(live)?, to_json(saved)?);
+    let mut diffs = Vec::new();
+    for (field, unit, ptr) in leaf_fields() {
+        let get = |v: &JsonValue| v.pointer(&ptr).and_then(|x| x.as_i64()).ok_or_else(|| format!("Missing field {}", field));
+        let (l, s) = (get(&live_json)?, get(&saved_json)?);
+        if l != s {
+            let reason = classify(&field, l, s);
+            diffs.push(FieldDiff { field, live: l, saved: s, unit, safe_to_apply: reason.is_none(), reason });
+        }
+    }
+    Ok(diffs)
+}
+
+// Saved configuration from a backup file or a profile
+pub fn load_saved_config(path: Option<&str>, profile: Option<&str>) -> Result<DeviceConfigBackup, String> {
+    match (path, profile) {
+        (Some(path), None) => DeviceConfigBackup::load(path),
+        (None, Some(name)) => {
+            let settings = settings::get_settings_sync()?;
+            let profile = settings.profile(name).ok_or_else(|| format!("No profile named \"{}\"", name))?;
+            profile.device_config.clone().ok_or_else(|| format!("Profile \"{}\" has no saved device configuration", name))
+        }
+        _ => Err("Give either a backup path or a profile name".into()),
+    }
+}
+
+pub fn diff_device_config_sync(saved: &DeviceConfigBackup) -> Result<Vec<FieldDiff>, String> {
+    diff_configs(&backup_device_config_sync()?, saved)
+}
+
+// Apply only the selected fields of `saved` on top of the live configuration. Fields that
+// are not safe to apply are refused unless `options.allow_unsafe` is set.
+pub fn apply_device_config_fields_sync(saved: &DeviceConfigBackup, fields: &[String], options: &RestoreOptions) -> Result<RestoreReport, String> {
+    let live = backup_device_config_sync()?;
+    let diffs = diff_configs(&live, saved)?;
+    let known = leaf_fields();
+    let mut patched = to_json(&live)?;
+    let mut groups: Vec<&str> = Vec::new();
+    for field in fields {
+        let (_, _, ptr) = known.iter().find(|(f, _, _)| f == field).ok_or_else(|| format!("Unknown field {}", field))?;
+        let Some(diff) = diffs.iter().find(|d| &d.field == field) else { continue }; // already equal
+        if !diff.safe_to_apply && !options.allow_unsafe {
+            return Err(format!("{} is not safe to apply: {}", field, diff.reason.as_deref().unwrap_or("")));
+        }
+        *patched.pointer_mut(ptr).ok_or_else(|| format!("Missing field {}", field))? = JsonValue::from(diff.saved);
+        groups.push(group_of(field));
+    }
+    let patched: DeviceConfigBackup = serde_json::from_value(patched).map_err(|e| format!("Invalid value: {}", e))?;
+    restore_groups(&patched, &|group| groups.contains(&group), options.save_eeprom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{tests::TEST_MUTEX, SIM_STATE};
+
+    #[test]
+    fn backup_restores_onto_another_board() {
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        sim::set_simulation_mode_sync(true).unwrap();
+
+        // tune the "old" board, back it up, then reset to factory state
+        device::set_velocity_pid_sync(Motor::M2, VelocityPidParams { p: 0x30000, i: 0x2000, d: 0, qpps: 52000 }).unwrap();
+        device::set_current_limits_sync(Motor::M1, CurrentLimits { max: 900, min: 0 }).unwrap();
+        device::set_voltage_limits_sync(Supply::Main, VoltageLimits { min: 105, max: 260 }).unwrap();
+        let backup = backup_device_config_sync().unwrap();
+        let path = std::env::temp_dir().join(format!("motion_studio_backup_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        backup.save(path).unwrap();
+        *SIM_STATE.lock().unwrap() = sim::initial_sim_state();
+
+        let loaded = DeviceConfigBackup::load(path).unwrap();
+        assert_eq!(loaded, backup);
+        let report = restore_device_config_sync(&loaded, &RestoreOptions { save_eeprom: true, ..Default::default() }).unwrap();
+        assert!(report.verified && report.eeprom_saved && report.eeprom_error.is_none());
+        assert!(report.fields.iter().all(|f| f.field != "config_word"));
+        assert_eq!(device::read_velocity_pid_sync(Motor::M2).unwrap().qpps, 52000);
+        assert_eq!(device::read_voltage_limits_sync(Supply::Main).unwrap().min, 105);
+        assert_eq!(SIM_STATE.lock().unwrap().eeprom_writes, 1);
+
+        // a rejected field fails verification and blocks the EEPROM save
+        let mut bad = loaded.clone();
+        bad.logic_battery_limits = VoltageLimits { min: 300, max: 100 };
+        let report = restore_device_config_sync(&bad, &RestoreOptions { save_eeprom: true, ..Default::default() }).unwrap();
+        assert!(!report.verified && !report.eeprom_saved);
+        let failed: Vec<_> = report.fields.iter().filter(|f| !f.verified).map(|f| f.field.as_str()).collect();
+        assert_eq!(failed, ["logic_battery_limits"]);
+
+        std::fs::write(path, r#"{"version": 99}"#).unwrap();
+        assert!(DeviceConfigBackup::load(path).is_err());
+        let _ = std::fs::remove_file(path);
+        *SIM_STATE.lock().unwrap() = sim::initial_sim_state();
+        sim::set_simulation_mode_sync(false).unwrap();
+    }
+
+    #[test]
+    fn diff_classifies_and_applies_selected_fields() {
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        sim::set_simulation_mode_sync(true).unwrap();
+        *SIM_STATE.lock().unwrap() = sim::initial_sim_state();
+
+        let mut saved = backup_device_config_sync().unwrap();
+        saved.motors[0].velocity_pid.qpps = 30000;
+        saved.motors[0].velocity_pid.p = 0x20000;
+        saved.motors[1].current_limits.max = 2500; // raised: unsafe
+        saved.motors[1].current_limits.min = 50;
+        saved.config_word = 0x00C3;
+
+        let diffs = diff_device_config_sync(&saved).unwrap();
+        let get = |f: &str| diffs.iter().find(|d| d.field == f).unwrap();
+        assert_eq!(diffs.len(), 5);
+        assert_eq!((get("M1.velocity_pid.qpps").live, get("M1.velocity_pid.qpps").saved), (44000, 30000));
+        assert_eq!(get("M1.velocity_pid.qpps").unit, "counts/s");
+        assert!(!get("M1.velocity_pid.p").safe_to_apply);
+        assert!(!get("M1.velocity_pid.qpps").safe_to_apply);
+        assert!(!get("M2.current_limits.max").safe_to_apply);
+        assert!(get("M2.current_limits.min").safe_to_apply);
+        assert!(!get("config_word").safe_to_apply);
+
+        // only qpps is applied; the rest of the M1 velocity PID keeps its live value
+        let qpps = ["M1.velocity_pid.qpps".to_string()];
+        assert!(apply_device_config_fields_sync(&saved, &qpps, &RestoreOptions::default()).is_err());
+        let options = RestoreOptions { allow_unsafe: true, ..Default::default() };
+        let report = apply_device_config_fields_sync(&saved, &qpps, &options).unwrap();
+        assert!(report.verified);
+        assert_eq!(report.fields.len(), 1);
+        let v = device::read_velocity_pid_sync(Motor::M1).unwrap();
+        assert_eq!((v.qpps, v.p), (30000, 0x10000));
+
+        let unsafe_fields = ["M2.current_limits.max".to_string(), "M2.current_limits.min".to_string()];
+        assert!(apply_device_config_fields_sync(&saved, &unsafe_fields, &RestoreOptions::default()).is_err());
+        assert!(apply_device_config_fields_sync(&saved, &["M3.encoder_mode".into()], &RestoreOptions::default()).is_err());
+        assert!(apply_device_config_fields_sync(&saved, &unsafe_fields, &options).unwrap().verified);
+        assert_eq!(device::read_current_limits_sync(Motor::M2).unwrap(), CurrentLimits { max: 2500, min: 50 });
+        assert_eq!(diff_device_config_sync(&saved).unwrap().len(), 2);
+
+        *SIM_STATE.lock().unwrap() = sim::initial_sim_state();
+        sim::set_simulation_mode_sync(false).unwrap();
+    }
+}
@@ -0,0 +1,215 @@
+use serde::{Serialize, Deserialize};
+
+use crate::device::VoltageLimits;
+
+// Main battery shared by both simulated motors. The open-circuit voltage follows the
+// state of charge, the bus sags across the internal resistance under the combined draw,
+// and the motors get drive in proportion to the bus voltage. Below the controller's
+// voltage limits the motors are cut (main) or the controller browns out (logic).
+
+// Status bits as reported by Read All Status
+pub const ERR_LOGIC_HIGH: u32 = 0x0000_0010;
+pub const ERR_LOGIC_LOW: u32 = 0x0000_0020;
+pub const WARN_MAIN_HIGH: u32 = 0x0004_0000;
+pub const WARN_MAIN_LOW: u32 = 0x0008_0000;
+
+// The bus must recover this far above the main minimum before the motors are re-enabled
+const CUTOFF_HYSTERESIS_V: f32 = 0.5;
+// Drop between the main bus and the logic supply when logic runs from the main battery
+const LOGIC_DROP_V: f32 = 0.7;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatteryConfig {
+    pub enabled: bool,   // off: a stiff supply at `nominal_v` (the plant behaves as before)
+    pub nominal_v: f32,  // bus voltage the plant gain refers to (full drive at this voltage)
+    pub full_v: f32,     // open-circuit voltage when full and when empty
+    pub empty_v: f32,
+    pub capacity_ah: f32, // 0 = the charge never runs down
+    pub initial_soc: f32, // state of charge when the battery is set (0..1)
+    pub internal_resistance_ohm: f32,
+    pub idle_current_a: f32,  // drawn by the controller itself
+    pub logic_v: Option<f32>, // separate logic battery; logic runs from the main bus when omitted
+}
+
+impl Default for BatteryConfig {
+    // A 3S LiPo pack
+    fn default() -> Self {
+        BatteryConfig {
+            enabled: false,
+            nominal_v: 12.0,
+            full_v: 12.6,
+            empty_v: 10.5,
+            capacity_ah: 2.0,
+            initial_soc: 1.0,
+            internal_resistance_ohm: 0.1,
+            idle_current_a: 0.05,
+            logic_v: None,
+        }
+    }
+}
+
+impl BatteryConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, v) in [("nominal_v", self.nominal_v), ("full_v", self.full_v), ("empty_v", self.empty_v)] {
+            if !v.is_finite() || v <= 0.0 { return Err(format!("{} must be > 0 (got {})", name, v)); }
+        }
+        if self.empty_v > self.full_v {
+            return Err(format!("empty_v {} must not exceed full_v {}", self.empty_v, self.full_v));
+        }
+        for (name, v) in [("capacity_ah", self.capacity_ah), ("internal_resistance_ohm", self.internal_resistance_ohm), ("idle_current_a", self.idle_current_a)] {
+            if !v.is_finite() || v < 0.0 { return Err(format!("{} must be >= 0 (got {})", name, v)); }
+        }
+        if !self.initial_soc.is_finite() || !(0.0..=1.0).contains(&self.initial_soc) {
+            return Err(format!("initial_soc must be within 0..1 (got {})", self.initial_soc));
+        }
+        if let Some(v) = self.logic_v {
+            if !v.is_finite() || v < 0.0 { return Err(format!("logic_v must be >= 0 (got {})", v)); }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Battery {
+    pub config: BatteryConfig,
+    pub soc: f32,       // state of charge (0..1)
+    pub voltage: f32,   // main bus voltage
+    pub current_a: f32, // total draw
+    pub cutoff: bool,   // motors disabled by main under-voltage
+    pub brownout: bool, // logic supply below its minimum
+    pub brownouts: u32,
+}
+
+impl Default for Battery {
+    fn default() -> Self {
+        Battery::new(BatteryConfig::default())
+    }
+}
+
+fn volts(tenths: u16) -> f32 {
+    tenths as f32 / 10.0
+}
+
+impl Battery {
+    pub fn new(config: BatteryConfig) -> Battery {
+        Battery {
+            soc: config.initial_soc,
+            voltage: config.nominal_v,
+            current_a: 0.0,
+            cutoff: false,
+            brownout: false,
+            brownouts: 0,
+            config,
+        }
+    }
+
+    pub fn open_circuit_v(&self) -> f32 {
+        let c = &self.config;
+        if !c.enabled { return c.nominal_v; }
+        c.empty_v + (c.full_v - c.empty_v) * self.soc
+    }
+
+    pub fn logic_voltage(&self) -> f32 {
+        self.config.logic_v.unwrap_or((self.voltage - LOGIC_DROP_V).max(0.0))
+    }
+
+    // Share of full drive the motors get: bus over nominal voltage, none while cut off
+    pub fn drive_scale(&self) -> f32 {
+        if self.cutoff || self.brownout { 0.0 } else { self.voltage / self.config.nominal_v }
+    }
+
+    // One sim step drawing `load_a` for the motors. Returns true when the controller has
+    // just browned out (it then restarts, dropping its commands).
+    pub fn update(&mut self, load_a: f32, h: f64, main: &VoltageLimits, logic: &VoltageLimits) -> bool {
+        let c = &self.config;
+        self.current_a = load_a + c.idle_current_a;
+        if c.enabled {
+            if c.capacity_ah > 0.0 {
+                self.soc = (self.soc - (self.current_a as f64 * h / 3600.0) as f32 / c.capacity_ah).max(0.0);
+            }
+            self.voltage = (self.open_circuit_v() - self.current_a * c.internal_resistance_ohm).max(0.0);
+        } else {
+            self.voltage = c.nominal_v;
+        }
+        let cutoff = if self.cutoff { volts(main.min) + CUTOFF_HYSTERESIS_V } else { volts(main.min) };
+        if (self.voltage < cutoff) != self.cutoff {
+            self.cutoff = !self.cutoff;
+            println!("[SIM] main battery {:.2} V: motors {}", self.voltage, if self.cutoff { "cut off (under-voltage)" } else { "re-enabled" });
+        }
+        let was_brownout = self.brownout;
+        self.brownout = self.logic_voltage() < volts(logic.min);
+        if self.brownout && !was_brownout {
+            self.brownouts += 1;
+            eprintln!("[SIM] logic supply {:.2} V below {:.1} V: controller brownout", self.logic_voltage(), volts(logic.min));
+        }
+        self.brownout && !was_brownout
+    }
+
+    // Voltage warning and error bits for the limits in force
+    pub fn status_bits(&self, main: &VoltageLimits, logic: &VoltageLimits) -> u32 {
+        let mut bits = 0;
+        if self.cutoff { bits |= WARN_MAIN_LOW; }
+        if self.voltage > volts(main.max) { bits |= WARN_MAIN_HIGH; }
+        if self.brownout { bits |= ERR_LOGIC_LOW; }
+        if self.logic_voltage() > volts(logic.max) { bits |= ERR_LOGIC_HIGH; }
+        bits
+    }
+
+    // Read-back in 0.1 V units, as the controller reports it
+    pub fn main_tenths(&self) -> i16 {
+        (self.voltage * 10.0).round() as i16
+    }
+
+    pub fn logic_tenths(&self) -> i16 {
+        (self.logic_voltage() * 10.0).round() as i16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motor::Motor;
+    use crate::sim::{initial_sim_state, sim_advance};
+
+    #[test]
+    fn bus_sags_under_load_and_cuts_the_motors() {
+        let mut sim = initial_sim_state();
+        for m in Motor::ALL {
+            sim[m].mode_pwm = true;
+            sim[m].pwm = 32767;
+        }
+        // stiff supply: full speed, nominal voltage reported
+        sim_advance(&mut sim, 1.0);
+        assert!((sim[Motor::M1].vel - 100.0).abs() < 1.0);
+        assert_eq!(sim.battery.main_tenths(), 120);
+
+        // a weak pack: both motors together pull the bus down, and the speed with it
+        let config = BatteryConfig { enabled: true, internal_resistance_ohm: 0.05, ..Default::default() };
+        assert!(BatteryConfig { empty_v: 13.0, ..config.clone() }.validate().is_err());
+        sim.battery = Battery::new(config.clone());
+        sim_advance(&mut sim, 1.0);
+        let b = &sim.battery;
+        assert!(b.voltage < 12.6 && (b.voltage - (b.open_circuit_v() - b.current_a * 0.05)).abs() < 1e-3, "{:?}", b);
+        assert!((sim[Motor::M1].vel - 100.0 * b.voltage / 12.0).abs() < 1.0, "{}", sim[Motor::M1].vel);
+        assert!(b.soc < 1.0);
+        assert_eq!(b.status_bits(&sim.main_battery_limits, &sim.logic_battery_limits), 0);
+
+        // an almost flat pack sags below the main minimum: motors cut, commands kept
+        sim.main_battery_limits.min = 110;
+        sim.battery = Battery::new(BatteryConfig { initial_soc: 0.3, logic_v: Some(12.0), ..config });
+        for m in Motor::ALL { sim[m].vel = 0.0; }
+        sim_advance(&mut sim, 0.2);
+        assert!(sim.battery.cutoff && sim[Motor::M1].vel.abs() < 1.0);
+        assert_eq!(sim[Motor::M1].pwm, 32767);
+        assert_eq!(sim.battery.status_bits(&sim.main_battery_limits, &sim.logic_battery_limits), WARN_MAIN_LOW);
+
+        // with logic fed from the bus, the surge on re-enabling browns the controller out
+        sim.main_battery_limits.min = 60;
+        sim.logic_battery_limits.min = 100;
+        sim.battery.config.logic_v = None;
+        sim_advance(&mut sim, 0.01);
+        assert_eq!(sim.battery.brownouts, 1);
+        assert_eq!(sim[Motor::M1].pwm, 0);
+    }
+}
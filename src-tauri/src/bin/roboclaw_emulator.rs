@@ -0,0 +1,75 @@
+// Virtual RoboClaw on a pseudo-terminal.
+//
+//   cargo run --bin roboclaw_emulator -- [--addr 0x80] [--link /tmp/roboclaw] [--faults faults.json]
+//
+// Prints the /dev/pts/N path to connect to (or creates a symlink to it with --link),
+// then serves packet-serial requests until killed. --faults loads sensor noise and link
+// faults (dropped/corrupted replies, latency) to inject, in the `set_sim_fault_injection` format.
+
+#[cfg(unix)]
+fn main() {
+    let mut addr: u8 = 0x80;
+    let mut link: Option<String> = None;
+    let mut faults: Option<String> = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--addr" => {
+                let v = args.next().expect("--addr needs a value");
+                let parsed = match v.strip_prefix("0x") {
+                    Some(hex) => u8::from_str_radix(hex, 16),
+                    None => v.parse::<u8>(),
+                };
+                addr = parsed.unwrap_or_else(|_| panic!("Invalid address: {}", v));
+            }
+            "--link" => link = Some(args.next().expect("--link needs a path")),
+            "--faults" => faults = Some(args.next().expect("--faults needs a path")),
+            "-h" | "--help" => {
+                println!("Usage: roboclaw_emulator [--addr 0x80] [--link PATH] [--faults FILE]");
+                return;
+            }
+            other => {
+                eprintln!("Unknown argument: {}", other);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let mut emulator = motion_studio_lib::emulator::Emulator::new(addr);
+    if let Some(path) = &faults {
+        let injection = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path, e))
+            .and_then(|text| serde_json::from_str(&text).map_err(|e| format!("Invalid fault config {}: {}", path, e)))
+            .and_then(|injection| emulator.set_fault_injection(injection));
+        if let Err(e) = injection {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+
+    let emu = motion_studio_lib::emulator::PtyEmulator::spawn_emulator(emulator).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    println!("RoboClaw emulator (address 0x{:02X}) listening on {}", addr, emu.path);
+
+    if let Some(link) = &link {
+        let _ = std::fs::remove_file(link);
+        match std::os::unix::fs::symlink(&emu.path, link) {
+            Ok(()) => println!("Symlinked {} -> {}", link, emu.path),
+            Err(e) => eprintln!("Failed to create symlink {}: {}", link, e),
+        }
+    }
+
+    if let Err(e) = emu.join() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("roboclaw_emulator needs a pseudo-terminal and only runs on Unix");
+    std::process::exit(1);
+}
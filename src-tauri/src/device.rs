@@ -1,28 +1,50 @@
 use once_cell::sync::Lazy;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::sync::Mutex;
 use std::sync::atomic::Ordering;
-use serialport::SerialPort;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::io::{Read, Write};
+use serialport::{SerialPort, SerialPortType};
 use serde::{Serialize, Deserialize};
 
-use crate::sim::{is_simulation_enabled, sim_update, SIM_STATE, SIMULATION_ENABLED};
+use crate::traffic;
+use crate::estop;
+use crate::monitor;
+use crate::motor::Motor;
+use crate::position::{PositionCommand, PositionMove};
+use crate::safety;
+use crate::sim_bus;
+use crate::sim::{is_simulation_enabled, sim_link, sim_update, sim_wait_unlocked, SIM_STATE, SIMULATION_ENABLED};
+
+// Byte stream the device layer talks packet serial over.
+// A real serial port in the app; tests can substitute e.g. a replay transport.
+pub trait Transport: Read + Write + Send {}
+
+impl<T: Read + Write + Send + ?Sized> Transport for T {}
 
 // Struct holding RoboClaw settings
 pub struct Roboclaw {
     pub addr: u8,
     pub baud_rate: u32,
     pub port_name: String,
-    pub port: Option<Box<dyn SerialPort>>,
+    pub port: Option<Box<dyn Transport>>,
+    pub known_addrs: Vec<u8>, // every address a frame was sent to (used by emergency stop)
+}
+
+// Open a serial port as a transport with the timeout used throughout the device layer
+pub fn open_serial(port_name: &str, baud_rate: u32) -> Result<Box<dyn Transport>, serialport::Error> {
+    let port: Box<dyn SerialPort> = serialport::new(port_name, baud_rate)
+        .timeout(Duration::from_millis(100))
+        .open()?;
+    Ok(Box::new(port))
 }
 
 pub static ROBOCLAW: Lazy<Mutex<Option<Roboclaw>>> = Lazy::new(|| {
     let baud_rate = 115_200;
     let port_name = std::env::var("ROBOCLAW_PORT").unwrap_or_else(|_| String::from("/dev/ttyACM0"));
 
-    let port: Option<Box<dyn SerialPort>> = match serialport::new(&port_name, baud_rate)
-        .timeout(Duration::from_millis(100))
-        .open()
-    {
+    let port: Option<Box<dyn Transport>> = match open_serial(&port_name, baud_rate) {
         Ok(p) => {
             println!("Successfully opened port {}", port_name);
             Some(p)
@@ -39,6 +61,7 @@ pub static ROBOCLAW: Lazy<Mutex<Option<Roboclaw>>> = Lazy::new(|| {
         baud_rate,
         port_name,
         port,
+        known_addrs: Vec::new(),
     };
 
     Mutex::new(Some(roboclaw))
@@ -99,9 +122,37 @@ pub fn parse_response(resp: &[u8], addr: u8, cmd: u8) -> Result<&[u8], String> {
     Ok(data)
 }
 
+// True for frames that set a motor moving: non-zero duty or speed, or a position move
+fn moves_motor(data: &[u8]) -> bool {
+    let be16 = |i: usize| data.get(i..i + 2).map_or(0, |b| i16::from_be_bytes([b[0], b[1]]));
+    match data.get(1) {
+        Some(6 | 7) => data.get(2).is_some_and(|&speed| speed != 64),
+        Some(32 | 33) => be16(2) != 0,
+        Some(34) => be16(2) != 0 || be16(4) != 0,
+        Some(38 | 39) => be16(2) != 0,
+        Some(40) => be16(2) != 0 || be16(8) != 0,
+        Some(65 | 66) => true,
+        _ => false,
+    }
+}
+
 pub fn send_and_read(data: &[u8], roboclaw: &mut Roboclaw) -> Result<Vec<u8>, String> {
-    send_serial_locked(roboclaw, data)?;
-    read_serial_locked(roboclaw)
+    // the e-stop may have latched while this frame was waiting for the port lock
+    if moves_motor(data) { estop::ensure_not_latched()?; }
+    if let Some(&addr) = data.first() {
+        if !roboclaw.known_addrs.contains(&addr) { roboclaw.known_addrs.push(addr); }
+    }
+    traffic::record_tx(data);
+    let sent_at = Instant::now();
+    if let Err(e) = send_serial_locked(roboclaw, data) {
+        // log the failed write too, so the TX frame has its outcome
+        let err = Err(format!("Write failed: {}", e));
+        traffic::record_rx(data, &err, sent_at.elapsed());
+        return err;
+    }
+    let reply = read_serial_locked(roboclaw);
+    traffic::record_rx(data, &reply, sent_at.elapsed());
+    reply
 }
 
 // Configure baud_rate
@@ -114,9 +165,7 @@ pub fn configure_baud_sync(baud_rate: u32) -> Result<(), String> {
             return Ok(());
         }
         roboclaw.baud_rate = baud_rate;
-        roboclaw.port = serialport::new(&roboclaw.port_name, baud_rate)
-            .timeout(Duration::from_millis(100))
-            .open()
+        roboclaw.port = open_serial(&roboclaw.port_name, baud_rate)
             .map(Some)
             .map_err(|e| format!("Failed to reopen port: {}", e))?;
         println!("Baud rate set to {}", baud_rate);
@@ -136,14 +185,21 @@ pub fn configure_port_sync(port_name: String, baud_rate: Option<u32>) -> Result<
             roboclaw.port_name = port_name.clone();
             return Ok(());
         }
+        if port_name == crate::SIMULATED_BUS_PORT {
+            // emulated controllers over the serial path; one at the current address if none are set up
+            SIMULATION_ENABLED.store(false, Ordering::Relaxed);
+            sim_bus::ensure_controller(roboclaw.addr)?;
+            roboclaw.port = Some(Box::new(sim_bus::SimBusPort::default()));
+            roboclaw.port_name = port_name.clone();
+            roboclaw.baud_rate = baud_rate.unwrap_or(roboclaw.baud_rate);
+            return Ok(());
+        }
         SIMULATION_ENABLED.store(false, Ordering::Relaxed);
         let baud = baud_rate.unwrap_or(roboclaw.baud_rate);
         roboclaw.port = None;
         roboclaw.port_name = port_name.clone();
         roboclaw.baud_rate = baud;
-        roboclaw.port = serialport::new(&port_name, baud)
-            .timeout(Duration::from_millis(100))
-            .open()
+        roboclaw.port = open_serial(&port_name, baud)
             .map(Some)
             .map_err(|e| format!("Failed to open port {}: {}", port_name, e))?;
         println!("Successfully opened port {} at {} baud", port_name, baud);
@@ -153,41 +209,208 @@ pub fn configure_port_sync(port_name: String, baud_rate: Option<u32>) -> Result<
     }
 }
 
-// List available serial ports
-// Roboclaw devices are usually on /dev/ttyACM*
-pub fn list_serial_ports_sync() -> Result<Vec<String>, String> {
-    serialport::available_ports()
-        .map(|ports| {
-            let mut list: Vec<String> = ports.iter()
-                .filter(|p| p.port_name.contains("ACM"))
-                .map(|p| p.port_name.clone()).collect();
-            list.push(crate::SIMULATED_PORT.to_string());
-            list
+// Packet serial address of the controller (0x80..0x87, as set in its configuration)
+pub fn configure_address_sync(addr: u8) -> Result<(), String> {
+    if !(0x80..=0x87).contains(&addr) {
+        return Err(format!("Invalid address 0x{:02X} (expected 0x80..0x87)", addr));
+    }
+    let mut guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
+    let roboclaw = guard.as_mut().ok_or("RoboClaw not initialized")?;
+    roboclaw.addr = addr;
+    println!("Address set to 0x{:02X}", addr);
+    Ok(())
+}
+
+/// Find the controllers on the bus: every packet serial address (0x80..0x87) that answers
+/// Read Config (command 99) with a valid reply. Absent addresses cost one read timeout each
+/// and are not remembered for the emergency stop.
+pub fn scan_addresses_sync() -> Result<Vec<u8>, String> {
+    let mut guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
+    let roboclaw = guard.as_mut().ok_or("RoboClaw not initialized")?;
+    if is_simulation_enabled() { return Ok(vec![roboclaw.addr]); }
+    let known = roboclaw.known_addrs.clone();
+    let mut found = Vec::new();
+    for addr in 0x80..=0x87u8 {
+        let answered = send_and_read(&[addr, 99], roboclaw).and_then(|r| parse_response(&r, addr, 99).map(|d| d.len() == 2));
+        if answered == Ok(true) { found.push(addr); }
+    }
+    roboclaw.known_addrs.retain(|a| known.contains(a) || found.contains(a));
+    println!("[SCAN] controllers at {:02X?}", found);
+    Ok(found)
+}
+
+// Current port, baud and address, so the UI can pick up a connection made at startup
+pub fn get_connection_info_sync() -> Result<serde_json::Value, String> {
+    let guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
+    let roboclaw = guard.as_ref().ok_or("RoboClaw not initialized")?;
+    let simulation = is_simulation_enabled();
+    Ok(serde_json::json!({
+        "port_name": roboclaw.port_name,
+        "baud_rate": roboclaw.baud_rate,
+        "address": roboclaw.addr,
+        "connected": simulation || roboclaw.port.is_some(),
+        "simulation": simulation,
+    }))
+}
+
+// USB vendor/product ID reported by RoboClaw controllers with a native USB port
+pub const ROBOCLAW_USB_VID: u16 = 0x03EB;
+pub const ROBOCLAW_USB_PID: u16 = 0x2404;
+
+// Directory holding stable udev symlinks for serial devices (Linux only)
+const SERIAL_BY_ID_DIR: &str = "/dev/serial/by-id";
+
+// One entry returned by list_serial_ports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SerialPortEntry {
+    pub path: String,
+    pub by_id: Option<String>, // /dev/serial/by-id/... symlink pointing at `path`
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub likely_roboclaw: bool,
+}
+
+// Filter applied when enumerating ports.
+// Missing fields in the frontend payload fall back to the defaults below.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PortFilter {
+    pub name_patterns: Vec<String>, // keep ports whose path or by-id link contains any of these (empty = all)
+    pub usb_only: bool,             // drop ports without USB metadata
+    pub roboclaw_only: bool,        // keep only ports flagged likely_roboclaw
+    pub include_simulated: bool,    // append the SIMULATED and SIMULATED_BUS pseudo ports
+}
+
+impl Default for PortFilter {
+    fn default() -> Self {
+        PortFilter {
+            name_patterns: vec!["ACM".into(), "USB".into(), "by-id".into()],
+            usb_only: false,
+            roboclaw_only: false,
+            include_simulated: true,
+        }
+    }
+}
+
+impl PortFilter {
+    pub fn matches(&self, entry: &SerialPortEntry) -> bool {
+        if self.usb_only && entry.vid.is_none() { return false; }
+        if self.roboclaw_only && !entry.likely_roboclaw { return false; }
+        // RoboClaws are always kept, whatever their device name
+        if self.name_patterns.is_empty() || entry.likely_roboclaw { return true; }
+        self.name_patterns.iter().any(|pat| {
+            entry.path.contains(pat.as_str()) || entry.by_id.as_deref().is_some_and(|b| b.contains(pat.as_str()))
+        })
+    }
+}
+
+// Guess whether a USB device is a RoboClaw from its IDs and descriptor strings
+pub fn is_likely_roboclaw(vid: Option<u16>, pid: Option<u16>, manufacturer: Option<&str>, product: Option<&str>) -> bool {
+    if vid == Some(ROBOCLAW_USB_VID) && pid == Some(ROBOCLAW_USB_PID) { return true; }
+    [manufacturer, product].iter().flatten().any(|s| {
+        let s = s.to_ascii_lowercase();
+        s.contains("roboclaw") || s.contains("basicmicro") || s.contains("ion motion")
+    })
+}
+
+// Map canonical device paths (e.g. /dev/ttyACM0) to their by-id symlinks
+fn serial_by_id_links() -> HashMap<PathBuf, String> {
+    let mut links = HashMap::new();
+    if let Ok(entries) = std::fs::read_dir(SERIAL_BY_ID_DIR) {
+        for entry in entries.flatten() {
+            let link = entry.path();
+            if let Ok(target) = std::fs::canonicalize(&link) {
+                links.insert(target, link.to_string_lossy().into_owned());
+            }
+        }
+    }
+    links
+}
+
+// List available serial ports with USB metadata.
+// Roboclaw devices are usually on /dev/ttyACM*, or /dev/ttyUSB* behind a USB-UART adapter.
+pub fn list_serial_ports_sync(filter: Option<PortFilter>) -> Result<Vec<SerialPortEntry>, String> {
+    let filter = filter.unwrap_or_default();
+    let ports = serialport::available_ports().map_err(|e| format!("Failed to list ports: {}", e))?;
+    let by_id = serial_by_id_links();
+
+    let mut list: Vec<SerialPortEntry> = ports.into_iter()
+        .map(|p| {
+            let canonical = std::fs::canonicalize(&p.port_name).unwrap_or_else(|_| PathBuf::from(&p.port_name));
+            let mut entry = SerialPortEntry {
+                path: p.port_name.clone(),
+                by_id: by_id.get(&canonical).cloned(),
+                vid: None,
+                pid: None,
+                serial_number: None,
+                manufacturer: None,
+                product: None,
+                likely_roboclaw: false,
+            };
+            if let SerialPortType::UsbPort(usb) = p.port_type {
+                entry.vid = Some(usb.vid);
+                entry.pid = Some(usb.pid);
+                entry.likely_roboclaw = is_likely_roboclaw(entry.vid, entry.pid, usb.manufacturer.as_deref(), usb.product.as_deref());
+                entry.serial_number = usb.serial_number;
+                entry.manufacturer = usb.manufacturer;
+                entry.product = usb.product;
+            }
+            entry
         })
-        .map_err(|e| format!("Failed to list ports: {}", e))
+        .filter(|e| filter.matches(e))
+        .collect();
+
+    // Likely RoboClaws first, then by path for a stable order in the UI
+    list.sort_by(|a, b| b.likely_roboclaw.cmp(&a.likely_roboclaw).then_with(|| a.path.cmp(&b.path)));
+
+    if filter.include_simulated {
+        for path in [crate::SIMULATED_PORT, crate::SIMULATED_BUS_PORT] {
+            list.push(SerialPortEntry {
+                path: path.to_string(),
+                by_id: None,
+                vid: None,
+                pid: None,
+                serial_number: None,
+                manufacturer: None,
+                product: None,
+                likely_roboclaw: false,
+            });
+        }
+    }
+    Ok(list)
 }
 
 // Drive motor with a simple speed command (no encoder)
-// open loop
-pub fn drive_simply_sync(speed: u8, motor_index: u8) -> Result<(), String> {
+// open loop. With a slew limit the command goes out as its duty equivalent with
+// acceleration (the 7-bit commands have none).
+pub fn drive_simply_sync(speed: u8, motor: Motor) -> Result<(), String> {
+    if speed != 64 { monitor::ensure_motor_enabled(motor)?; }
+    let speed = safety::filter_speed(motor, speed)?;
+    let accel = if speed != 64 { safety::duty_accel(motor)? } else { None };
     if is_simulation_enabled() {
         let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to acquire sim lock: {}", e))?;
         sim_update(&mut sim);
-        if motor_index == 1 {
-            sim.m1_speed = speed;
-            sim.m1_mode_pwm = false;
-        } else if motor_index == 2 {
-            sim.m2_speed = speed;
-            sim.m2_mode_pwm = false;
-        }
-        return Ok(());
+        if speed != 64 { estop::ensure_not_latched()?; }
+        sim[motor].speed = speed;
+        sim[motor].mode_pwm = false;
+        sim[motor].duty_accel = accel;
+        sim[motor].position_move = None;
+        return sim_link(&mut sim);
     }
     let mut guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
     let mut roboclaw = guard.as_mut().ok_or("Roboclaw not initialized")?;
+    if accel.is_some() {
+        let duty = ((speed.min(127) as f32 - 64.0) / 63.0 * 32767.0).round() as i16;
+        let addr = roboclaw.addr;
+        return drive_pwm_locked(roboclaw, addr, duty, motor, accel);
+    }
     let speed = speed.min(127);
     let mut data: Vec<u8> = Vec::new();
     data.push(roboclaw.addr);
-    if motor_index == 1 { data.push(0x06); } else { data.push(0x07); }
+    data.push(motor.pick(0x06, 0x07));
     data.push(speed);
     let crc = calc_crc(&data);
     data.push((crc >> 8) as u8);
@@ -197,46 +420,99 @@ pub fn drive_simply_sync(speed: u8, motor_index: u8) -> Result<(), String> {
 }
 
 // Drive motor with a raw PWM duty command (signed 16-bit)
-pub fn drive_pwm_sync(pwm: i16, motor_index: u8) -> Result<(), String> {
+pub fn drive_pwm_sync(pwm: i16, motor: Motor) -> Result<(), String> {
+    if pwm != 0 { monitor::ensure_motor_enabled(motor)?; }
+    let pwm = safety::filter_duty(motor, pwm)?;
+    let accel = if pwm != 0 { safety::duty_accel(motor)? } else { None };
     if is_simulation_enabled() {
         let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to acquire sim lock: {}", e))?;
         sim_update(&mut sim);
-        if motor_index == 1 { sim.m1_pwm = pwm; sim.m1_mode_pwm = true; } else { sim.m2_pwm = pwm; sim.m2_mode_pwm = true; }
-        return Ok(());
+        if pwm != 0 { estop::ensure_not_latched()?; }
+        sim[motor].pwm = pwm;
+        sim[motor].mode_pwm = true;
+        sim[motor].duty_accel = accel;
+        sim[motor].position_move = None;
+        return sim_link(&mut sim);
     }
     let mut guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
-    let mut roboclaw = guard.as_mut().ok_or("Roboclaw not initialized")?;
+    let roboclaw = guard.as_mut().ok_or("Roboclaw not initialized")?;
+    let addr = roboclaw.addr;
+    drive_pwm_locked(roboclaw, addr, pwm, motor, accel)
+}
+
+// Drive both motors with one frame (command 34, or 40 with a slew limit), each duty checked
+// against its motor's limits
+pub fn drive_pwm_both_sync(m1_pwm: i16, m2_pwm: i16) -> Result<(), String> {
+    let mut duties = [m1_pwm, m2_pwm];
+    let mut accels = [None, None];
+    for m in Motor::ALL {
+        if duties[m.index()] != 0 { monitor::ensure_motor_enabled(m)?; }
+        duties[m.index()] = safety::filter_duty(m, duties[m.index()])?;
+        if duties[m.index()] != 0 { accels[m.index()] = safety::duty_accel(m)?; }
+    }
+    if is_simulation_enabled() {
+        let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to acquire sim lock: {}", e))?;
+        sim_update(&mut sim);
+        if duties != [0, 0] { estop::ensure_not_latched()?; }
+        for m in Motor::ALL {
+            sim[m].pwm = duties[m.index()];
+            sim[m].mode_pwm = true;
+            sim[m].duty_accel = accels[m.index()];
+            sim[m].position_move = None;
+        }
+        return sim_link(&mut sim);
+    }
+    let mut guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
+    let roboclaw = guard.as_mut().ok_or("Roboclaw not initialized")?;
+    let ramped = accels != [None, None];
+    let mut data: Vec<u8> = vec![roboclaw.addr, if ramped { 40 } else { 34 }];
+    for m in Motor::ALL {
+        data.extend_from_slice(&duties[m.index()].to_be_bytes());
+        // a motor without a ramp (or stopping) in a ramped frame takes the fastest one
+        if ramped { data.extend_from_slice(&accels[m.index()].unwrap_or(safety::MAX_DUTY_ACCEL).to_be_bytes()); }
+    }
+    let crc = calc_crc(&data);
+    data.push((crc >> 8) as u8);
+    data.push((crc & 0xFF) as u8);
+    let response = send_and_read(&data, roboclaw)?;
+    if response.first() == Some(&0xFF) { Ok(()) } else { Err("Failed to drive motors PWM".to_string()) }
+}
+
+// Send a duty command to `addr` on an already locked port (commands 32/33, or 38/39 with
+// a duty acceleration; stops always go out without one)
+pub fn drive_pwm_locked(roboclaw: &mut Roboclaw, addr: u8, pwm: i16, motor: Motor, accel: Option<u32>) -> Result<(), String> {
     let pwm = pwm.clamp(-32767, 32767);
-    let cmd = if motor_index == 1 { 32 } else { 33 };
+    let accel = accel.filter(|_| pwm != 0);
+    let cmd = if accel.is_some() { motor.pick(38, 39) } else { motor.pick(32, 33) };
     let mut data: Vec<u8> = Vec::new();
-    data.push(roboclaw.addr);
+    data.push(addr);
     data.push(cmd);
     data.push(((pwm >> 8) & 0xFF) as u8);
     data.push((pwm & 0xFF) as u8);
+    if let Some(accel) = accel { data.extend_from_slice(&accel.to_be_bytes()); }
     let crc = calc_crc(&data);
     data.push((crc >> 8) as u8);
     data.push((crc & 0xFF) as u8);
-    let response = send_and_read(&data, &mut roboclaw)?;
+    let response = send_and_read(&data, roboclaw)?;
     if response.get(0) == Some(&0xFF) { Ok(()) } else { Err("Failed to drive motor PWM".to_string()) }
 }
 
-
 // Read encoder value in pulses per second
-pub fn read_speed_sync(motor_index: u8) -> Result<i32, String> {
+pub fn read_speed_sync(motor: Motor) -> Result<i32, String> {
     if is_simulation_enabled() {
         let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to acquire sim lock: {}", e))?;
         sim_update(&mut sim);
-        let vel = if motor_index == 1 { sim.m1_vel } else { sim.m2_vel };
-        return Ok(vel.round() as i32);
+        sim_link(&mut sim)?;
+        return Ok(sim.measured_speed(motor));
     }
     let mut guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
     let mut roboclaw = guard.as_mut().ok_or("Roboclaw is not initialized")?;
     let mut data: Vec<u8> = Vec::new();
     data.push(roboclaw.addr);
-    if motor_index == 1 { data.push(18); } else { data.push(19); }
+    let cmd = motor.pick(18, 19);
+    data.push(cmd);
     let response = send_and_read(&data, &mut roboclaw)?;
     if response.is_empty() { return Err("The response is empty".to_string()); }
-    let cmd = if motor_index == 1 { 18 } else { 19 };
     match parse_response(&response, roboclaw.addr, cmd) {
         Ok(data) => {
             let speed = ((data[0] as u32) << 24) | ((data[1] as u32) << 16) | ((data[2] as u32) << 8) | (data[3] as u32);
@@ -245,7 +521,8 @@ pub fn read_speed_sync(motor_index: u8) -> Result<i32, String> {
             else if status == 1 { Ok(-(speed as i32)) }
             else { Err("Invalid value".to_string()) }
         }
-        Err(e) => { eprintln!("[DEBUG] Failed to parse! {:?}", e); Err("Invalid response".to_string()) }
+        // the raw reply is already in the traffic log
+        Err(e) => Err(format!("Invalid response: {:?}", e)),
     }
 }
 
@@ -253,27 +530,28 @@ pub fn read_all_status_sync() -> Result<serde_json::Value, String> {
     if is_simulation_enabled() {
         let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to acquire sim lock: {}", e))?;
         sim_update(&mut sim);
+        sim_link(&mut sim)?;
         let v = serde_json::json!({
             "timertick": 0u32,
-            "errors": 0u32,
-            "temp1": 0i16,
-            "temp2": 0i16,
-            "main_batt": 0i16,
-            "logic_batt": 0i16,
-            "m1_pwm": sim.m1_pwm,
-            "m2_pwm": sim.m2_pwm,
-            "m1_current": 0i16,
-            "m2_current": 0i16,
-            "m1_encoder": sim.m1_encoder,
-            "m2_encoder": sim.m2_encoder,
-            "m1_speed": sim.m1_vel.round() as i32,
-            "m2_speed": sim.m2_vel.round() as i32,
+            "errors": sim.status_bits(),
+            "temp1": sim.thermal.driver_tenths(Motor::M1),
+            "temp2": sim.thermal.driver_tenths(Motor::M2),
+            "main_batt": sim.battery.main_tenths(),
+            "logic_batt": sim.battery.logic_tenths(),
+            "m1_pwm": sim[Motor::M1].applied_duty(),
+            "m2_pwm": sim[Motor::M2].applied_duty(),
+            "m1_current": sim.measured_current(Motor::M1) as i16,
+            "m2_current": sim.measured_current(Motor::M2) as i16,
+            "m1_encoder": sim[Motor::M1].encoder,
+            "m2_encoder": sim[Motor::M2].encoder,
+            "m1_speed": sim.measured_speed(Motor::M1),
+            "m2_speed": sim.measured_speed(Motor::M2),
             "m1_ispeed": 0i32,
             "m2_ispeed": 0i32,
             "m1_speed_err": 0i16,
             "m2_speed_err": 0i16,
-            "m1_pos_err": 0i16,
-            "m2_pos_err": 0i16,
+            "m1_pos_err": sim[Motor::M1].position_error(),
+            "m2_pos_err": sim[Motor::M2].position_error(),
         });
         return Ok(v);
     }
@@ -336,9 +614,8 @@ pub fn read_motor_currents_sync() -> Result<(u32, u32), String> {
     if is_simulation_enabled() {
         let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to acquire sim lock: {}", e))?;
         sim_update(&mut sim);
-        let m1_current = (sim.m1_vel.abs() * 15.0) as u32;
-        let m2_current = (sim.m2_vel.abs() * 15.0) as u32;
-        return Ok((m1_current, m2_current));
+        sim_link(&mut sim)?;
+        return Ok((sim.measured_current(Motor::M1), sim.measured_current(Motor::M2)));
     }
     let mut guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
     let mut roboclaw = guard.as_mut().ok_or("Failed to open port")?;
@@ -363,9 +640,8 @@ pub fn read_pwm_values_sync() -> Result<(i32, i32), String> {
     if is_simulation_enabled() {
         let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to acquire sim lock: {}", e))?;
         sim_update(&mut sim);
-        let m1_pwm = if sim.m1_mode_pwm { sim.m1_pwm as i32 } else { (sim.m1_vel / 120.0 * 32767.0).clamp(-32767.0, 32767.0) as i32 };
-        let m2_pwm = if sim.m2_mode_pwm { sim.m2_pwm as i32 } else { (sim.m2_vel / 120.0 * 32767.0).clamp(-32767.0, 32767.0) as i32 };
-        return Ok((m1_pwm, m2_pwm));
+        sim_link(&mut sim)?;
+        return Ok((sim[Motor::M1].applied_duty() as i32, sim[Motor::M2].applied_duty() as i32));
     }
     let mut guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
     let mut roboclaw = guard.as_mut().ok_or("Failed to open port")?;
@@ -381,9 +657,6 @@ pub fn read_pwm_values_sync() -> Result<(i32, i32), String> {
             let m2_pwm_raw = ((data[2] as u16) << 8) | (data[3] as u16);
             let m1_pwm_signed = m1_pwm_raw as i16;
             let m2_pwm_signed = m2_pwm_raw as i16;
-            let m1_duty_cycle = (m1_pwm_signed as f64) / 327.67;
-            let m2_duty_cycle = (m2_pwm_signed as f64) / 327.67;
-            println!("[DEBUG] M1 duty cycle: {:.2}%, M2 duty cycle: {:.2}%", m1_duty_cycle, m2_duty_cycle);
             (m1_pwm_signed as i32, m2_pwm_signed as i32)
         }
         Err(e) => return Err(format!("Failed to parse: {:?}", e)),
@@ -394,10 +667,9 @@ pub fn read_pwm_values_sync() -> Result<(i32, i32), String> {
 pub fn reset_encoder_sync() -> Result<(), String> {
     if is_simulation_enabled() {
         let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to acquire sim lock: {}", e))?;
-        sim.m1_speed = 64; sim.m2_speed = 64; sim.m1_pwm = 0; sim.m2_pwm = 0; sim.m1_mode_pwm = false; sim.m2_mode_pwm = false; sim.m1_vel = 0.0; sim.m2_vel = 0.0;
-        // Also clear encoder counts in simulation
-        sim.m1_encoder = 0;
-        sim.m2_encoder = 0;
+        // count up to now first, so the motion so far is not credited after the reset
+        sim_update(&mut sim);
+        for m in sim.motors.iter_mut() { m.reset_encoder(); }
         return Ok(());
     }
     let mut guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
@@ -412,12 +684,12 @@ pub fn reset_encoder_sync() -> Result<(), String> {
     data.push(msb);
     data.push(lsb);
     let response = send_and_read(&data, &mut roboclaw)?;
-    let result = parse_response(&response, roboclaw.addr, cmd)?;
-    if result.get(0) == Some(&0xFF) { Ok(()) } else { Err("Failed to reset encoder".into()) }
+    // Write commands are acknowledged with a single 0xFF byte (no CRC)
+    if response.get(0) == Some(&0xFF) { Ok(()) } else { Err("Failed to reset encoder".into()) }
 }
 
 // Struct for position PID parameters
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PositionPidParams {
     pub p: i32,
     pub i: i32,
@@ -443,7 +715,7 @@ impl Default for PositionPidParams {
 }
 
 // Struct for velocity PID parameters
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VelocityPidParams {
     pub p: i32,
     pub i: i32,
@@ -457,26 +729,65 @@ impl Default for VelocityPidParams {
     }
 }
 
+// Motor current limits in 10 mA units (commands 133-136)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CurrentLimits {
+    pub max: u32,
+    pub min: u32,
+}
+
+impl Default for CurrentLimits {
+    fn default() -> Self {
+        CurrentLimits { max: 1500, min: 0 }
+    }
+}
+
+// Battery cutoff voltages in 0.1 V units (commands 57-60)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoltageLimits {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl Default for VoltageLimits {
+    fn default() -> Self {
+        VoltageLimits { min: 60, max: 340 }
+    }
+}
+
+// Main (motor) or logic supply, for the battery voltage limit commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Supply {
+    Main,
+    Logic,
+}
+
+impl Supply {
+    pub fn pick<T>(self, main: T, logic: T) -> T {
+        match self {
+            Supply::Main => main,
+            Supply::Logic => logic,
+        }
+    }
+}
+
 /// Read RoboClaw position PID constants for the specified motor.
 /// Uses command 63 for M1 or 64 for M2.
 /// Returns: P, I, D, MaxI, Deadzone, MinPos, MaxPos (all 32-bit signed integers).
 /// Used for position control commands or when encoders are enabled in RC/Analog modes.
-pub fn read_position_pid_sync(motor_index: u8) -> Result<PositionPidParams, String> {
+pub fn read_position_pid_sync(motor: Motor) -> Result<PositionPidParams, String> {
 
     if is_simulation_enabled() {
         // Simulation: return stored position PID from sim state
         let sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
-        if motor_index == 1 { return Ok(sim.m1_position_pid.clone()); } else { return Ok(sim.m2_position_pid.clone()); }
+        return Ok(sim[motor].position_pid.clone());
     }
 
     let mut guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
     let mut roboclaw = guard.as_mut().ok_or("Failed to open port")?;
     
-    let cmd = if motor_index == 1 {
-        63
-    } else {
-        64
-    }; // 63 for M1, 64 for M2
+    let cmd = motor.pick(63, 64);
     
     // data buffer
     let mut data: Vec<u8> = Vec::new();
@@ -518,23 +829,19 @@ pub fn read_position_pid_sync(motor_index: u8) -> Result<PositionPidParams, Stri
 /// Uses command 61 for M1 or 62 for M2.
 /// Parameters: D, P, I, MaxI, Deadzone, MinPos, MaxPos (all 32-bit signed integers).
 /// Used for position control commands or when encoders are enabled in RC/Analog modes.
-pub fn set_position_pid_sync(motor_index: u8, params: PositionPidParams) -> Result<(), String> {
+pub fn set_position_pid_sync(motor: Motor, params: PositionPidParams) -> Result<(), String> {
 
     if is_simulation_enabled() {
         // Update sim stored params
         let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
-        if motor_index == 1 { sim.m1_position_pid = params; } else { sim.m2_position_pid = params; }
+        sim[motor].position_pid = params;
         return Ok(());
     }
 
     let mut guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
     let mut roboclaw = guard.as_mut().ok_or("Failed to open port")?;
 
-    let cmd = if motor_index == 1 {
-        61 
-    } else {
-        62 
-    }; // 61 for M1, 62 for M2
+    let cmd = motor.pick(61, 62);
     
     // data buffer
     let mut data: Vec<u8> = Vec::new();
@@ -564,10 +871,9 @@ pub fn set_position_pid_sync(motor_index: u8, params: PositionPidParams) -> Resu
 
     // Send command and read response
     let response = send_and_read(&data, &mut roboclaw)?;
-    let result = parse_response(&response, roboclaw.addr, cmd)?;
     
-    // Check for success
-    if result.get(0) == Some(&0xFF) { 
+    // Check for success (single 0xFF acknowledge byte, no CRC)
+    if response.get(0) == Some(&0xFF) { 
         Ok(()) 
     } else {
         Err("Failed to set PID".into()) 
@@ -578,22 +884,18 @@ pub fn set_position_pid_sync(motor_index: u8, params: PositionPidParams) -> Resu
 /// Uses command 55 for M1 or 56 for M2.
 /// Returns: P, I, D, QPPS (all 32-bit signed integers).
 /// Used for velocity control commands.
-pub fn read_velocity_pid_sync(motor_index: u8) -> Result<VelocityPidParams, String> {
+pub fn read_velocity_pid_sync(motor: Motor) -> Result<VelocityPidParams, String> {
 
     if is_simulation_enabled() {
         // Simulation: return stored PID values from sim state
         let sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
-        if motor_index == 1 { return Ok(sim.m1_velocity_pid.clone()); } else { return Ok(sim.m2_velocity_pid.clone()); }
+        return Ok(sim[motor].velocity_pid.clone());
     }
     
     let mut guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
     let mut roboclaw = guard.as_mut().ok_or("Failed to open port")?;
     
-    let cmd = if motor_index == 1 {
-        55
-    } else {
-        56
-    }; // 55 for M1, 56 for M2
+    let cmd = motor.pick(55, 56);
 
     // data buffer
     let mut data: Vec<u8> = Vec::new();
@@ -628,23 +930,19 @@ pub fn read_velocity_pid_sync(motor_index: u8) -> Result<VelocityPidParams, Stri
 /// QPPS is the speed of the encoder when the motor is at 100% power.
 /// Default values: QPPS = 44000, P = 0x00010000, I = 0x00008000, D = 0x00004000.
 /// Used for velocity control commands.
-pub fn set_velocity_pid_sync(motor_index: u8, params: VelocityPidParams) -> Result<(), String> {
+pub fn set_velocity_pid_sync(motor: Motor, params: VelocityPidParams) -> Result<(), String> {
 
     if is_simulation_enabled() {
         // Update sim stored params
         let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
-        if motor_index == 1 { sim.m1_velocity_pid = params; } else { sim.m2_velocity_pid = params; }
+        sim[motor].velocity_pid = params;
         return Ok(());
     }
 
     let mut guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
     let mut roboclaw = guard.as_mut().ok_or("Failed to open port")?;
     
-    let cmd = if motor_index == 1 {
-        28
-    } else {
-        29
-    }; // 28 for M1, 29 for M2
+    let cmd = motor.pick(28, 29);
     
     // data buffer
     let mut data: Vec<u8> = Vec::new();
@@ -668,10 +966,9 @@ pub fn set_velocity_pid_sync(motor_index: u8, params: VelocityPidParams) -> Resu
     data.push(lsb);
 
     let response = send_and_read(&data, &mut roboclaw)?;
-    let result = parse_response(&response, roboclaw.addr, cmd)?;
     
-    // Check for success
-    if result.get(0) == Some(&0xFF) { 
+    // Check for success (single 0xFF acknowledge byte, no CRC)
+    if response.get(0) == Some(&0xFF) { 
         Ok(()) 
     } else {
         Err("Failed to set velocity PID".into()) 
@@ -681,7 +978,7 @@ pub fn set_velocity_pid_sync(motor_index: u8, params: VelocityPidParams) -> Resu
 /// Measure QPPS (Quadrature Pulses Per Second) by running the motor at full forward (speed=127)
 /// for the specified duration and sampling the encoder-reported speed.
 /// Returns the measured QPPS (integer) or an error.
-pub fn measure_qpps_sync(motor_index: u8, duration_ms: u32) -> Result<serde_json::Value, String> {
+pub fn measure_qpps_sync(motor: Motor, duration_ms: u32) -> Result<serde_json::Value, String> {
     if duration_ms < 200 { return Err("duration_ms must be >= 200".into()); }
 
     let sample_interval = 100u32; // ms
@@ -691,39 +988,62 @@ pub fn measure_qpps_sync(motor_index: u8, duration_ms: u32) -> Result<serde_json
     reset_encoder_sync()?;
     if is_simulation_enabled() {
         // Use sim encoder counters: set full PWM for the duration
+        let full = safety::filter_duty(motor, 32767)?;
+        let accel = safety::duty_accel(motor)?;
         let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
-        let prev_pwm = if motor_index == 1 { sim.m1_pwm } else { sim.m2_pwm };
-        let prev_mode = if motor_index == 1 { sim.m1_mode_pwm } else { sim.m2_mode_pwm };
-        if motor_index == 1 { sim.m1_pwm = 32767; sim.m1_mode_pwm = true; } else { sim.m2_pwm = 32767; sim.m2_mode_pwm = true; }
+        sim_update(&mut sim);
+        // nothing is driven while the e-stop or a fault trip holds the motor
+        monitor::ensure_motor_enabled(motor)?;
+        let prev = (sim[motor].pwm, sim[motor].mode_pwm, sim[motor].duty_accel);
+        sim[motor].pwm = full;
+        sim[motor].mode_pwm = true;
+        sim[motor].duty_accel = accel;
+        sim[motor].position_move = None;
         // wait a bit to settle and sample counts
         let mut total = 0u32;
-        while total < duration_ms {
+        let sampled = loop {
+            if total >= duration_ms { break Ok(()); }
+            if let Err(e) = estop::ensure_not_latched() { break Err(e); }
             sim_update(&mut sim);
-            encoder_samples.push(if motor_index == 1 { sim.m1_encoder } else { sim.m2_encoder });
-            std::thread::sleep(std::time::Duration::from_millis(sample_interval as u64));
+            if let Err(e) = monitor::check_sim(&mut sim, motor) { break Err(e); }
+            encoder_samples.push(sim[motor].encoder);
+            sim = sim_wait_unlocked(sim, std::time::Duration::from_millis(sample_interval as u64));
             total += sample_interval;
+        };
+        match sampled {
+            // restore previous pwm and mode
+            Ok(()) => (sim[motor].pwm, sim[motor].mode_pwm, sim[motor].duty_accel) = prev,
+            // cut short by the e-stop or a fault trip: leave the motor stopped
+            Err(e) => {
+                sim[motor].stop();
+                return Err(e);
+            }
         }
-        // restore previous pwm and mode
-        if motor_index == 1 { sim.m1_pwm = prev_pwm; sim.m1_mode_pwm = prev_mode; } else { sim.m2_pwm = prev_pwm; sim.m2_mode_pwm = prev_mode; }
     } else {
         // Real device: set PWM to full (signed 16-bit max) and sample encoder counts via Read All Status
-        drive_pwm_sync(32767, motor_index)?;
-        std::thread::sleep(std::time::Duration::from_millis(500));
-        let mut elapsed = 0u32;
-        while elapsed < duration_ms {
-            match read_all_status_sync() {
-                Ok(v) => {
-                    // v is serde_json with m1_encoder/m2_encoder
-                    if motor_index == 1 { encoder_samples.push(v.get("m1_encoder").and_then(|x| x.as_i64()).unwrap_or(0)); }
-                    else { encoder_samples.push(v.get("m2_encoder").and_then(|x| x.as_i64()).unwrap_or(0)); }
+        drive_pwm_sync(32767, motor)?;
+        let sampled = (|| -> Result<(), String> {
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            let mut elapsed = 0u32;
+            while elapsed < duration_ms {
+                estop::ensure_not_latched()?;
+                match read_all_status_sync() {
+                    Ok(v) => {
+                        monitor::check_status(motor, &v)?;
+                        // v is serde_json with m1_encoder/m2_encoder
+                        encoder_samples.push(v.get(motor.pick("m1_encoder", "m2_encoder")).and_then(|x| x.as_i64()).unwrap_or(0));
+                    }
+                    Err(e) => eprintln!("measure_qpps: read_all_status failed: {}", e),
                 }
-                Err(e) => eprintln!("measure_qpps: read_all_status failed: {}", e),
+                std::thread::sleep(std::time::Duration::from_millis(sample_interval as u64));
+                elapsed += sample_interval;
             }
-            std::thread::sleep(std::time::Duration::from_millis(sample_interval as u64));
-            elapsed += sample_interval;
-        }
-        // stop PWM (0)
-        drive_pwm_sync(0, motor_index)?;
+            Ok(())
+        })();
+        // stop PWM (0), also when the run was cut short
+        let stopped = drive_pwm_sync(0, motor);
+        sampled?;
+        stopped?;
     }
 
     if encoder_samples.len() < 2 { return Err("Not enough encoder samples".into()); }
@@ -746,8 +1066,229 @@ pub fn measure_qpps_sync(motor_index: u8, duration_ms: u32) -> Result<serde_json
 
 // Async wrappers moved to crate root (`lib.rs`) as tauri command handlers.
 
-// Stub: write current velocity PID to EEPROM (not implemented on device yet)
-pub fn write_velocity_pid_eeprom_sync(_motor_index: u8) -> Result<(), String> {
-    // RoboClaw EEPROM write command is not implemented here; return explicit error for now
-    Err("EEPROM write not implemented".into())
+// Read command: bare addr + cmd, reply is payload + CRC. Returns the checked payload.
+fn read_command(cmd: u8, len: usize) -> Result<Vec<u8>, String> {
+    let mut guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
+    let roboclaw = guard.as_mut().ok_or("Failed to open port")?;
+    let data = vec![roboclaw.addr, cmd];
+    let response = send_and_read(&data, roboclaw)?;
+    let result = parse_response(&response, roboclaw.addr, cmd)?;
+    if result.len() < len {
+        return Err(format!("Invalid response length for command {}: {} bytes, expected {}", cmd, result.len(), len));
+    }
+    Ok(result[..len].to_vec())
+}
+
+// Write command: addr + cmd + payload + CRC, acknowledged with a single 0xFF
+fn write_command(cmd: u8, payload: &[u8]) -> Result<(), String> {
+    let mut guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
+    let roboclaw = guard.as_mut().ok_or("Failed to open port")?;
+    let mut data = vec![roboclaw.addr, cmd];
+    data.extend_from_slice(payload);
+    let crc = calc_crc(&data);
+    data.push((crc >> 8) as u8);
+    data.push((crc & 0xFF) as u8);
+    let response = send_and_read(&data, roboclaw)?;
+    if response.first() == Some(&0xFF) { Ok(()) } else { Err(format!("Command {} was not acknowledged", cmd)) }
+}
+
+/// Drive to an encoder position with a speed/accel/decel profile (command 65 for M1, 66 for M2).
+/// The command replaces any buffered ones and runs the position PID until the next drive command.
+/// The move is checked against the motor's safety limits from the current encoder count.
+pub fn drive_position_sync(motor: Motor, command: PositionCommand) -> Result<(), String> {
+    monitor::ensure_motor_enabled(motor)?;
+    if is_simulation_enabled() {
+        let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+        sim_update(&mut sim);
+        estop::ensure_not_latched()?;
+        let m = &mut sim[motor];
+        let command = safety::filter_position(motor, command, m.encoder, m.velocity_pid.qpps)?;
+        m.position_move = Some(PositionMove::new(command, m.encoder, &m.position_pid));
+        return sim_link(&mut sim);
+    }
+    let r = read_command(motor.pick(16, 17), 4)?;
+    let position = i32::from_be_bytes([r[0], r[1], r[2], r[3]]) as i64;
+    let qpps = read_velocity_pid_sync(motor)?.qpps;
+    let command = safety::filter_position(motor, command, position, qpps)?;
+    let mut payload = Vec::with_capacity(17);
+    for x in [command.accel, command.speed, command.decel] { payload.extend_from_slice(&x.to_be_bytes()); }
+    payload.extend_from_slice(&command.position.to_be_bytes());
+    payload.push(1); // execute immediately
+    write_command(motor.pick(65, 66), &payload)
+}
+
+/// Read the standard config word (command 99): control mode, baud rate, packet address, etc.
+pub fn read_config_sync() -> Result<u16, String> {
+    if is_simulation_enabled() {
+        let sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+        return Ok(sim.config_word);
+    }
+    let r = read_command(99, 2)?;
+    Ok(u16::from_be_bytes([r[0], r[1]]))
+}
+
+/// Set the standard config word (command 98).
+/// Changing the mode, baud rate or address bits takes effect immediately and can drop the link.
+pub fn set_config_sync(config: u16) -> Result<(), String> {
+    if is_simulation_enabled() {
+        let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+        sim.config_word = config;
+        return Ok(());
+    }
+    write_command(98, &config.to_be_bytes())
+}
+
+/// Read the encoder modes of both channels (command 91).
+/// Bit 7 selects absolute (analog) instead of quadrature, bits 5/6 reverse the encoder/motor.
+pub fn read_encoder_modes_sync() -> Result<(u8, u8), String> {
+    if is_simulation_enabled() {
+        let sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+        return Ok((sim[Motor::M1].encoder_mode, sim[Motor::M2].encoder_mode));
+    }
+    let r = read_command(91, 2)?;
+    Ok((r[0], r[1]))
+}
+
+/// Set the encoder mode of one channel (command 92 for M1, 93 for M2).
+pub fn set_encoder_mode_sync(motor: Motor, mode: u8) -> Result<(), String> {
+    if is_simulation_enabled() {
+        let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+        sim[motor].encoder_mode = mode;
+        return Ok(());
+    }
+    write_command(motor.pick(92, 93), &[mode])
+}
+
+/// Read the motor current limits (command 135 for M1, 136 for M2), in 10 mA units.
+pub fn read_current_limits_sync(motor: Motor) -> Result<CurrentLimits, String> {
+    if is_simulation_enabled() {
+        let sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+        return Ok(sim[motor].current_limits);
+    }
+    let r = read_command(motor.pick(135, 136), 8)?;
+    Ok(CurrentLimits {
+        max: u32::from_be_bytes([r[0], r[1], r[2], r[3]]),
+        min: u32::from_be_bytes([r[4], r[5], r[6], r[7]]),
+    })
+}
+
+/// Set the motor current limits (command 133 for M1, 134 for M2).
+pub fn set_current_limits_sync(motor: Motor, limits: CurrentLimits) -> Result<(), String> {
+    if is_simulation_enabled() {
+        let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+        sim[motor].current_limits = limits;
+        return Ok(());
+    }
+    let mut payload = limits.max.to_be_bytes().to_vec();
+    payload.extend_from_slice(&limits.min.to_be_bytes());
+    write_command(motor.pick(133, 134), &payload)
+}
+
+/// Read the battery cutoff voltages (command 59 for main, 60 for logic), in 0.1 V units.
+pub fn read_voltage_limits_sync(supply: Supply) -> Result<VoltageLimits, String> {
+    if is_simulation_enabled() {
+        let sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+        return Ok(supply.pick(sim.main_battery_limits, sim.logic_battery_limits));
+    }
+    let r = read_command(supply.pick(59, 60), 4)?;
+    Ok(VoltageLimits { min: u16::from_be_bytes([r[0], r[1]]), max: u16::from_be_bytes([r[2], r[3]]) })
+}
+
+/// Set the battery cutoff voltages (command 57 for main, 58 for logic).
+pub fn set_voltage_limits_sync(supply: Supply, limits: VoltageLimits) -> Result<(), String> {
+    if limits.min > limits.max {
+        return Err(format!("Minimum voltage {} exceeds maximum {}", limits.min, limits.max));
+    }
+    if is_simulation_enabled() {
+        let mut guard = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+        let sim = &mut *guard;
+        *supply.pick(&mut sim.main_battery_limits, &mut sim.logic_battery_limits) = limits;
+        return Ok(());
+    }
+    let mut payload = limits.min.to_be_bytes().to_vec();
+    payload.extend_from_slice(&limits.max.to_be_bytes());
+    write_command(supply.pick(57, 58), &payload)
+}
+
+/// Write all current settings (PIDs, limits, config, encoder modes) to EEPROM (command 94),
+/// so they survive a power cycle.
+pub fn write_settings_eeprom_sync() -> Result<(), String> {
+    if is_simulation_enabled() {
+        let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+        sim.eeprom_writes += 1;
+        return Ok(());
+    }
+    write_command(94, &[])
+}
+
+// The controller only saves all settings at once, so this persists the velocity PID
+// of both channels along with everything else
+pub fn write_velocity_pid_eeprom_sync(_motor: Motor) -> Result<(), String> {
+    write_settings_eeprom_sync()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roboclaw_detection_and_port_filter() {
+        let (vid, pid) = (Some(ROBOCLAW_USB_VID), Some(ROBOCLAW_USB_PID));
+        let cases = [
+            (vid, pid, None, None, true),
+            (vid, Some(0x2405), None, None, false), // VID alone is not enough (other Atmel devices)
+            (Some(0x0403), pid, None, None, false),
+            (Some(0x0403), Some(0x6001), Some("FTDI"), Some("FT232R USB UART"), false),
+            (Some(0x0403), Some(0x6001), None, Some("USB RoboClaw 2x7A"), true),
+            (None, None, Some("BASICMICRO"), None, true),
+            (None, None, Some("Ion Motion Control"), None, true),
+            (None, None, None, None, false), // not a USB port
+        ];
+        for (vid, pid, manufacturer, product, expected) in cases {
+            assert_eq!(is_likely_roboclaw(vid, pid, manufacturer, product), expected, "{:?} {:?} {:?} {:?}", vid, pid, manufacturer, product);
+        }
+
+        let acm = SerialPortEntry {
+            path: "/dev/ttyACM0".into(),
+            by_id: Some("/dev/serial/by-id/usb-foo-if00".into()),
+            vid: Some(0x0403),
+            pid: Some(0x6001),
+            serial_number: None,
+            manufacturer: None,
+            product: None,
+            likely_roboclaw: false,
+        };
+        let uart = SerialPortEntry {
+            path: "/dev/ttyS0".into(),
+            by_id: None,
+            vid: None,
+            pid: None,
+            serial_number: None,
+            manufacturer: None,
+            product: None,
+            likely_roboclaw: false,
+        };
+        let claw = SerialPortEntry {
+            path: "/dev/ttyS1".into(),
+            by_id: None,
+            vid: Some(ROBOCLAW_USB_VID),
+            pid: Some(ROBOCLAW_USB_PID),
+            serial_number: None,
+            manufacturer: Some("BASICMICRO".into()),
+            product: None,
+            likely_roboclaw: true,
+        };
+        let default = PortFilter::default();
+        assert!(default.matches(&acm));
+        assert!(!default.matches(&uart));
+        assert!(default.matches(&claw)); // kept whatever its name
+        let by_id = PortFilter { name_patterns: vec!["usb-foo".into()], ..Default::default() };
+        assert!(by_id.matches(&acm));
+        let any_name = PortFilter { name_patterns: Vec::new(), ..Default::default() };
+        assert!(any_name.matches(&uart));
+        let usb_only = PortFilter { name_patterns: Vec::new(), usb_only: true, ..Default::default() };
+        assert!(usb_only.matches(&acm) && !usb_only.matches(&uart));
+        let roboclaw_only = PortFilter { roboclaw_only: true, ..Default::default() };
+        assert!(!roboclaw_only.matches(&acm) && roboclaw_only.matches(&claw));
+    }
 }
@@ -0,0 +1,123 @@
+use serde::{Serialize, Deserialize};
+
+// Scheduled load disturbances for a simulated motor. Torques are given as a fraction of
+// the motor's stall torque (the duty it takes to hold the load); positive torque opposes
+// forward rotation.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DisturbanceKind {
+    TorqueStep { torque: f32 },
+    // rises linearly from zero to `torque` over `ramp_s`, then holds
+    TorqueRamp { torque: f32, ramp_s: f64 },
+    TorqueSine {
+        amplitude: f32,
+        freq_hz: f64,
+        #[serde(default)]
+        offset: f32,
+    },
+    // multiplies the load inertia (mechanical time constant) by `factor`
+    InertiaChange { factor: f32 },
+}
+
+// One disturbance, active from `at_s` (until `until_s` if given), in seconds from the
+// start of the schedule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Disturbance {
+    pub at_s: f64,
+    #[serde(default)]
+    pub until_s: Option<f64>,
+    #[serde(flatten)]
+    pub kind: DisturbanceKind,
+}
+
+// Combined effect of the active disturbances
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Load {
+    pub torque: f32,
+    pub inertia_scale: f32,
+}
+
+impl Default for Load {
+    fn default() -> Self {
+        Load { torque: 0.0, inertia_scale: 1.0 }
+    }
+}
+
+// Load acting on the motor during an experiment sample
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DisturbanceSample {
+    pub t_ms: i64,
+    pub torque: f32,
+    pub inertia_scale: f32,
+}
+
+impl Disturbance {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.at_s.is_finite() || self.at_s < 0.0 {
+            return Err(format!("Disturbance start must be >= 0 s (got {})", self.at_s));
+        }
+        if let Some(until) = self.until_s {
+            if !until.is_finite() || until <= self.at_s {
+                return Err(format!("Disturbance end {} s must come after its start {} s", until, self.at_s));
+            }
+        }
+        match self.kind {
+            DisturbanceKind::TorqueStep { torque } if torque.is_finite() => Ok(()),
+            DisturbanceKind::TorqueRamp { torque, ramp_s } if torque.is_finite() && ramp_s.is_finite() && ramp_s >= 0.0 => Ok(()),
+            DisturbanceKind::TorqueSine { amplitude, freq_hz, offset } if amplitude.is_finite() && offset.is_finite() && freq_hz.is_finite() && freq_hz > 0.0 => Ok(()),
+            DisturbanceKind::InertiaChange { factor } if factor.is_finite() && factor > 0.0 => Ok(()),
+            _ => Err(format!("Invalid disturbance parameters: {:?}", self.kind)),
+        }
+    }
+
+    // Edges get a little slack so whole clock steps that sum to `at_s` start on time
+    fn active(&self, t_s: f64) -> bool {
+        let t_s = t_s + 1e-9;
+        t_s >= self.at_s && self.until_s.is_none_or(|until| t_s < until)
+    }
+}
+
+// Sum of the torques and product of the inertia changes active at `t_s`
+pub fn load_at(schedule: &[Disturbance], t_s: f64) -> Load {
+    let mut load = Load::default();
+    for d in schedule.iter().filter(|d| d.active(t_s)) {
+        let elapsed = t_s - d.at_s;
+        match d.kind {
+            DisturbanceKind::TorqueStep { torque } => load.torque += torque,
+            DisturbanceKind::TorqueRamp { torque, ramp_s } => {
+                let frac = if ramp_s > 0.0 { (elapsed / ramp_s).min(1.0) } else { 1.0 };
+                load.torque += torque * frac as f32;
+            }
+            DisturbanceKind::TorqueSine { amplitude, freq_hz, offset } => {
+                load.torque += offset + amplitude * (std::f64::consts::TAU * freq_hz * elapsed).sin() as f32;
+            }
+            DisturbanceKind::InertiaChange { factor } => load.inertia_scale *= factor,
+        }
+    }
+    load
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedule_combines_active_disturbances() {
+        let schedule: Vec<Disturbance> = serde_json::from_value(serde_json::json!([
+            { "kind": "torque_step", "at_s": 1.0, "until_s": 2.0, "torque": 0.2 },
+            { "kind": "torque_ramp", "at_s": 1.5, "torque": 0.4, "ramp_s": 1.0 },
+            { "kind": "torque_sine", "at_s": 3.0, "amplitude": 0.1, "freq_hz": 1.0 },
+            { "kind": "inertia_change", "at_s": 0.5, "factor": 2.0 },
+        ]))
+        .unwrap();
+        assert!(schedule.iter().all(|d| d.validate().is_ok()));
+        assert_eq!(load_at(&schedule, 0.0), Load::default());
+        assert_eq!(load_at(&schedule, 1.0), Load { torque: 0.2, inertia_scale: 2.0 });
+        assert!((load_at(&schedule, 2.0).torque - 0.2).abs() < 1e-6); // step over, ramp half way
+        assert!((load_at(&schedule, 3.25).torque - 0.5).abs() < 1e-6); // ramp held + sine peak
+        let bad = Disturbance { at_s: 1.0, until_s: Some(0.5), kind: DisturbanceKind::TorqueStep { torque: 0.1 } };
+        assert!(bad.validate().is_err());
+        assert!(Disturbance { at_s: 0.0, until_s: None, kind: DisturbanceKind::InertiaChange { factor: 0.0 } }.validate().is_err());
+    }
+}
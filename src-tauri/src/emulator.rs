@@ -0,0 +1,434 @@
+use std::io::{ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::device::{calc_crc, CurrentLimits, PositionPidParams, VelocityPidParams, VoltageLimits};
+use crate::motor::Motor;
+use crate::position::{PositionCommand, PositionMove};
+use crate::injection::FaultInjection;
+use crate::sim::{initial_sim_state, sim_update, SimState};
+
+// Virtual RoboClaw: speaks the packet-serial protocol over a byte stream and drives
+// a `SimState` plant, so the real serial code path can run without hardware.
+
+// Payload length (excluding addr/cmd/CRC) of host -> controller frames, and whether
+// the host appends a CRC. Read commands are sent as bare addr + cmd.
+fn request_layout(cmd: u8) -> Option<(usize, bool)> {
+    match cmd {
+        6 | 7 => Some((1, true)),
+        32 | 33 => Some((2, true)),
+        34 => Some((4, true)),
+        38 | 39 => Some((6, true)),
+        40 => Some((12, true)),
+        65 | 66 => Some((17, true)),
+        20 => Some((0, true)),
+        28 | 29 => Some((16, true)),
+        61 | 62 => Some((28, true)),
+        57 | 58 => Some((4, true)),
+        92 | 93 => Some((1, true)),
+        94 => Some((0, true)),
+        98 => Some((2, true)),
+        133 | 134 => Some((8, true)),
+        16 | 17 | 18 | 19 | 48 | 49 | 55 | 56 | 59 | 60 | 63 | 64 | 73 | 91 | 99 | 135 | 136 => Some((0, false)),
+        _ => None,
+    }
+}
+
+pub struct Emulator {
+    pub addr: u8,
+    sim: SimState,
+    rx: Vec<u8>,
+}
+
+impl Emulator {
+    pub fn new(addr: u8) -> Self {
+        Self::with_state(addr, initial_sim_state())
+    }
+
+    // Emulate a controller starting from `sim` (e.g. a restored snapshot)
+    pub fn with_state(addr: u8, sim: SimState) -> Self {
+        Emulator { addr, sim, rx: Vec::new() }
+    }
+
+    pub fn state(&self) -> &SimState {
+        &self.sim
+    }
+
+    pub fn state_mut(&mut self) -> &mut SimState {
+        &mut self.sim
+    }
+
+    // Inject sensor and link faults into this emulator's replies
+    pub fn set_fault_injection(&mut self, injection: FaultInjection) -> Result<(), String> {
+        injection.validate()?;
+        self.sim.set_fault_injection(injection);
+        Ok(())
+    }
+
+    // Delay before each reply goes out
+    pub fn latency(&self) -> Duration {
+        Duration::from_secs_f64(self.sim.injection.latency_ms / 1000.0)
+    }
+
+    // Feed received bytes; returns the bytes to send back for every complete frame.
+    // Frames with a bad CRC or unknown command are dropped without reply, like the controller.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<u8> {
+        self.rx.extend_from_slice(bytes);
+        let mut out = Vec::new();
+        while self.rx.len() >= 2 {
+            if self.rx[0] != self.addr {
+                // not for us (or out of sync): resynchronize on the next byte
+                self.rx.remove(0);
+                continue;
+            }
+            let cmd = self.rx[1];
+            let (payload_len, has_crc) = match request_layout(cmd) {
+                Some(layout) => layout,
+                None => {
+                    eprintln!("[EMU] unsupported command {}; flushing input", cmd);
+                    self.rx.clear();
+                    break;
+                }
+            };
+            let frame_len = 2 + payload_len + if has_crc { 2 } else { 0 };
+            if self.rx.len() < frame_len { break; }
+            let frame: Vec<u8> = self.rx.drain(..frame_len).collect();
+            if has_crc {
+                let n = frame_len - 2;
+                let crc = ((frame[n] as u16) << 8) | (frame[n + 1] as u16);
+                if calc_crc(&frame[..n]) != crc {
+                    eprintln!("[EMU] CRC mismatch on command {}; ignoring frame", cmd);
+                    continue;
+                }
+            }
+            if let Some(mut reply) = self.execute(cmd, &frame[2..2 + payload_len]) {
+                let inj = self.sim.injection;
+                if self.sim.rng.chance(inj.drop_rate) { continue; }
+                if self.sim.rng.chance(inj.crc_error_rate) {
+                    // flip a bit of the CRC (or of the ack byte)
+                    if let Some(last) = reply.last_mut() { *last ^= 0x01; }
+                }
+                out.extend_from_slice(&reply);
+            }
+        }
+        out
+    }
+
+    // Append the CRC of addr + cmd + payload to a read reply
+    fn with_crc(&self, cmd: u8, mut payload: Vec<u8>) -> Vec<u8> {
+        let mut full = vec![self.addr, cmd];
+        full.extend_from_slice(&payload);
+        let crc = calc_crc(&full);
+        payload.push((crc >> 8) as u8);
+        payload.push((crc & 0xFF) as u8);
+        payload
+    }
+
+    fn execute(&mut self, cmd: u8, p: &[u8]) -> Option<Vec<u8>> {
+        let be32 = |i: usize| i32::from_be_bytes([p[i], p[i + 1], p[i + 2], p[i + 3]]);
+        sim_update(&mut self.sim);
+        let sim = &mut self.sim;
+        let ack = Some(vec![0xFF]);
+        // Motor addressed by a command pair such as 55/56
+        let motor = |m1_cmd: u8| if cmd == m1_cmd { Motor::M1 } else { Motor::M2 };
+        match cmd {
+            6 | 7 => { let m = &mut sim[motor(6)]; m.speed = p[0].min(127); m.mode_pwm = false; m.duty_accel = None; m.position_move = None; ack }
+            32 | 33 => { let m = &mut sim[motor(32)]; m.pwm = i16::from_be_bytes([p[0], p[1]]); m.mode_pwm = true; m.duty_accel = None; m.position_move = None; ack }
+            34 => {
+                for (m, duty) in sim.motors.iter_mut().zip([i16::from_be_bytes([p[0], p[1]]), i16::from_be_bytes([p[2], p[3]])]) {
+                    m.pwm = duty;
+                    m.mode_pwm = true;
+                    m.duty_accel = None;
+                    m.position_move = None;
+                }
+                ack
+            }
+            // duty with acceleration: duty, accel (and again for M2 with 40)
+            38..=40 => {
+                let targets = match cmd { 38 => vec![(Motor::M1, 0)], 39 => vec![(Motor::M2, 0)], _ => vec![(Motor::M1, 0), (Motor::M2, 6)] };
+                for (m, at) in targets {
+                    let m = &mut sim[m];
+                    m.pwm = i16::from_be_bytes([p[at], p[at + 1]]);
+                    m.duty_accel = Some(be32(at + 2) as u32);
+                    m.mode_pwm = true;
+                    m.position_move = None;
+                }
+                ack
+            }
+            65 | 66 => {
+                // accel, speed, decel, position, buffer flag (commands always run immediately here)
+                let command = PositionCommand { accel: be32(0) as u32, speed: be32(4) as u32, decel: be32(8) as u32, position: be32(12) };
+                let m = &mut sim[motor(65)];
+                m.position_move = Some(PositionMove::new(command, m.encoder, &m.position_pid));
+                ack
+            }
+            20 => { for m in sim.motors.iter_mut() { m.reset_encoder(); } ack }
+            28 | 29 => {
+                // D, P, I, QPPS on the wire
+                sim[motor(28)].velocity_pid = VelocityPidParams { d: be32(0), p: be32(4), i: be32(8), qpps: be32(12) };
+                ack
+            }
+            61 | 62 => {
+                // D, P, I, MaxI, Deadzone, MinPos, MaxPos on the wire
+                sim[motor(61)].position_pid = PositionPidParams { d: be32(0), p: be32(4), i: be32(8), max_i: be32(12), deadzone: be32(16), min: be32(20), max: be32(24) };
+                ack
+            }
+            57 | 58 => {
+                let limits = VoltageLimits { min: u16::from_be_bytes([p[0], p[1]]), max: u16::from_be_bytes([p[2], p[3]]) };
+                if cmd == 57 { sim.main_battery_limits = limits } else { sim.logic_battery_limits = limits }
+                ack
+            }
+            92 | 93 => { sim[motor(92)].encoder_mode = p[0]; ack }
+            94 => { sim.eeprom_writes += 1; ack }
+            98 => { sim.config_word = u16::from_be_bytes([p[0], p[1]]); ack }
+            133 | 134 => {
+                sim[motor(133)].current_limits = CurrentLimits { max: be32(0) as u32, min: be32(4) as u32 };
+                ack
+            }
+            59 | 60 => {
+                let v = if cmd == 59 { sim.main_battery_limits } else { sim.logic_battery_limits };
+                let mut payload = v.min.to_be_bytes().to_vec();
+                payload.extend_from_slice(&v.max.to_be_bytes());
+                Some(self.with_crc(cmd, payload))
+            }
+            91 => { let payload = vec![sim[Motor::M1].encoder_mode, sim[Motor::M2].encoder_mode]; Some(self.with_crc(cmd, payload)) }
+            99 => { let payload = sim.config_word.to_be_bytes().to_vec(); Some(self.with_crc(cmd, payload)) }
+            135 | 136 => {
+                let v = sim[motor(135)].current_limits;
+                let mut payload = v.max.to_be_bytes().to_vec();
+                payload.extend_from_slice(&v.min.to_be_bytes());
+                Some(self.with_crc(cmd, payload))
+            }
+            16 | 17 => {
+                let m = motor(16);
+                // count followed by status byte (bit 1 = counting backwards)
+                let mut payload = (sim[m].encoder as i32).to_be_bytes().to_vec();
+                payload.push(if sim.measured_speed(m) < 0 { 0x02 } else { 0 });
+                Some(self.with_crc(cmd, payload))
+            }
+            18 | 19 => {
+                let vel = sim.measured_speed(motor(18));
+                // magnitude followed by direction byte (1 = reverse)
+                let mut payload = vel.unsigned_abs().to_be_bytes().to_vec();
+                payload.push(if vel < 0 { 1 } else { 0 });
+                Some(self.with_crc(cmd, payload))
+            }
+            48 => {
+                let mut payload = Vec::new();
+                for m in &sim.motors { payload.extend_from_slice(&m.applied_duty().to_be_bytes()); }
+                Some(self.with_crc(cmd, payload))
+            }
+            49 => {
+                let mut payload = Vec::new();
+                for m in Motor::ALL { payload.extend_from_slice(&sim_current(sim, m).to_be_bytes()); }
+                Some(self.with_crc(cmd, payload))
+            }
+            55 | 56 => {
+                let v = &sim[motor(55)].velocity_pid;
+                let mut payload = Vec::new();
+                for x in [v.p, v.i, v.d, v.qpps] { payload.extend_from_slice(&x.to_be_bytes()); }
+                Some(self.with_crc(cmd, payload))
+            }
+            63 | 64 => {
+                let v = &sim[motor(63)].position_pid;
+                let mut payload = Vec::new();
+                for x in [v.p, v.i, v.d, v.max_i, v.deadzone, v.min, v.max] { payload.extend_from_slice(&x.to_be_bytes()); }
+                Some(self.with_crc(cmd, payload))
+            }
+            73 => {
+                let mut payload = Vec::with_capacity(56);
+                payload.extend_from_slice(&0u32.to_be_bytes()); // timertick
+                payload.extend_from_slice(&sim.status_bits().to_be_bytes());
+                let temps = [sim.thermal.driver_tenths(Motor::M1), sim.thermal.driver_tenths(Motor::M2)];
+                for x in temps.into_iter().chain([sim.battery.main_tenths(), sim.battery.logic_tenths()]) { payload.extend_from_slice(&x.to_be_bytes()); }
+                for m in &sim.motors { payload.extend_from_slice(&m.applied_duty().to_be_bytes()); }
+                for m in Motor::ALL { payload.extend_from_slice(&(sim_current(sim, m) as i16).to_be_bytes()); }
+                for m in &sim.motors { payload.extend_from_slice(&(m.encoder as i32).to_be_bytes()); }
+                for m in Motor::ALL { payload.extend_from_slice(&sim.measured_speed(m).to_be_bytes()); }
+                for x in [0i32, 0] { payload.extend_from_slice(&x.to_be_bytes()); } // ispeed
+                for x in [0i16, 0] { payload.extend_from_slice(&x.to_be_bytes()); } // speed errors
+                for m in &sim.motors { payload.extend_from_slice(&m.position_error().to_be_bytes()); }
+                Some(self.with_crc(cmd, payload))
+            }
+            _ => None,
+        }
+    }
+}
+
+// Same current approximation as the in-app simulator (10 mA units)
+fn sim_current(sim: &mut SimState, m: Motor) -> u16 {
+    sim.measured_current(m).min(u16::MAX as u32) as u16
+}
+
+// Serve the emulator on `port` until `stop` is set
+pub fn serve<P: Read + Write>(emu: &mut Emulator, port: &mut P, stop: &AtomicBool) -> Result<(), String> {
+    let mut buf = [0u8; 256];
+    while !stop.load(Ordering::Relaxed) {
+        match port.read(&mut buf) {
+            Ok(0) => continue,
+            Ok(n) => {
+                let reply = emu.feed(&buf[..n]);
+                if !reply.is_empty() {
+                    std::thread::sleep(emu.latency());
+                    port.write_all(&reply).map_err(|e| format!("Emulator write failed: {}", e))?;
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(format!("Emulator read failed: {}", e)),
+        }
+    }
+    Ok(())
+}
+
+// A running emulator on a pseudo-terminal. Connect to `path` like a real device.
+#[cfg(unix)]
+pub struct PtyEmulator {
+    pub path: String,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<(), String>>>,
+}
+
+#[cfg(unix)]
+impl PtyEmulator {
+    // Open a pty pair and serve a RoboClaw at `addr` on the master side in a background thread
+    pub fn spawn(addr: u8) -> Result<Self, String> {
+        Self::spawn_emulator(Emulator::new(addr))
+    }
+
+    // Same, serving an already configured emulator
+    pub fn spawn_emulator(mut emu: Emulator) -> Result<Self, String> {
+        let (mut master, slave) = serialport::TTYPort::pair().map_err(|e| format!("Failed to open pty: {}", e))?;
+        let path = serialport::SerialPort::name(&slave).ok_or("pty has no slave name")?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_thread = stop.clone();
+        let thread = std::thread::spawn(move || {
+            // keep the slave open so the pty survives the app closing and reopening it
+            let _slave = slave;
+            serve(&mut emu, &mut master, &stop_thread)
+        });
+        Ok(PtyEmulator { path, stop, thread: Some(thread) })
+    }
+
+    // Block until the emulator thread exits (it only does so on error or stop)
+    pub fn join(mut self) -> Result<(), String> {
+        match self.thread.take() {
+            Some(t) => t.join().map_err(|_| "Emulator thread panicked".to_string())?,
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for PtyEmulator {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{self, ROBOCLAW};
+    use crate::sim::tests::TEST_MUTEX;
+
+    fn crc_frame(mut data: Vec<u8>) -> Vec<u8> {
+        let crc = calc_crc(&data);
+        data.push((crc >> 8) as u8);
+        data.push((crc & 0xFF) as u8);
+        data
+    }
+
+    #[test]
+    fn emulator_frames_and_crc() {
+        let mut emu = Emulator::new(0x80);
+        // split write frame across two feeds
+        let frame = crc_frame(vec![0x80, 32, 0x40, 0x00]);
+        assert!(emu.feed(&frame[..3]).is_empty());
+        assert_eq!(emu.feed(&frame[3..]), vec![0xFF]);
+        // corrupted CRC gets no reply
+        let mut bad = crc_frame(vec![0x80, 33, 0x10, 0x00]);
+        bad[4] ^= 0xFF;
+        assert!(emu.feed(&bad).is_empty());
+        // other address is ignored
+        assert!(emu.feed(&[0x81, 73]).is_empty());
+        // read reply carries a valid CRC
+        let reply = emu.feed(&[0x80, 55]);
+        assert_eq!(reply.len(), 18);
+        assert!(device::parse_response(&reply, 0x80, 55).is_ok());
+        // in speed mode the duty read back is the controller output, as the app reports it
+        emu.sim.clock.pacing = crate::sim::SimPacing::FastAsPossible;
+        let m1 = &mut emu.sim[Motor::M1];
+        m1.mode_pwm = false;
+        m1.u = 0.25;
+        m1.vel = 90.0;
+        let reply = emu.feed(&[0x80, 48]);
+        assert_eq!(i16::from_be_bytes([reply[0], reply[1]]), 8192);
+    }
+
+    #[test]
+    fn emulator_injects_link_faults() {
+        let mut emu = Emulator::new(0x80);
+        emu.set_fault_injection(FaultInjection { drop_rate: 1.0, ..Default::default() }).unwrap();
+        assert!(emu.feed(&[0x80, 55]).is_empty());
+        emu.set_fault_injection(FaultInjection { crc_error_rate: 1.0, ..Default::default() }).unwrap();
+        let reply = emu.feed(&[0x80, 55]);
+        assert_eq!(reply.len(), 18);
+        assert_eq!(device::parse_response(&reply, 0x80, 55), Err("CRC mismatch".to_string()));
+        assert_eq!(emu.feed(&crc_frame(vec![0x80, 32, 0x40, 0x00])), vec![0xFE]);
+        assert!(emu.set_fault_injection(FaultInjection { drop_rate: 1.5, ..Default::default() }).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn device_layer_over_pty() {
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        let emu = PtyEmulator::spawn(0x80).expect("spawn emulator");
+        {
+            let mut rc = ROBOCLAW.lock().unwrap_or_else(|e| e.into_inner());
+            rc.as_mut().unwrap().addr = 0x80;
+        }
+        device::configure_port_sync(emu.path.clone(), Some(115_200)).expect("open pty");
+
+        let params = VelocityPidParams { p: 0x00020000, i: 0x00001000, d: 0, qpps: 50000 };
+        device::set_velocity_pid_sync(Motor::M2, params).expect("set velocity pid");
+        let read = device::read_velocity_pid_sync(Motor::M2).expect("read velocity pid");
+        assert_eq!((read.p, read.i, read.d, read.qpps), (0x00020000, 0x00001000, 0, 50000));
+
+        device::drive_pwm_sync(16384, Motor::M1).expect("drive pwm");
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        let speed = device::read_speed_sync(Motor::M1).expect("read speed");
+        assert!(speed > 0, "speed {}", speed);
+        let status = device::read_all_status_sync().expect("read all status");
+        assert_eq!(status["m1_pwm"], 16384);
+        assert!(status["m1_speed"].as_i64().unwrap() > 0);
+
+        device::drive_pwm_both_sync(-8000, 8000).expect("drive both");
+        assert_eq!(device::read_pwm_values_sync().expect("read pwm"), (-8000, 8000));
+
+        let limits = device::CurrentLimits { max: 2200, min: 100 };
+        device::set_current_limits_sync(Motor::M2, limits).expect("set current limits");
+        assert_eq!(device::read_current_limits_sync(Motor::M2).expect("read current limits"), limits);
+        device::set_encoder_mode_sync(Motor::M1, 0x20).expect("set encoder mode");
+        assert_eq!(device::read_encoder_modes_sync().expect("read encoder modes"), (0x20, 0));
+        assert_eq!(device::read_config_sync().expect("read config"), crate::sim::SIM_CONFIG_WORD);
+        device::write_settings_eeprom_sync().expect("write eeprom");
+
+        let target = PositionCommand { accel: 1000, speed: 500, decel: 1000, position: 300 };
+        device::drive_position_sync(Motor::M2, target).expect("drive position");
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        let status = device::read_all_status_sync().expect("read all status");
+        assert!(status["m2_pos_err"].as_i64().unwrap() > 0);
+
+        device::drive_pwm_sync(0, Motor::M1).expect("stop");
+        device::drive_pwm_sync(0, Motor::M2).expect("stop");
+        device::reset_encoder_sync().expect("reset encoders");
+
+        let mut rc = ROBOCLAW.lock().unwrap_or_else(|e| e.into_inner());
+        rc.as_mut().unwrap().port = None;
+    }
+}
@@ -3,7 +3,7 @@ use serde::{Serialize, Deserialize};
 use serde_json::json;
 use num_complex::Complex64;
 
-#[derive(Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FrfPoint {
     pub freq_hz: f64,
     pub gain: f64,
@@ -0,0 +1,147 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::device::{drive_pwm_locked, ROBOCLAW};
+use crate::motor::Motor;
+use crate::sim::{is_simulation_enabled, sim_update, SIM_STATE};
+
+// Software emergency stop.
+//
+// The latch is an atomic so it takes effect immediately, without waiting for the
+// ROBOCLAW / SIM_STATE locks. Experiment loops check it every sample and bail out, and
+// release the sim lock while they wait between samples. The locks are not fair, so a
+// drive command already waiting on one may get it before the zero-duty frames do: drive
+// commands therefore check the latch again once they hold the lock (send_and_read for
+// the port, the drive functions for the simulator), and a late non-zero command is rejected.
+
+pub static ESTOP_LATCHED: AtomicBool = AtomicBool::new(false);
+
+pub fn is_estop_latched() -> bool {
+    ESTOP_LATCHED.load(Ordering::SeqCst)
+}
+
+// Returns an error while the e-stop is latched; call before any non-zero drive command
+pub fn ensure_not_latched() -> Result<(), String> {
+    if is_estop_latched() {
+        Err("Emergency stop is active; call clear_estop before driving".into())
+    } else {
+        Ok(())
+    }
+}
+
+// Zero duty on both motors of the simulator and of every known address on the port.
+// Best effort: every address is tried even if one fails; returns the addresses that were stopped.
+pub fn stop_all_motors() -> Result<Vec<u8>, String> {
+    let mut errors: Vec<String> = Vec::new();
+    let mut stopped: Vec<u8> = Vec::new();
+
+    let simulated = is_simulation_enabled();
+    if simulated {
+        // lock even if poisoned: stopping matters more than the panic that poisoned it
+        let mut sim = SIM_STATE.lock().unwrap_or_else(|e| e.into_inner());
+        sim_update(&mut sim);
+        for m in sim.motors.iter_mut() { m.stop(); }
+    }
+
+    // a real port can still be open while simulating: stop that controller too
+    let mut guard = ROBOCLAW.lock().unwrap_or_else(|e| e.into_inner());
+    let roboclaw = match guard.as_mut() {
+        Some(r) => r,
+        None if simulated => return Ok(stopped),
+        None => return Err("Roboclaw not initialized".into()),
+    };
+    if roboclaw.port.is_none() {
+        return Ok(stopped);
+    }
+    let mut addrs = roboclaw.known_addrs.clone();
+    if !addrs.contains(&roboclaw.addr) { addrs.insert(0, roboclaw.addr); }
+    for addr in addrs {
+        let mut ok = true;
+        for motor in Motor::ALL {
+            if let Err(e) = drive_pwm_locked(roboclaw, addr, 0, motor, None) {
+                errors.push(format!("addr 0x{:02X} {}: {}", addr, motor, e));
+                ok = false;
+            }
+        }
+        if ok { stopped.push(addr); }
+    }
+    if errors.is_empty() { Ok(stopped) } else { Err(format!("Emergency stop incomplete: {}", errors.join("; "))) }
+}
+
+// Latch the e-stop, then stop every motor. The latch stays set even if sending fails.
+pub fn emergency_stop_sync() -> Result<serde_json::Value, String> {
+    ESTOP_LATCHED.store(true, Ordering::SeqCst);
+    eprintln!("[ESTOP] Emergency stop latched");
+    let stopped = stop_all_motors()?;
+    Ok(serde_json::json!({ "latched": true, "stopped_addresses": stopped }))
+}
+
+pub fn clear_estop_sync() -> Result<(), String> {
+    ESTOP_LATCHED.store(false, Ordering::SeqCst);
+    println!("[ESTOP] Emergency stop cleared");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device;
+    use crate::sim::set_simulation_mode_sync;
+    use crate::sim::tests::TEST_MUTEX;
+
+    #[test]
+    fn estop_latches_and_zeroes_sim() {
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        set_simulation_mode_sync(true).unwrap();
+        device::drive_pwm_sync(20000, Motor::M1).unwrap();
+
+        emergency_stop_sync().unwrap();
+        assert_eq!(SIM_STATE.lock().unwrap()[Motor::M1].pwm, 0);
+        assert!(device::drive_pwm_sync(20000, Motor::M1).is_err());
+        assert!(device::drive_simply_sync(100, Motor::M2).is_err());
+        // stop commands still go through
+        assert!(device::drive_simply_sync(64, Motor::M2).is_ok());
+
+        clear_estop_sync().unwrap();
+        assert!(device::drive_pwm_sync(1000, Motor::M1).is_ok());
+        device::drive_pwm_sync(0, Motor::M1).unwrap();
+        set_simulation_mode_sync(false).unwrap();
+    }
+
+    #[test]
+    fn drive_waiting_on_the_lock_is_rejected_after_estop() {
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        set_simulation_mode_sync(true).unwrap();
+        // the drive passes its first latch check, then waits for the sim lock
+        let sim = SIM_STATE.lock().unwrap();
+        let drive = std::thread::spawn(|| device::drive_pwm_sync(20000, Motor::M1));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        ESTOP_LATCHED.store(true, Ordering::SeqCst);
+        drop(sim);
+        assert!(drive.join().unwrap().is_err());
+        emergency_stop_sync().unwrap();
+        assert_eq!(SIM_STATE.lock().unwrap()[Motor::M1].pwm, 0);
+
+        // on the port: the frame is refused before it is written
+        let mut rc = device::Roboclaw { addr: 0x80, baud_rate: 115_200, port_name: String::new(), port: None, known_addrs: Vec::new() };
+        let e = device::send_and_read(&[0x80, 32, 0x4E, 0x20, 0, 0], &mut rc).unwrap_err();
+        assert!(e.contains("Emergency stop"), "{}", e);
+        // stop frames still go out (and fail here only because no port is open)
+        let e = device::send_and_read(&[0x80, 32, 0, 0, 0, 0], &mut rc).unwrap_err();
+        assert!(e.contains("not opened"), "{}", e);
+
+        clear_estop_sync().unwrap();
+        set_simulation_mode_sync(false).unwrap();
+    }
+
+    #[test]
+    fn latched_qpps_run_never_drives() {
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        set_simulation_mode_sync(true).unwrap();
+        device::drive_pwm_sync(0, Motor::M1).unwrap();
+        emergency_stop_sync().unwrap();
+        assert!(device::measure_qpps_sync(Motor::M1, 200).is_err());
+        assert_eq!(SIM_STATE.lock().unwrap()[Motor::M1].pwm, 0);
+        clear_estop_sync().unwrap();
+        set_simulation_mode_sync(false).unwrap();
+    }
+}
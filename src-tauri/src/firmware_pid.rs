@@ -0,0 +1,106 @@
+use serde::{Serialize, Deserialize};
+
+use crate::device::VelocityPidParams;
+
+// Velocity loop of the simulated controller. `Continuous` is the sim's original float PID
+// (per-second integrator, output normalized by QPPS). `Firmware` emulates the controller's
+// discrete loop: it runs at a fixed rate on integer speeds measured from encoder counts,
+// applies the 16.16 gains in integer math on top of a QPPS feedforward, limits the
+// integrator so its term cannot exceed full duty, and holds the output between ticks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum VelocityLoop {
+    #[default]
+    Continuous,
+    Firmware {
+        #[serde(default = "default_loop_hz")]
+        loop_hz: u32,
+    },
+}
+
+fn default_loop_hz() -> u32 {
+    300
+}
+
+pub const MAX_LOOP_HZ: u32 = 1000;
+
+impl VelocityLoop {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            VelocityLoop::Firmware { loop_hz } if loop_hz == 0 || loop_hz > MAX_LOOP_HZ => {
+                Err(format!("loop_hz must be within 1..{} (got {})", MAX_LOOP_HZ, loop_hz))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Integer state of the firmware loop
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FirmwarePidState {
+    last_encoder: i64,
+    since_tick_s: f64,
+    pub integral: i64,
+    last_err: i64,
+    pub output: i32, // duty held until the next tick (±32767)
+}
+
+impl FirmwarePidState {
+    // Stopped loop measuring from `encoder`
+    pub fn idle(encoder: i64) -> Self {
+        FirmwarePidState { last_encoder: encoder, ..Default::default() }
+    }
+
+    // Advance by `h` seconds; on a loop tick, measure speed from the encoder and update the
+    // output. Returns the duty to apply (-1..1).
+    pub fn step(&mut self, pid: &VelocityPidParams, set_pps: f32, encoder: i64, loop_hz: u32, h: f64) -> f32 {
+        self.since_tick_s += h;
+        let period = 1.0 / loop_hz as f64;
+        if self.since_tick_s + 1e-9 >= period {
+            // whole counts over the time since the last tick
+            let speed = ((encoder - self.last_encoder) as f64 / self.since_tick_s).round() as i64;
+            self.last_encoder = encoder;
+            self.since_tick_s -= period;
+            self.tick(pid, set_pps.round() as i64, speed);
+        }
+        self.output as f32 / 32767.0
+    }
+
+    fn tick(&mut self, pid: &VelocityPidParams, set_pps: i64, speed: i64) {
+        let (p, i, d) = (pid.p as i64, pid.i as i64, pid.d as i64);
+        let err = set_pps - speed;
+        self.integral += err;
+        if i > 0 {
+            let limit = (32767_i64 << 16) / i;
+            self.integral = self.integral.clamp(-limit, limit);
+        }
+        let deriv = err - self.last_err;
+        self.last_err = err;
+        let feedforward = if pid.qpps > 0 { set_pps * 32767 / pid.qpps as i64 } else { 0 };
+        let correction = (p * err + i * self.integral + d * deriv) >> 16;
+        self.output = (feedforward + correction).clamp(-32767, 32767) as i32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_is_held_between_ticks_and_integrator_is_limited() {
+        let pid = VelocityPidParams { p: 0, i: 4 << 16, d: 0, qpps: 1000 };
+        let mut st = FirmwarePidState::default();
+        // motor never moves: the integrator winds up to its limit and no further
+        let outputs: Vec<f32> = (0..3000).map(|_| st.step(&pid, 500.0, 0, 100, 0.001)).collect();
+        assert!(outputs[..9].iter().all(|&u| u == 0.0));
+        // first tick: feedforward 500/1000 of full duty plus I * 500
+        assert_eq!(st_output(outputs[9]), 16383 + 2000);
+        assert!(outputs[9..19].iter().all(|&u| u == outputs[9]));
+        assert_eq!(st.integral, (32767 << 16) / (4 << 16));
+        assert_eq!(st.output, 32767);
+    }
+
+    fn st_output(u: f32) -> i32 {
+        (u * 32767.0).round() as i32
+    }
+}
@@ -0,0 +1,144 @@
+use serde::{Serialize, Deserialize};
+
+// Sensor and link imperfections for the simulator. Everything random comes from one
+// seeded generator, so a run with the same seed and inputs fails the same way.
+
+// Small deterministic PRNG (SplitMix64)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimRng(u64);
+
+impl Default for SimRng {
+    fn default() -> Self {
+        SimRng::new(1)
+    }
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        SimRng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1)
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Standard normal (Box-Muller)
+    pub fn gaussian(&mut self) -> f64 {
+        let u1 = 1.0 - self.uniform(); // (0, 1]
+        let u2 = self.uniform();
+        (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    }
+
+    // True with probability `p`; draws nothing when `p` is zero
+    pub fn chance(&mut self, p: f64) -> bool {
+        p > 0.0 && self.uniform() < p
+    }
+}
+
+// Simulator-wide fault injection settings. All off by default.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FaultInjection {
+    pub seed: u64,
+    pub speed_noise_std: f32,   // Gaussian noise on reported speed (pps)
+    pub speed_quantum: f32,     // reported speed rounded to multiples of this (pps; 0 = whole pps)
+    pub speed_window_s: f64,    // speed measured as whole encoder counts over this window (0 = instantaneous)
+    pub current_noise_std: f32, // Gaussian noise on reported current (10 mA units)
+    pub current_quantum: f32,   // reported current rounded to multiples of this (10 mA units)
+    pub sample_jitter_ms: f64,  // extra random delay of up to this much on every experiment sample
+    pub latency_ms: f64,        // added to every request/reply
+    pub drop_rate: f64,         // probability a reply never arrives (0..1)
+    pub crc_error_rate: f64,    // probability a reply arrives with a bad CRC (0..1)
+}
+
+impl Default for FaultInjection {
+    fn default() -> Self {
+        FaultInjection {
+            seed: 1,
+            speed_noise_std: 0.0,
+            speed_quantum: 0.0,
+            speed_window_s: 0.0,
+            current_noise_std: 0.0,
+            current_quantum: 0.0,
+            sample_jitter_ms: 0.0,
+            latency_ms: 0.0,
+            drop_rate: 0.0,
+            crc_error_rate: 0.0,
+        }
+    }
+}
+
+impl FaultInjection {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, v) in [("speed_noise_std", self.speed_noise_std), ("speed_quantum", self.speed_quantum), ("current_noise_std", self.current_noise_std), ("current_quantum", self.current_quantum)] {
+            if !v.is_finite() || v < 0.0 { return Err(format!("{} must be >= 0 (got {})", name, v)); }
+        }
+        if !(0.0..=1.0).contains(&self.speed_window_s) {
+            return Err(format!("speed_window_s must be within 0..1 s (got {})", self.speed_window_s));
+        }
+        for (name, v) in [("sample_jitter_ms", self.sample_jitter_ms), ("latency_ms", self.latency_ms)] {
+            if !(0.0..=1000.0).contains(&v) { return Err(format!("{} must be within 0..1000 ms (got {})", name, v)); }
+        }
+        for (name, v) in [("drop_rate", self.drop_rate), ("crc_error_rate", self.crc_error_rate)] {
+            if !(0.0..=1.0).contains(&v) { return Err(format!("{} must be within 0..1 (got {})", name, v)); }
+        }
+        Ok(())
+    }
+}
+
+// Round to a multiple of `quantum` (no-op when it is zero)
+pub fn quantize(v: f32, quantum: f32) -> f32 {
+    if quantum > 0.0 { (v / quantum).round() * quantum } else { v }
+}
+
+// Fixed-period speed measurement: the count rate over the last complete window
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpeedWindow {
+    start_encoder: i64,
+    elapsed_s: f64,
+    pub latched: f32,
+}
+
+impl SpeedWindow {
+    // Start a fresh window at `encoder` (after a counter reset or a settings change)
+    pub fn restart(&mut self, encoder: i64) {
+        *self = SpeedWindow { start_encoder: encoder, ..Default::default() };
+    }
+
+    pub fn update(&mut self, encoder: i64, window_s: f64, h: f64) {
+        self.elapsed_s += h;
+        if self.elapsed_s + 1e-9 >= window_s {
+            self.latched = ((encoder - self.start_encoder) as f64 / self.elapsed_s) as f32;
+            self.start_encoder = encoder;
+            self.elapsed_s = 0.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rng_is_seeded_and_roughly_normal() {
+        let mut a = SimRng::new(42);
+        let mut b = SimRng::new(42);
+        let xs: Vec<f64> = (0..20000).map(|_| a.gaussian()).collect();
+        let ys: Vec<f64> = (0..20000).map(|_| b.gaussian()).collect();
+        assert_eq!(xs, ys);
+        let mean = xs.iter().sum::<f64>() / xs.len() as f64;
+        let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / xs.len() as f64;
+        assert!(mean.abs() < 0.03, "mean {}", mean);
+        assert!((var - 1.0).abs() < 0.05, "var {}", var);
+        assert_ne!(SimRng::new(43).next_u64(), SimRng::new(42).next_u64());
+    }
+}
@@ -3,19 +3,43 @@ use std::time::{Duration, Instant};
 mod sim;
 mod estimators;
 mod device;
+mod traffic;
+#[cfg(test)]
+mod replay;
+mod estop;
+mod safety;
+mod monitor;
+mod motor;
+mod settings;
+mod backup;
+mod plant;
+pub mod injection;
+mod disturbance;
+mod position;
+mod firmware_pid;
+mod twin;
+mod scenario;
+pub mod emulator;
+mod sim_bus;
+mod robot;
+mod battery;
+mod thermal;
 
 use serde_json::Value as JsonValue;
 
-use crate::sim::{is_simulation_enabled, SIM_STATE, sim_update};
+use crate::sim::{is_simulation_enabled, SIM_STATE, sim_update, sim_wait_unlocked};
 use crate::estimators::{FrfPoint, StepSample};
-use crate::device::{PositionPidParams, VelocityPidParams};
+use crate::device::{PortFilter, PositionPidParams, SerialPortEntry, VelocityPidParams};
+use crate::motor::{Motor, MotorSelect};
 
 const SIMULATED_PORT: &str = "SIMULATED";
+// Emulated controllers at several addresses, reached over the serial code path (see sim_bus.rs)
+const SIMULATED_BUS_PORT: &str = "SIMULATED_BUS";
 
 // Device implementations live in `device.rs`; command wrappers are defined in this file.
 // Run a step response entirely in the Rust sim and return sampled data
 #[tauri::command]
-async fn run_step_response_async(motor_index: u8, step_value: u8, duration_ms: u32, sample_interval_ms: u32, apply_delay_ms: u32) -> Result<Vec<(i64, i32, i32)>, String> {
+async fn run_step_response_async(motor_index: Motor, step_value: u8, duration_ms: u32, sample_interval_ms: u32, apply_delay_ms: u32) -> Result<Vec<(i64, i32, i32)>, String> {
     tauri::async_runtime::spawn_blocking(move || {
         let mut results: Vec<(i64, i32, i32)> = Vec::new();
 
@@ -23,65 +47,59 @@ async fn run_step_response_async(motor_index: u8, step_value: u8, duration_ms: u
             return Err("Simulation mode not enabled".to_string());
         }
 
+        if sample_interval_ms == 0 {
+            return Err("sample_interval_ms must be > 0".to_string());
+        }
+        let step_value = safety::filter_speed(motor_index, step_value)?;
+        let accel = safety::duty_accel(motor_index)?;
         let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
 
         // Initialize sim state
-        sim.m1_speed = 64;
-        sim.m2_speed = 64;
-        sim.m1_pwm = 0;
-        sim.m2_pwm = 0;
-        sim.m1_mode_pwm = false;
-        sim.m2_mode_pwm = false;
-        sim.m1_vel = 0.0;
-        sim.m2_vel = 0.0;
+        for m in sim.motors.iter_mut() {
+            m.speed = 64;
+            m.pwm = 0;
+            m.mode_pwm = false;
+            m.position_move = None;
+            m.vel = 0.0;
+        }
         sim.last_update = Some(Instant::now());
 
         // settle before sampling
-        let settle = Duration::from_millis(200);
-        std::thread::sleep(settle);
-
-        // sampling loop: start sampling, then apply step after requested apply_delay
-        let apply_delay = Duration::from_millis(apply_delay_ms as u64);
-        let start = Instant::now();
+        sim = sim_wait_unlocked(sim, Duration::from_millis(200));
 
+        // sampling loop on the sim clock: start sampling, then apply step after requested apply_delay
+        let start = sim.time_s;
+        sim[motor_index].begin_disturbances(start);
         let sample_interval = Duration::from_millis(sample_interval_ms as u64);
-        let total_duration = Duration::from_millis(duration_ms as u64);
+        let apply_at = apply_delay_ms as i64;
+        let end_at = apply_at + duration_ms as i64;
 
-        let step_apply_time = start + apply_delay;
-        let end_time = step_apply_time + total_duration;
-
-        let mut now = Instant::now();
-        while now <= end_time {
+        loop {
+            // time relative to sampling start (non-negative). Frontend will
+            // use the command change time (applyDelay) to position the step.
+            let t_rel = ((sim.time_s - start) * 1000.0).round() as i64;
+            if t_rel > end_at { break; }
+            estop::ensure_not_latched()?;
             // apply step if reached
-            if now >= step_apply_time {
-                if motor_index == 1 {
-                    sim.m1_speed = step_value;
-                    sim.m1_mode_pwm = false;
-                } else {
-                    sim.m2_speed = step_value;
-                    sim.m2_mode_pwm = false;
-                }
+            if t_rel >= apply_at {
+                sim[motor_index].speed = step_value;
+                sim[motor_index].mode_pwm = false;
+                sim[motor_index].duty_accel = accel;
             }
 
             sim_update(&mut sim);
+            monitor::check_sim(&mut sim, motor_index)?;
 
-            // record time relative to sampling start (non-negative). Frontend will
-            // use the command change time (applyDelay) to position the step.
-            let t_rel = now.duration_since(start).as_millis() as i64;
-            let vel = if motor_index == 1 { sim.m1_vel } else { sim.m2_vel };
-            let cmd_now = if now >= step_apply_time && now < step_apply_time + total_duration { step_value as i32 } else { 64 as i32 };
-            results.push((t_rel, vel.round() as i32, cmd_now));
+            let vel = sim.measured_speed(motor_index);
+            let cmd_now = if t_rel >= apply_at && t_rel < end_at { step_value as i32 } else { 64 as i32 };
+            results.push((t_rel, vel, cmd_now));
+            sim[motor_index].record_disturbance(t_rel);
 
-            std::thread::sleep(sample_interval);
-            now = Instant::now();
+            sim = sim_wait_unlocked(sim, sample_interval);
         }
 
         // after end, issue stop
-        if motor_index == 1 {
-            sim.m1_speed = 64;
-        } else {
-            sim.m2_speed = 64;
-        }
+        sim[motor_index].speed = 64;
         sim_update(&mut sim);
 
         Ok(results)
@@ -92,7 +110,7 @@ async fn run_step_response_async(motor_index: u8, step_value: u8, duration_ms: u
 
 // Run a step response on a real device: send stop, wait, apply step, sample via read_speed
 #[tauri::command]
-async fn run_step_response_device_async(motor_index: u8, step_value: u8, duration_ms: u32, sample_interval_ms: u32, apply_delay_ms: u32) -> Result<Vec<(i64, i32, i32)>, String> {
+async fn run_step_response_device_async(motor_index: Motor, step_value: u8, duration_ms: u32, sample_interval_ms: u32, apply_delay_ms: u32) -> Result<Vec<(i64, i32, i32)>, String> {
     tauri::async_runtime::spawn_blocking(move || {
         let mut results: Vec<(i64, i32, i32)> = Vec::new();
 
@@ -120,6 +138,7 @@ async fn run_step_response_device_async(motor_index: u8, step_value: u8, duratio
         let mut now = Instant::now();
         let mut applied = false;
         while now <= end_time {
+            estop::ensure_not_latched()?;
             if !applied && now >= step_apply_time {
                 // apply step
                     device::drive_simply_sync(step_value, motor_index)?;
@@ -138,6 +157,7 @@ async fn run_step_response_device_async(motor_index: u8, step_value: u8, duratio
 
             let t_rel = now.duration_since(start).as_millis() as i64;
             let cmd_now = if applied { step_value as i32 } else { 64 as i32 };
+            if vel != -9999 { monitor::check(motor_index, monitor::speed_cmd_duty(cmd_now as u8), vel, None)?; }
             results.push((t_rel, vel, cmd_now));
 
             std::thread::sleep(sample_interval);
@@ -158,7 +178,7 @@ async fn run_step_response_device_async(motor_index: u8, step_value: u8, duratio
 // Frequency response: perform per-frequency sine tests (steady-state fit)
 #[tauri::command]
 async fn run_frequency_response_async(
-    motor_index: u8,
+    motor_index: Motor,
     start_hz: f64,
     end_hz: f64,
     points: u32,
@@ -190,57 +210,71 @@ async fn run_frequency_response_async(
             let samples_per_cycle = (fs / freq).round() as usize;
             let n_samples = (samples_per_cycle * (cycles as usize)).max(3);
 
-            // settle
-            std::thread::sleep(Duration::from_millis(200));
-
-            // Collect samples
+            // Collect samples, projecting the output onto sin/cos of the reference
             let mut s_sum = 0.0_f64;
             let mut c_sum = 0.0_f64;
             let mut count = 0_usize;
 
             let omega = 2.0 * std::f64::consts::PI * freq;
-            let t0 = Instant::now();
-
-            for _ in 0..n_samples {
-                let now = Instant::now();
-                let t = now.duration_since(t0).as_secs_f64();
-                let sinref = (omega * t).sin();
-                let cosref = (omega * t).cos();
-
-                // compute command (centered at 64)
-                let cmdf = 64.0 + (amplitude_cmd as f64) * sinref;
-                let cmdu = cmdf.round().clamp(0.0, 127.0) as u8;
-
-                if is_simulation_enabled() {
-                    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
-                    if motor_index == 1 {
-                        sim.m1_speed = cmdu;
-                        sim.m1_mode_pwm = false;
-                    } else {
-                        sim.m2_speed = cmdu;
-                        sim.m2_mode_pwm = false;
-                    }
+            // command centered at 64
+            let command = |t: f64| (64.0 + (amplitude_cmd as f64) * (omega * t).sin()).round().clamp(0.0, 127.0) as u8;
+
+            if is_simulation_enabled() {
+                if sample_interval_ms == 0 { return Err("sample_interval_ms must be > 0".to_string()); }
+                let accel = safety::duty_accel(motor_index)?;
+                let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+                sim[motor_index].position_move = None;
+                // settle
+                sim = sim_wait_unlocked(sim, Duration::from_millis(200));
+                let t0 = sim.time_s;
+                // the disturbance schedule and trace span the whole sweep
+                if i == 0 { sim[motor_index].begin_disturbances(t0); }
+                for _ in 0..n_samples {
+                    estop::ensure_not_latched()?;
+                    let t = sim.time_s - t0;
+                    let cmdu = safety::filter_speed(motor_index, command(t))?;
+                    sim[motor_index].speed = cmdu;
+                    sim[motor_index].mode_pwm = false;
+                    sim[motor_index].duty_accel = accel;
                     sim_update(&mut sim);
-                } else {
+                    monitor::check_sim(&mut sim, motor_index)?;
+                    let vel = sim.measured_speed(motor_index) as f64;
+                    let t_ms = ((sim.time_s - sim[motor_index].disturbance_t0) * 1000.0).round() as i64;
+                    sim[motor_index].record_disturbance(t_ms);
+                    s_sum += vel * (omega * t).sin();
+                    c_sum += vel * (omega * t).cos();
+                    count += 1;
+                    sim = sim_wait_unlocked(sim, sample_interval);
+                }
+            } else {
+                // settle
+                std::thread::sleep(Duration::from_millis(200));
+                let t0 = Instant::now();
+                for _ in 0..n_samples {
+                    estop::ensure_not_latched()?;
+                    let t = t0.elapsed().as_secs_f64();
+                    let cmdu = command(t);
                     // send to device
-                        device::drive_simply_sync(cmdu, motor_index)?;
+                    device::drive_simply_sync(cmdu, motor_index)?;
+
+                    // read velocity
+                    let vel = match device::read_speed_sync(motor_index) {
+                        Ok(v) => {
+                            monitor::check(motor_index, monitor::speed_cmd_duty(cmdu), v, None)?;
+                            v as f64
+                        }
+                        Err(_) => {
+                            // treat as zero on error
+                            0.0
+                        }
+                    };
+
+                    s_sum += vel * (omega * t).sin();
+                    c_sum += vel * (omega * t).cos();
+                    count += 1;
+
+                    std::thread::sleep(sample_interval);
                 }
-
-                // read velocity
-                let vel = match device::read_speed_sync(motor_index) {
-                    Ok(v) => v as f64,
-                    Err(_) => {
-                        // treat as zero on error
-                        0.0
-                    }
-                };
-
-                // accumulate projection onto sin/cos to estimate amplitude/phase
-                s_sum += vel * sinref;
-                c_sum += vel * cosref;
-                count += 1;
-
-                std::thread::sleep(sample_interval);
             }
 
             // compute amplitude and phase from projections
@@ -251,7 +285,7 @@ async fn run_frequency_response_async(
             // compute input amplitude in velocity units if sim, else in command units
             let amplitude_in_velocity = if is_simulation_enabled() {
                 let sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
-                let gain = if motor_index == 1 { sim.gain_m1 } else { sim.gain_m2 } as f64;
+                let gain = sim[motor_index].full_scale_pps() as f64;
                 gain * (amplitude_cmd as f64 / 63.0)
             } else {
                 // for device, return per-command-unit gain (velocity per command unit)
@@ -269,9 +303,54 @@ async fn run_frequency_response_async(
     })
     .await
     .map_err(|e| format!("Failed to join: {:?}", e))?}
+// Open-loop PWM step on the simulator, timed by the sim clock
+fn run_pwm_step_sim(motor_index: Motor, pwm_step: i16, duration_ms: u32, sample_interval_ms: u32, apply_delay_ms: u32) -> Result<Vec<(i64, i32, i32)>, String> {
+    if sample_interval_ms == 0 {
+        return Err("sample_interval_ms must be > 0".to_string());
+    }
+    let pwm_step = safety::filter_duty(motor_index, pwm_step)?;
+    let accel = safety::duty_accel(motor_index)?;
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    let mut results: Vec<(i64, i32, i32)> = Vec::new();
+
+    // initialize
+    for m in sim.motors.iter_mut() { m.pwm = 0; m.mode_pwm = true; m.position_move = None; m.vel = 0.0; }
+    sim = sim_wait_unlocked(sim, Duration::from_millis(200));
+
+    let start = sim.time_s;
+    sim[motor_index].begin_disturbances(start);
+    let apply_at = apply_delay_ms as i64;
+    let end_at = apply_at + duration_ms as i64;
+    let sample_interval = Duration::from_millis(sample_interval_ms as u64);
+
+    loop {
+        let t_rel = ((sim.time_s - start) * 1000.0).round() as i64;
+        if t_rel > end_at { break; }
+        estop::ensure_not_latched()?;
+        if t_rel >= apply_at {
+            sim[motor_index].pwm = pwm_step;
+            sim[motor_index].mode_pwm = true;
+            sim[motor_index].duty_accel = accel;
+        }
+        sim_update(&mut sim);
+        monitor::check_sim(&mut sim, motor_index)?;
+        let vel = sim.measured_speed(motor_index);
+        let cmd_now = if t_rel >= apply_at { pwm_step as i32 } else { 0i32 };
+        results.push((t_rel, vel, cmd_now));
+        sim[motor_index].record_disturbance(t_rel);
+        sim = sim_wait_unlocked(sim, sample_interval);
+    }
+
+    // restore pwm to 0
+    sim[motor_index].pwm = 0;
+    sim_update(&mut sim);
+
+    Ok(results)
+}
+
 // Run an OPEN-LOOP PWM step response: apply PWM and sample measured speed via Read All Status.
 #[tauri::command]
-async fn run_pwm_step_response_async(motor_index: u8, pwm_step: i16, duration_ms: u32, sample_interval_ms: u32, apply_delay_ms: u32) -> Result<Vec<(i64, i32, i32)>, String> {
+async fn run_pwm_step_response_async(motor_index: Motor, pwm_step: i16, duration_ms: u32, sample_interval_ms: u32, apply_delay_ms: u32) -> Result<Vec<(i64, i32, i32)>, String> {
     tauri::async_runtime::spawn_blocking(move || {
         let mut results: Vec<(i64, i32, i32)> = Vec::new();
 
@@ -284,39 +363,7 @@ async fn run_pwm_step_response_async(motor_index: u8, pwm_step: i16, duration_ms
             }
         };
         if force_sim {
-            let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
-
-            // initialize
-            sim.m1_pwm = 0; sim.m2_pwm = 0; sim.m1_mode_pwm = true; sim.m2_mode_pwm = true;
-            sim.m1_vel = 0.0; sim.m2_vel = 0.0;
-            let settle = std::time::Duration::from_millis(200);
-            std::thread::sleep(settle);
-
-            let apply_delay = std::time::Duration::from_millis(apply_delay_ms as u64);
-            let start = std::time::Instant::now();
-            let step_apply_time = start + apply_delay;
-            let end_time = step_apply_time + std::time::Duration::from_millis(duration_ms as u64);
-            let sample_interval = std::time::Duration::from_millis(sample_interval_ms as u64);
-
-            let mut now = std::time::Instant::now();
-            while now <= end_time {
-                if now >= step_apply_time {
-                    if motor_index == 1 { sim.m1_pwm = pwm_step; sim.m1_mode_pwm = true; } else { sim.m2_pwm = pwm_step; sim.m2_mode_pwm = true; }
-                }
-                sim_update(&mut sim);
-                let t_rel = now.duration_since(start).as_millis() as i64;
-                let vel = if motor_index == 1 { sim.m1_vel.round() as i32 } else { sim.m2_vel.round() as i32 };
-                let cmd_now = if now >= step_apply_time { pwm_step as i32 } else { 0i32 };
-                results.push((t_rel, vel, cmd_now));
-                std::thread::sleep(sample_interval);
-                now = std::time::Instant::now();
-            }
-
-            // restore pwm to 0
-            if motor_index == 1 { sim.m1_pwm = 0; } else { sim.m2_pwm = 0; }
-            sim_update(&mut sim);
-
-            return Ok(results);
+            return run_pwm_step_sim(motor_index, pwm_step, duration_ms, sample_interval_ms, apply_delay_ms);
         }
 
         // Real device: attempt to set PWM to zero, then apply pwm_step and sample via Read All Status
@@ -331,36 +378,7 @@ async fn run_pwm_step_response_async(motor_index: u8, pwm_step: i16, duration_ms
 
         if !real_drive_ok {
             // fallback to simulation-like sampling (use sim state directly)
-            let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
-            sim.m1_pwm = 0; sim.m2_pwm = 0; sim.m1_mode_pwm = true; sim.m2_mode_pwm = true;
-            let settle = std::time::Duration::from_millis(200);
-            std::thread::sleep(settle);
-
-            let apply_delay = std::time::Duration::from_millis(apply_delay_ms as u64);
-            let start = std::time::Instant::now();
-            let step_apply_time = start + apply_delay;
-            let end_time = step_apply_time + std::time::Duration::from_millis(duration_ms as u64);
-            let sample_interval = std::time::Duration::from_millis(sample_interval_ms as u64);
-
-            let mut now = std::time::Instant::now();
-            while now <= end_time {
-                if now >= step_apply_time {
-                    if motor_index == 1 { sim.m1_pwm = pwm_step; sim.m1_mode_pwm = true; } else { sim.m2_pwm = pwm_step; sim.m2_mode_pwm = true; }
-                }
-                sim_update(&mut sim);
-                let t_rel = now.duration_since(start).as_millis() as i64;
-                let vel = if motor_index == 1 { sim.m1_vel.round() as i32 } else { sim.m2_vel.round() as i32 };
-                let cmd_now = if now >= step_apply_time { pwm_step as i32 } else { 0i32 };
-                results.push((t_rel, vel, cmd_now));
-                std::thread::sleep(sample_interval);
-                now = std::time::Instant::now();
-            }
-
-            // restore pwm to 0
-            if motor_index == 1 { sim.m1_pwm = 0; } else { sim.m2_pwm = 0; }
-            sim_update(&mut sim);
-
-            return Ok(results);
+            return run_pwm_step_sim(motor_index, pwm_step, duration_ms, sample_interval_ms, apply_delay_ms);
         }
 
         // Proceed with real device sampling
@@ -376,6 +394,7 @@ async fn run_pwm_step_response_async(motor_index: u8, pwm_step: i16, duration_ms
         let mut now = std::time::Instant::now();
         let mut applied = false;
         while now <= end_time {
+            estop::ensure_not_latched()?;
             if !applied && now >= step_apply_time {
                 device::drive_pwm_sync(pwm_step, motor_index)?;
                 applied = true;
@@ -383,8 +402,9 @@ async fn run_pwm_step_response_async(motor_index: u8, pwm_step: i16, duration_ms
 
             match device::read_all_status_sync() {
                 Ok(v) => {
+                    monitor::check_status(motor_index, &v)?;
                     let t_rel = now.duration_since(start).as_millis() as i64;
-                    let vel = if motor_index == 1 { v.get("m1_speed").and_then(|x| x.as_i64()).unwrap_or(0) as i32 } else { v.get("m2_speed").and_then(|x| x.as_i64()).unwrap_or(0) as i32 };
+                    let vel = v.get(motor_index.pick("m1_speed", "m2_speed")).and_then(|x| x.as_i64()).unwrap_or(0) as i32;
                     let cmd_now = if applied { pwm_step as i32 } else { 0i32 };
                     results.push((t_rel, vel, cmd_now));
                 }
@@ -406,7 +426,7 @@ async fn run_pwm_step_response_async(motor_index: u8, pwm_step: i16, duration_ms
 // Autotune velocity using an OPEN-LOOP PWM step + system identification (estimate K, tau), then synthesize PI via IMC.
 #[tauri::command]
 async fn autotune_velocity_step_async(
-    motor_index: u8,
+    motor_index: Motor,
     pwm_step: i16,
     duration_ms: u32,
     sample_interval_ms: u32,
@@ -477,6 +497,7 @@ async fn autotune_velocity_step_async(
         "lambda_s": lambda,
         "suggested_pid": { "p": kp_fixed, "i": ki_fixed, "d": kd_fixed, "qpps": velpid.qpps },
         "samples": samples_json,
+        "disturbance": if is_simulation_enabled() { sim::get_sim_disturbance_trace_sync(motor_index)? } else { Vec::new() },
         "applied": applied,
     });
 
@@ -486,7 +507,7 @@ async fn autotune_velocity_step_async(
 // Autotune velocity using Frequency Response data and FRF fitting, then synthesize PI via IMC.
 #[tauri::command]
 async fn autotune_velocity_frf_async(
-    motor_index: u8,
+    motor_index: Motor,
     start_hz: f64,
     end_hz: f64,
     points: u32,
@@ -570,21 +591,24 @@ async fn autotune_velocity_frf_async(
 // Don't put "pub" keyword in front of these functions;
 // That will cause multiple definition/import errors.
 #[tauri::command]
-async fn drive_simply_async(speed: u8, motor_index: u8) -> Result<(), String> {
+async fn drive_simply_async(speed: u8, motor_index: Motor) -> Result<(), String> {
     tauri::async_runtime::spawn_blocking(move || device::drive_simply_sync(speed, motor_index))
         .await
         .map_err(|e| format!("Failed to join: {:?}", e))?
 }
 
 #[tauri::command]
-async fn drive_pwm_async(pwm: i16, motor_index: u8) -> Result<(), String> {
-    tauri::async_runtime::spawn_blocking(move || device::drive_pwm_sync(pwm, motor_index))
+async fn drive_pwm_async(pwm: i16, motor_index: MotorSelect) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || match motor_index {
+        MotorSelect::One(motor) => device::drive_pwm_sync(pwm, motor),
+        MotorSelect::Both => device::drive_pwm_both_sync(pwm, pwm),
+    })
         .await
         .map_err(|e| format!("Failed to join: {:?}", e))?
 }
 
 #[tauri::command]
-async fn read_speed_async(motor_index: u8) -> Result<i32, String> {
+async fn read_speed_async(motor_index: Motor) -> Result<i32, String> {
     tauri::async_runtime::spawn_blocking(move || device::read_speed_sync(motor_index))
         .await
         .map_err(|e| format!("Failed to join: {:?}", e))?
@@ -626,8 +650,350 @@ async fn configure_port(port_name: String, baud_rate: Option<u32>) -> Result<(),
 }
 
 #[tauri::command]
-fn list_serial_ports() -> Result<Vec<String>, String> {
-    device::list_serial_ports_sync()
+fn configure_address(address: u8) -> Result<(), String> {
+    device::configure_address_sync(address)
+}
+
+// Addresses (0x80..0x87) that answer on the current port
+#[tauri::command]
+async fn scan_addresses() -> Result<Vec<u8>, String> {
+    tauri::async_runtime::spawn_blocking(device::scan_addresses_sync)
+        .await
+        .map_err(|e| format!("Failed to join: {:?}", e))?
+}
+
+#[tauri::command]
+fn get_connection_info() -> Result<JsonValue, String> {
+    device::get_connection_info_sync()
+}
+
+#[tauri::command]
+fn get_settings() -> Result<settings::Settings, String> {
+    settings::get_settings_sync()
+}
+
+#[tauri::command]
+fn save_profile(profile: settings::DeviceProfile) -> Result<(), String> {
+    settings::save_profile_sync(profile)
+}
+
+// Save the live connection, limits and sim plant under `name`
+#[tauri::command]
+fn save_current_profile(name: String) -> Result<settings::DeviceProfile, String> {
+    let profile = settings::capture_profile_sync(&name)?;
+    settings::save_profile_sync(profile.clone())?;
+    Ok(profile)
+}
+
+// Identify the connected device and fit the simulator to it; saved as the sim plant of `profile` when given
+#[tauri::command]
+async fn build_sim_twin(options: Option<twin::TwinOptions>, profile: Option<String>) -> Result<twin::TwinReport, String> {
+    tauri::async_runtime::spawn_blocking(move || twin::build_twin_sync(&options.unwrap_or_default(), profile.as_deref()))
+        .await
+        .map_err(|e| format!("Failed to join: {:?}", e))?
+}
+
+#[tauri::command]
+async fn load_profile(name: String) -> Result<settings::DeviceProfile, String> {
+    tauri::async_runtime::spawn_blocking(move || settings::load_profile_sync(&name))
+        .await
+        .map_err(|e| format!("Failed to join: {:?}", e))?
+}
+
+#[tauri::command]
+fn delete_profile(name: String) -> Result<(), String> {
+    settings::delete_profile_sync(&name)
+}
+
+#[tauri::command]
+fn set_auto_connect(enabled: bool) -> Result<(), String> {
+    settings::set_auto_connect_sync(enabled)
+}
+
+// Read the full device configuration; also written to `path` and/or attached to `profile` when given
+#[tauri::command]
+async fn backup_device_config(path: Option<String>, profile: Option<String>) -> Result<backup::DeviceConfigBackup, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let backup = backup::backup_device_config_sync()?;
+        if let Some(path) = path { backup.save(&path)?; }
+        if let Some(name) = profile { settings::store_device_config_sync(&name, backup.clone())?; }
+        Ok(backup)
+    })
+    .await
+    .map_err(|e| format!("Failed to join: {:?}", e))?
+}
+
+#[tauri::command]
+async fn restore_device_config(path: String, options: Option<backup::RestoreOptions>) -> Result<backup::RestoreReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let backup = backup::DeviceConfigBackup::load(&path)?;
+        backup::restore_device_config_sync(&backup, &options.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| format!("Failed to join: {:?}", e))?
+}
+
+// Compare the live controller with a backup file (`path`) or a profile's saved configuration
+#[tauri::command]
+async fn diff_device_config(path: Option<String>, profile: Option<String>) -> Result<Vec<backup::FieldDiff>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let saved = backup::load_saved_config(path.as_deref(), profile.as_deref())?;
+        backup::diff_device_config_sync(&saved)
+    })
+    .await
+    .map_err(|e| format!("Failed to join: {:?}", e))?
+}
+
+#[tauri::command]
+async fn apply_device_config_fields(path: Option<String>, profile: Option<String>, fields: Vec<String>, options: Option<backup::RestoreOptions>) -> Result<backup::RestoreReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let saved = backup::load_saved_config(path.as_deref(), profile.as_deref())?;
+        backup::apply_device_config_fields_sync(&saved, &fields, &options.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| format!("Failed to join: {:?}", e))?
+}
+
+#[tauri::command]
+fn list_serial_ports(filter: Option<PortFilter>) -> Result<Vec<SerialPortEntry>, String> {
+    device::list_serial_ports_sync(filter)
+}
+
+// Runs on its own blocking thread so it never waits behind a queued drive/read command
+#[tauri::command]
+async fn emergency_stop() -> Result<JsonValue, String> {
+    tauri::async_runtime::spawn_blocking(estop::emergency_stop_sync)
+        .await
+        .map_err(|e| format!("Failed to join: {:?}", e))?
+}
+
+#[tauri::command]
+fn clear_estop() -> Result<(), String> {
+    estop::clear_estop_sync()
+}
+
+#[tauri::command]
+fn is_estop_latched() -> bool {
+    estop::is_estop_latched()
+}
+
+#[tauri::command]
+fn get_safety_config() -> Result<[safety::MotorSafety; 2], String> {
+    safety::get_safety_config_sync()
+}
+
+#[tauri::command]
+fn set_safety_config(motor_index: Motor, config: safety::MotorSafety) -> Result<(), String> {
+    safety::set_safety_config_sync(motor_index, config)
+}
+
+#[tauri::command]
+fn get_safety_events() -> Result<Vec<safety::SafetyEvent>, String> {
+    safety::get_safety_events_sync()
+}
+
+#[tauri::command]
+fn clear_safety_events() -> Result<(), String> {
+    safety::clear_safety_events_sync()
+}
+
+// poll_interval_ms = 0: only the experiment loops feed the monitor
+#[tauri::command]
+async fn start_fault_monitor(poll_interval_ms: Option<u32>) -> Result<(), String> {
+    let interval = poll_interval_ms.unwrap_or(100);
+    tauri::async_runtime::spawn_blocking(move || monitor::start_fault_monitor_sync(interval))
+        .await
+        .map_err(|e| format!("Failed to join: {:?}", e))?
+}
+
+#[tauri::command]
+fn stop_fault_monitor() -> Result<(), String> {
+    monitor::stop_fault_monitor_sync()
+}
+
+#[tauri::command]
+fn get_monitor_status() -> Result<monitor::MonitorStatus, String> {
+    monitor::get_monitor_status_sync()
+}
+
+#[tauri::command]
+fn set_monitor_config(motor_index: Motor, config: monitor::MonitorConfig) -> Result<(), String> {
+    monitor::set_monitor_config_sync(motor_index, config)
+}
+
+#[tauri::command]
+fn clear_motor_fault(motor_index: Motor) -> Result<(), String> {
+    monitor::clear_motor_fault_sync(motor_index)
+}
+
+#[tauri::command]
+fn set_sim_fault(motor_index: Motor, fault: sim::SimFault) -> Result<(), String> {
+    sim::set_sim_fault_sync(motor_index, fault)
+}
+
+// Switch a simulated motor between the first-order lag and the DC motor model
+#[tauri::command]
+fn set_sim_plant(motor_index: Motor, plant: plant::PlantModel) -> Result<(), String> {
+    sim::set_sim_plant_sync(motor_index, plant)
+}
+
+#[tauri::command]
+fn set_sim_nonlinearities(motor_index: Motor, config: plant::Nonlinearities) -> Result<(), String> {
+    sim::set_sim_nonlinearities_sync(motor_index, config)
+}
+
+#[tauri::command]
+fn set_sim_fault_injection(config: injection::FaultInjection) -> Result<(), String> {
+    sim::set_sim_fault_injection_sync(config)
+}
+
+#[tauri::command]
+fn set_sim_disturbances(motor_index: Motor, schedule: Vec<disturbance::Disturbance>) -> Result<(), String> {
+    sim::set_sim_disturbances_sync(motor_index, schedule)
+}
+
+#[tauri::command]
+fn get_sim_disturbance_trace(motor_index: Motor) -> Result<Vec<disturbance::DisturbanceSample>, String> {
+    sim::get_sim_disturbance_trace_sync(motor_index)
+}
+
+// Controllers served on the SIMULATED_BUS port
+#[tauri::command]
+fn configure_sim_bus(controllers: Vec<sim_bus::SimControllerConfig>) -> Result<Vec<u8>, String> {
+    sim_bus::configure_sim_bus_sync(&controllers)
+}
+
+#[tauri::command]
+fn get_sim_bus_snapshot(address: u8) -> Result<scenario::SimSnapshot, String> {
+    sim_bus::get_sim_bus_snapshot_sync(address)
+}
+
+#[tauri::command]
+fn set_sim_bus_fault(address: u8, motor_index: Motor, fault: sim::SimFault) -> Result<(), String> {
+    sim_bus::set_sim_bus_fault_sync(address, motor_index, fault)
+}
+
+// Copy of the live sim state; also written to `path` when given
+#[tauri::command]
+fn save_sim_snapshot(path: Option<String>) -> Result<scenario::SimSnapshot, String> {
+    let snapshot = scenario::take_sim_snapshot_sync()?;
+    if let Some(path) = path { snapshot.save(&path)?; }
+    Ok(snapshot)
+}
+
+// Restore the sim from a snapshot, given inline or as a file
+#[tauri::command]
+fn restore_sim_snapshot(snapshot: Option<scenario::SimSnapshot>, path: Option<String>) -> Result<(), String> {
+    let snapshot = match (snapshot, path) {
+        (Some(snapshot), None) => snapshot,
+        (None, Some(path)) => scenario::SimSnapshot::load(&path)?,
+        _ => return Err("Give either a snapshot or a snapshot path".into()),
+    };
+    scenario::restore_sim_snapshot_sync(&snapshot)
+}
+
+#[tauri::command]
+fn reset_sim() -> Result<(), String> {
+    scenario::reset_sim_sync()
+}
+
+// Run a scenario (inline or from a file) on a private copy of the sim; the trace is also
+// written to `trace_path` when given
+#[tauri::command]
+async fn run_sim_scenario(scenario: Option<scenario::Scenario>, path: Option<String>, trace_path: Option<String>) -> Result<scenario::ScenarioTrace, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let scenario = match (scenario, path) {
+            (Some(scenario), None) => scenario,
+            (None, Some(path)) => scenario::Scenario::load(&path)?,
+            _ => return Err("Give either a scenario or a scenario path".into()),
+        };
+        let trace = scenario::run_scenario_sync(&scenario)?;
+        if let Some(path) = trace_path { trace.save(&path)?; }
+        Ok(trace)
+    })
+    .await
+    .map_err(|e| format!("Failed to join: {:?}", e))?
+}
+
+// Turn the differential-drive robot sim on (`config`) or off (null)
+#[tauri::command]
+fn set_sim_robot(config: Option<robot::RobotConfig>) -> Result<Option<robot::RobotSim>, String> {
+    robot::set_sim_robot_sync(config)
+}
+
+#[tauri::command]
+fn get_sim_pose() -> Result<robot::PoseEvent, String> {
+    robot::get_sim_pose_sync()
+}
+
+#[tauri::command]
+fn get_sim_pose_events() -> Result<Vec<robot::PoseEvent>, String> {
+    robot::get_sim_pose_events_sync()
+}
+
+#[tauri::command]
+fn reset_sim_pose(pose: Option<robot::Pose2D>) -> Result<(), String> {
+    robot::reset_sim_pose_sync(pose)
+}
+
+#[tauri::command]
+fn set_sim_battery(config: battery::BatteryConfig) -> Result<(), String> {
+    sim::set_sim_battery_sync(config)
+}
+
+// Bus voltage, draw, charge and under-voltage state of the simulated battery
+#[tauri::command]
+fn get_sim_battery() -> Result<battery::Battery, String> {
+    sim::get_sim_battery_sync()
+}
+
+#[tauri::command]
+fn set_sim_thermal(config: thermal::ThermalConfig) -> Result<(), String> {
+    sim::set_sim_thermal_sync(config)
+}
+
+// Driver and winding temperatures of the simulated motors
+#[tauri::command]
+fn get_sim_thermal() -> Result<thermal::Thermal, String> {
+    sim::get_sim_thermal_sync()
+}
+
+#[tauri::command]
+fn set_sim_velocity_loop(motor_index: Motor, config: firmware_pid::VelocityLoop) -> Result<(), String> {
+    sim::set_sim_velocity_loop_sync(motor_index, config)
+}
+
+#[tauri::command]
+fn set_sim_clock(clock: sim::SimClock) -> Result<(), String> {
+    sim::set_sim_clock_sync(clock)
+}
+
+// Advance a fast-paced simulator; returns the new sim time in seconds
+#[tauri::command]
+fn step_sim(duration_ms: u32) -> Result<f64, String> {
+    sim::step_sim_sync(duration_ms)
+}
+
+#[tauri::command]
+fn set_traffic_logging(enabled: bool, capacity: Option<usize>) -> Result<(), String> {
+    traffic::set_traffic_logging_sync(enabled, capacity)
+}
+
+#[tauri::command]
+fn get_traffic_log() -> Result<Vec<traffic::TrafficEntry>, String> {
+    traffic::get_traffic_log_sync()
+}
+
+#[tauri::command]
+fn clear_traffic_log() -> Result<(), String> {
+    traffic::clear_traffic_log_sync()
+}
+
+#[tauri::command]
+async fn export_traffic_log(path: String, format: Option<String>) -> Result<usize, String> {
+    tauri::async_runtime::spawn_blocking(move || traffic::export_traffic_log_sync(path, format))
+        .await
+        .map_err(|e| format!("Failed to join: {:?}", e))?
 }
 
 #[tauri::command]
@@ -636,7 +1002,7 @@ fn set_simulation_mode(enabled: bool) -> Result<(), String> {
 }
 
 #[tauri::command]
-fn set_sim_params(motor_index: u8, tau: f32, gain: f32) -> Result<(), String> {
+fn set_sim_params(motor_index: Motor, tau: f32, gain: f32) -> Result<(), String> {
     sim::set_sim_params_sync(motor_index, tau, gain)
 }
 
@@ -663,36 +1029,41 @@ async fn fit_frf_async(
 }
 
 #[tauri::command]
-async fn read_position_pid_async(motor_index: u8) -> Result<PositionPidParams, String> {
+async fn read_position_pid_async(motor_index: Motor) -> Result<PositionPidParams, String> {
     device::read_position_pid_sync(motor_index)
 }
 
 #[tauri::command]
-async fn set_position_pid_async(motor_index: u8, p: i32, i: i32, d: i32, max_i: i32, deadzone: i32, min: i32, max: i32) -> Result<(), String> {
+async fn set_position_pid_async(motor_index: Motor, p: i32, i: i32, d: i32, max_i: i32, deadzone: i32, min: i32, max: i32) -> Result<(), String> {
     let params = PositionPidParams { p, i, d, max_i, deadzone, min, max };
     device::set_position_pid_sync(motor_index, params)
 }
 
 #[tauri::command]
-async fn read_velocity_pid_async(motor_index: u8) -> Result<VelocityPidParams, String> {
+async fn drive_position_async(motor_index: Motor, accel: u32, speed: u32, decel: u32, position: i32) -> Result<(), String> {
+    device::drive_position_sync(motor_index, position::PositionCommand { accel, speed, decel, position })
+}
+
+#[tauri::command]
+async fn read_velocity_pid_async(motor_index: Motor) -> Result<VelocityPidParams, String> {
     device::read_velocity_pid_sync(motor_index)
 }
 
 #[tauri::command]
-async fn set_velocity_pid_async(motor_index: u8, p: i32, i: i32, d: i32, qpps: i32) -> Result<(), String> {
+async fn set_velocity_pid_async(motor_index: Motor, p: i32, i: i32, d: i32, qpps: i32) -> Result<(), String> {
     let params = VelocityPidParams { p, i, d, qpps };
     device::set_velocity_pid_sync(motor_index, params)
 }
 
 #[tauri::command]
-async fn write_velocity_pid_eeprom_async(motor_index: u8) -> Result<(), String> {
+async fn write_velocity_pid_eeprom_async(motor_index: Motor) -> Result<(), String> {
     tauri::async_runtime::spawn_blocking(move || device::write_velocity_pid_eeprom_sync(motor_index))
         .await
         .map_err(|e| format!("Failed to join: {:?}", e))?
 }
 
 #[tauri::command]
-async fn measure_qpps_async(motor_index: u8, duration_ms: Option<u32>) -> Result<serde_json::Value, String> {
+async fn measure_qpps_async(motor_index: Motor, duration_ms: Option<u32>) -> Result<serde_json::Value, String> {
     let dur = duration_ms.unwrap_or(2000);
     tauri::async_runtime::spawn_blocking(move || device::measure_qpps_sync(motor_index, dur)).await.map_err(|e| format!("Join error: {}", e))?
 }
@@ -701,6 +1072,30 @@ async fn measure_qpps_async(motor_index: u8, duration_ms: Option<u32>) -> Result
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            use tauri::Manager;
+            match app.path().app_config_dir() {
+                Ok(dir) => {
+                    settings::init_settings_dir(dir);
+                    // Connect before the UI asks for the connection state
+                    match settings::auto_connect_sync() {
+                        Ok(Some(name)) => println!("[SETTINGS] auto-connected profile \"{}\"", name),
+                        Ok(None) => {}
+                        Err(e) => eprintln!("[SETTINGS] auto-connect failed: {}", e),
+                    }
+                }
+                Err(e) => eprintln!("[SETTINGS] no app config dir, settings disabled: {}", e),
+            }
+            Ok(())
+        })
+        .on_window_event(|_window, event| {
+            // Never leave motors running when the UI goes away
+            if let tauri::WindowEvent::CloseRequested { .. } = event {
+                if let Err(e) = estop::stop_all_motors() {
+                    eprintln!("[ESTOP] Failed to stop motors on window close: {}", e);
+                }
+            }
+        })
         .invoke_handler(tauri::generate_handler![ // Register functions invoked from the frontend
             drive_simply_async,
             drive_pwm_async,
@@ -713,6 +1108,20 @@ pub fn run() {
             reset_encoder_async,
             configure_baud,
             configure_port,
+            configure_address,
+            scan_addresses,
+            get_connection_info,
+            get_settings,
+            save_profile,
+            save_current_profile,
+            build_sim_twin,
+            load_profile,
+            delete_profile,
+            set_auto_connect,
+            backup_device_config,
+            restore_device_config,
+            diff_device_config,
+            apply_device_config_fields,
             list_serial_ports,
             set_simulation_mode,
             set_sim_params,
@@ -721,12 +1130,60 @@ pub fn run() {
             fit_frf_async,
             read_position_pid_async,
             set_position_pid_async,
+            drive_position_async,
             read_velocity_pid_async,
             set_velocity_pid_async,
             run_pwm_step_response_async,
             autotune_velocity_step_async,
             measure_qpps_async,
+            set_traffic_logging,
+            get_traffic_log,
+            clear_traffic_log,
+            export_traffic_log,
+            emergency_stop,
+            clear_estop,
+            is_estop_latched,
+            get_safety_config,
+            set_safety_config,
+            get_safety_events,
+            clear_safety_events,
+            start_fault_monitor,
+            stop_fault_monitor,
+            get_monitor_status,
+            set_monitor_config,
+            clear_motor_fault,
+            set_sim_fault,
+            set_sim_plant,
+            set_sim_nonlinearities,
+            set_sim_fault_injection,
+            set_sim_disturbances,
+            get_sim_disturbance_trace,
+            set_sim_velocity_loop,
+            configure_sim_bus,
+            get_sim_bus_snapshot,
+            set_sim_bus_fault,
+            save_sim_snapshot,
+            restore_sim_snapshot,
+            reset_sim,
+            run_sim_scenario,
+            set_sim_robot,
+            get_sim_pose,
+            get_sim_pose_events,
+            reset_sim_pose,
+            set_sim_battery,
+            get_sim_battery,
+            set_sim_thermal,
+            get_sim_thermal,
+            set_sim_clock,
+            step_sim,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            if let tauri::RunEvent::Exit = event {
+                if let Err(e) = estop::stop_all_motors() {
+                    eprintln!("[ESTOP] Failed to stop motors on exit: {}", e);
+                }
+            }
+        });
 }
@@ -0,0 +1,468 @@
+use once_cell::sync::Lazy;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use serde_json::Value as JsonValue;
+
+use crate::device;
+use crate::estop;
+use crate::motor::Motor;
+use crate::safety::MAX_DUTY;
+use crate::sim::{is_simulation_enabled, SimPacing, SimState, SIM_STATE};
+
+// Motor fault monitor: compares commanded duty with measured speed and current, and cuts a
+// motor that is stalled, running away or whose encoder counts backwards. The motor then
+// stays disabled until the trip is cleared.
+//
+// Samples come from the experiment loops, which already read the motor every step, and
+// from an optional background thread polling Read All Status (for manual driving).
+
+const MAX_EVENTS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultKind {
+    Stall,         // high duty, no motion, current not dropping (jammed wheel or dead encoder)
+    Runaway,       // faster than the duty command or QPPS allows
+    SignInversion, // speed consistently opposite to the duty (swapped encoder or motor leads)
+}
+
+// Thresholds for one motor. Duty and speed limits are fractions of full duty and of QPPS.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MonitorConfig {
+    pub qpps: u32, // speed at full duty; 0 = read from the controller when the monitor starts
+    pub stall_min_duty: f32,
+    pub stall_max_speed: f32,
+    pub stall_time_s: f32,
+    pub runaway_factor: f32, // allowed speed = factor * |duty| * qpps + margin * qpps, capped at factor * qpps
+    pub runaway_margin: f32,
+    pub runaway_time_s: f32,
+    pub inversion_min_duty: f32,
+    pub inversion_min_speed: f32,
+    pub inversion_time_s: f32,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        MonitorConfig {
+            qpps: 0,
+            stall_min_duty: 0.25,
+            stall_max_speed: 0.02,
+            stall_time_s: 0.5,
+            runaway_factor: 1.2,
+            runaway_margin: 0.1,
+            runaway_time_s: 0.3,
+            inversion_min_duty: 0.15,
+            inversion_min_speed: 0.05,
+            inversion_time_s: 0.3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sample {
+    pub t_s: f32,
+    pub duty: i16,
+    pub speed: i32,
+    pub current: Option<u32>, // 10 mA units; None when the caller only read the speed
+}
+
+// Per-motor detector state: how long each condition has held
+#[derive(Debug, Clone, Default)]
+pub struct FaultDetector {
+    stall_since: Option<f32>,
+    stall_current: Option<u32>,
+    runaway_since: Option<f32>,
+    inversion_since: Option<f32>,
+}
+
+// True once `active` has held continuously for `hold_s`
+fn held(since: &mut Option<f32>, active: bool, t_s: f32, hold_s: f32) -> bool {
+    if !active {
+        *since = None;
+        return false;
+    }
+    let start = *since.get_or_insert(t_s);
+    t_s - start >= hold_s
+}
+
+impl FaultDetector {
+    pub fn update(&mut self, cfg: &MonitorConfig, s: &Sample) -> Option<FaultKind> {
+        if cfg.qpps == 0 { return None; }
+        let qpps = cfg.qpps as f32;
+        let duty = s.duty as f32 / MAX_DUTY as f32;
+        let speed = s.speed as f32;
+
+        // Stall: the current must not fall while the condition holds (a motor spinning up
+        // through a slow encoder sees its current drop; a locked rotor does not)
+        let mut stalled = duty.abs() >= cfg.stall_min_duty && speed.abs() <= cfg.stall_max_speed * qpps;
+        if let (Some(c), Some(c0)) = (s.current, self.stall_current) {
+            if stalled && (c as f32) < c0 as f32 * 0.9 { stalled = false; }
+        }
+        self.stall_current = if stalled { self.stall_current.or(s.current) } else { None };
+        let stall = held(&mut self.stall_since, stalled, s.t_s, cfg.stall_time_s);
+
+        let allowed = (cfg.runaway_factor * duty.abs() * qpps + cfg.runaway_margin * qpps).min(cfg.runaway_factor * qpps);
+        let runaway = held(&mut self.runaway_since, speed.abs() > allowed, s.t_s, cfg.runaway_time_s);
+
+        let inverted = duty.abs() >= cfg.inversion_min_duty
+            && speed.abs() >= cfg.inversion_min_speed * qpps
+            && duty.signum() != speed.signum();
+        let inversion = held(&mut self.inversion_since, inverted, s.t_s, cfg.inversion_time_s);
+
+        if stall { Some(FaultKind::Stall) }
+        else if runaway { Some(FaultKind::Runaway) }
+        else if inversion { Some(FaultKind::SignInversion) }
+        else { None }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaultTrip {
+    pub timestamp_ms: u64,
+    pub motor_index: Motor,
+    pub kind: FaultKind,
+    pub duty: i16,
+    pub speed: i32,
+    pub current: Option<u32>,
+    pub message: String,
+}
+
+pub struct MonitorState {
+    pub config: [MonitorConfig; 2],
+    qpps: [u32; 2], // config.qpps, or the value read from the controller when that is 0
+    detectors: [FaultDetector; 2],
+    pub trips: [Option<FaultTrip>; 2],
+    pub events: VecDeque<FaultTrip>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitorStatus {
+    pub enabled: bool,
+    pub polling: bool,
+    pub config: [MonitorConfig; 2],
+    pub qpps: [u32; 2],
+    pub trips: [Option<FaultTrip>; 2],
+    pub events: Vec<FaultTrip>,
+}
+
+pub static MONITOR_ENABLED: AtomicBool = AtomicBool::new(false);
+pub static MONITOR: Lazy<Mutex<MonitorState>> = Lazy::new(|| Mutex::new(MonitorState {
+    config: [MonitorConfig::default(), MonitorConfig::default()],
+    qpps: [0, 0],
+    detectors: [FaultDetector::default(), FaultDetector::default()],
+    trips: [None, None],
+    events: VecDeque::new(),
+}));
+type Poller = (Arc<AtomicBool>, JoinHandle<()>); // stop flag, thread
+static POLLER: Lazy<Mutex<Option<Poller>>> = Lazy::new(|| Mutex::new(None));
+static EPOCH: Lazy<Instant> = Lazy::new(Instant::now);
+
+fn lock_monitor() -> Result<std::sync::MutexGuard<'static, MonitorState>, String> {
+    MONITOR.lock().map_err(|e| format!("Failed to lock fault monitor: {}", e))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+// Duty equivalent of a 7-bit speed command (0..127, 64 = stop)
+pub fn speed_cmd_duty(speed: u8) -> i16 {
+    (((speed.min(127) as f32 - 64.0) / 63.0) * MAX_DUTY as f32).round() as i16
+}
+
+// Returns an error while the e-stop is latched or the motor has a fault trip;
+// call before any non-zero drive command
+pub fn ensure_motor_enabled(motor: Motor) -> Result<(), String> {
+    estop::ensure_not_latched()?;
+    let state = lock_monitor()?;
+    match &state.trips[motor.index()] {
+        Some(trip) => Err(format!("{}; call clear_motor_fault before driving {}", trip.message, motor)),
+        None => Ok(()),
+    }
+}
+
+// Sample time for the detectors: simulated time when the simulator is not paced by the
+// wall clock (hold times would otherwise never elapse), wall time otherwise
+fn sim_clock_s(sim: &SimState) -> f32 {
+    match sim.clock.pacing {
+        SimPacing::FastAsPossible => sim.time_s as f32,
+        SimPacing::RealTime => EPOCH.elapsed().as_secs_f32(),
+    }
+}
+
+fn clock_s() -> Result<f32, String> {
+    if is_simulation_enabled() {
+        let sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+        return Ok(sim_clock_s(&sim));
+    }
+    Ok(EPOCH.elapsed().as_secs_f32())
+}
+
+// Feed one sample; returns the trip if this sample fired one. Does not touch the motor.
+fn observe(motor: Motor, t_s: f32, duty: i16, speed: i32, current: Option<u32>) -> Result<Option<FaultTrip>, String> {
+    if !MONITOR_ENABLED.load(Ordering::SeqCst) { return Ok(None); }
+    let i = motor.index();
+    let mut state = lock_monitor()?;
+    if state.trips[i].is_some() { return Ok(None); }
+    let mut cfg = state.config[i].clone();
+    cfg.qpps = state.qpps[i];
+    let sample = Sample { t_s, duty, speed, current };
+    let kind = match state.detectors[i].update(&cfg, &sample) {
+        Some(k) => k,
+        None => return Ok(None),
+    };
+    let what = match kind {
+        FaultKind::Stall => "stall",
+        FaultKind::Runaway => "runaway",
+        FaultKind::SignInversion => "encoder sign inversion",
+    };
+    let current_text = current.map(|c| format!(", current {}", c)).unwrap_or_default();
+    let message = format!("{} cut by fault monitor: {} (duty {}, speed {}{})", motor, what, duty, speed, current_text);
+    eprintln!("[MONITOR] {}", message);
+    let trip = FaultTrip { timestamp_ms: now_ms(), motor_index: motor, kind, duty, speed, current, message };
+    state.trips[i] = Some(trip.clone());
+    while state.events.len() >= MAX_EVENTS { state.events.pop_front(); }
+    state.events.push_back(trip.clone());
+    Ok(Some(trip))
+}
+
+// Check a sample from a loop that does not hold the sim/port locks; on a trip the motor is
+// driven to zero duty and the trip message is returned as the error
+pub fn check(motor: Motor, duty: i16, speed: i32, current: Option<u32>) -> Result<(), String> {
+    ensure_motor_enabled(motor)?;
+    match observe(motor, clock_s()?, duty, speed, current)? {
+        Some(trip) => {
+            if let Err(e) = device::drive_pwm_sync(0, motor) {
+                eprintln!("[MONITOR] failed to stop {}: {}", motor, e);
+            }
+            Err(trip.message)
+        }
+        None => Ok(()),
+    }
+}
+
+// Same check for loops that drive the sim under its lock: the motor is stopped in place
+pub fn check_sim(sim: &mut SimState, motor: Motor) -> Result<(), String> {
+    // latched or tripped elsewhere while the loop was driving it
+    if let Err(e) = ensure_motor_enabled(motor) {
+        sim[motor].stop();
+        return Err(e);
+    }
+    let t_s = sim_clock_s(sim);
+    let duty = (sim[motor].u * MAX_DUTY as f32).round() as i16;
+    let speed = sim.measured_speed(motor);
+    let current = sim.measured_current(motor);
+    match observe(motor, t_s, duty, speed, Some(current))? {
+        Some(trip) => {
+            sim[motor].stop();
+            Err(trip.message)
+        }
+        None => Ok(()),
+    }
+}
+
+// Check one motor from a Read All Status result
+pub fn check_status(motor: Motor, status: &JsonValue) -> Result<(), String> {
+    let field = |name: &str| status.get(format!("m{}_{}", motor.number(), name)).and_then(|x| x.as_i64());
+    let duty = field("pwm").unwrap_or(0) as i16;
+    let speed = field("speed").unwrap_or(0) as i32;
+    let current = field("current").map(|c| c.unsigned_abs() as u32);
+    check(motor, duty, speed, current)
+}
+
+// One background poll: read status once and check both motors that are not already tripped
+pub fn poll_once() -> Result<(), String> {
+    let status = device::read_all_status_sync()?;
+    for motor in Motor::ALL {
+        let tripped = lock_monitor()?.trips[motor.index()].is_some();
+        if tripped { continue; }
+        // a trip here has already stopped the motor and been recorded
+        if let Err(e) = check_status(motor, &status) {
+            if !e.contains("fault monitor") { return Err(e); }
+        }
+    }
+    Ok(())
+}
+
+// Full-duty speed used when the config leaves qpps at 0
+fn resolve_qpps(motor: Motor) -> Result<u32, String> {
+    if is_simulation_enabled() {
+        let sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+        return Ok(sim[motor].full_scale_pps().abs().round() as u32);
+    }
+    Ok(device::read_velocity_pid_sync(motor)?.qpps.unsigned_abs())
+}
+
+// Enable the monitor. With `poll_interval_ms` > 0 a background thread also polls the
+// controller; with 0 only the experiment loops feed it.
+pub fn start_fault_monitor_sync(poll_interval_ms: u32) -> Result<(), String> {
+    stop_fault_monitor_sync()?;
+    let mut qpps = [0u32; 2];
+    for motor in Motor::ALL {
+        let i = motor.index();
+        let configured = lock_monitor()?.config[i].qpps;
+        qpps[i] = if configured > 0 { configured } else { resolve_qpps(motor)? };
+    }
+    {
+        let mut state = lock_monitor()?;
+        state.qpps = qpps;
+        state.detectors = [FaultDetector::default(), FaultDetector::default()];
+    }
+    MONITOR_ENABLED.store(true, Ordering::SeqCst);
+    println!("[MONITOR] started (qpps {:?}, poll {} ms)", qpps, poll_interval_ms);
+
+    if poll_interval_ms > 0 {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+        let interval = Duration::from_millis(poll_interval_ms as u64);
+        let handle = std::thread::spawn(move || {
+            while !stop_flag.load(Ordering::SeqCst) {
+                if let Err(e) = poll_once() { eprintln!("[MONITOR] poll failed: {}", e); }
+                std::thread::sleep(interval);
+            }
+        });
+        *POLLER.lock().map_err(|e| format!("Failed to lock poller: {}", e))? = Some((stop, handle));
+    }
+    Ok(())
+}
+
+pub fn stop_fault_monitor_sync() -> Result<(), String> {
+    MONITOR_ENABLED.store(false, Ordering::SeqCst);
+    let poller = POLLER.lock().map_err(|e| format!("Failed to lock poller: {}", e))?.take();
+    if let Some((stop, handle)) = poller {
+        stop.store(true, Ordering::SeqCst);
+        let _ = handle.join();
+        println!("[MONITOR] stopped");
+    }
+    Ok(())
+}
+
+pub fn get_monitor_status_sync() -> Result<MonitorStatus, String> {
+    let polling = POLLER.lock().map_err(|e| format!("Failed to lock poller: {}", e))?.is_some();
+    let state = lock_monitor()?;
+    Ok(MonitorStatus {
+        enabled: MONITOR_ENABLED.load(Ordering::SeqCst),
+        polling,
+        config: state.config.clone(),
+        qpps: state.qpps,
+        trips: state.trips.clone(),
+        events: state.events.iter().cloned().collect(),
+    })
+}
+
+pub fn set_monitor_config_sync(motor: Motor, config: MonitorConfig) -> Result<(), String> {
+    let i = motor.index();
+    let mut state = lock_monitor()?;
+    println!("[MONITOR] {} config: {:?}", motor, config);
+    if config.qpps > 0 { state.qpps[i] = config.qpps; }
+    state.config[i] = config;
+    state.detectors[i] = FaultDetector::default();
+    Ok(())
+}
+
+// Re-enable a motor after a trip. The detector restarts from scratch.
+pub fn clear_motor_fault_sync(motor: Motor) -> Result<(), String> {
+    let i = motor.index();
+    let mut state = lock_monitor()?;
+    state.trips[i] = None;
+    state.detectors[i] = FaultDetector::default();
+    println!("[MONITOR] {} fault cleared", motor);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{set_sim_fault_sync, set_simulation_mode_sync, SimFault};
+    use crate::sim::tests::TEST_MUTEX;
+
+    fn cfg() -> MonitorConfig {
+        MonitorConfig { qpps: 1000, ..Default::default() }
+    }
+
+    // Feed `speed`/`current` at `duty` every 50 ms for `secs`; returns the first fault
+    fn run(det: &mut FaultDetector, duty: i16, secs: f32, f: impl Fn(f32) -> (i32, Option<u32>)) -> Option<(f32, FaultKind)> {
+        let mut t = 0.0;
+        while t <= secs {
+            let (speed, current) = f(t);
+            if let Some(k) = det.update(&cfg(), &Sample { t_s: t, duty, speed, current }) { return Some((t, k)); }
+            t += 0.05;
+        }
+        None
+    }
+
+    #[test]
+    fn detects_each_fault_after_hold_time() {
+        let half = MAX_DUTY / 2;
+        // jammed: no motion, current steady
+        let (t, k) = run(&mut FaultDetector::default(), half, 2.0, |_| (0, Some(800))).unwrap();
+        assert_eq!(k, FaultKind::Stall);
+        assert!((0.5..0.6).contains(&t), "{}", t);
+        // full speed with zero command
+        assert_eq!(run(&mut FaultDetector::default(), 0, 2.0, |_| (1000, None)).unwrap().1, FaultKind::Runaway);
+        // beyond QPPS at full duty
+        assert_eq!(run(&mut FaultDetector::default(), MAX_DUTY, 2.0, |_| (1300, None)).unwrap().1, FaultKind::Runaway);
+        // speed opposite to duty
+        assert_eq!(run(&mut FaultDetector::default(), half, 2.0, |_| (-450, None)).unwrap().1, FaultKind::SignInversion);
+    }
+
+    #[test]
+    fn healthy_motion_does_not_trip() {
+        // first-order spin-up to the commanded speed, current falling as it accelerates
+        let spin_up = |t: f32| {
+            let v = 500.0 * (1.0 - (-t / 0.2).exp());
+            (v.round() as i32, Some((1000.0 - v) as u32))
+        };
+        assert!(run(&mut FaultDetector::default(), MAX_DUTY / 2, 3.0, spin_up).is_none());
+        // slow spin-up: no motion for a while, but the current drops as soon as it moves
+        let slow = |t: f32| if t < 0.3 { (0, Some(1000)) } else { (400, Some(500)) };
+        assert!(run(&mut FaultDetector::default(), MAX_DUTY / 2, 3.0, slow).is_none());
+        // monitor stays quiet until QPPS is known
+        let mut det = FaultDetector::default();
+        let unset = MonitorConfig::default();
+        assert!((0..40).all(|n| det.update(&unset, &Sample { t_s: n as f32 * 0.05, duty: MAX_DUTY, ..Default::default() }).is_none()));
+    }
+
+    #[test]
+    fn sim_stall_cuts_motor() {
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        set_simulation_mode_sync(true).unwrap();
+        set_sim_fault_sync(Motor::M1, SimFault::Stall).unwrap();
+        start_fault_monitor_sync(0).unwrap();
+        device::drive_pwm_sync(20000, Motor::M1).unwrap();
+
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(2) {
+            poll_once().unwrap();
+            if get_monitor_status_sync().unwrap().trips[0].is_some() { break; }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        let status = get_monitor_status_sync().unwrap();
+        let trip = status.trips[0].clone().expect("stall detected");
+        assert_eq!(trip.kind, FaultKind::Stall);
+        assert!(status.trips[1].is_none());
+        assert_eq!(SIM_STATE.lock().unwrap()[Motor::M1].pwm, 0);
+        // M1 stays disabled until the trip is cleared; M2 is unaffected
+        assert!(device::drive_pwm_sync(20000, Motor::M1).is_err());
+        assert!(device::drive_pwm_sync(1000, Motor::M2).is_ok());
+
+        // a qpps run into the same stall is cut and leaves the motor stopped
+        clear_motor_fault_sync(Motor::M1).unwrap();
+        assert!(crate::sim::tests::with_fast_clock(|| device::measure_qpps_sync(Motor::M1, 3000)).is_err());
+        assert!(get_monitor_status_sync().unwrap().trips[0].is_some());
+        assert_eq!(SIM_STATE.lock().unwrap()[Motor::M1].pwm, 0);
+
+        stop_fault_monitor_sync().unwrap();
+        clear_motor_fault_sync(Motor::M1).unwrap();
+        set_sim_fault_sync(Motor::M1, SimFault::None).unwrap();
+        assert!(device::drive_pwm_sync(1000, Motor::M1).is_ok());
+        device::drive_pwm_sync(0, Motor::M1).unwrap();
+        device::drive_pwm_sync(0, Motor::M2).unwrap();
+        set_simulation_mode_sync(false).unwrap();
+    }
+}
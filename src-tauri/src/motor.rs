@@ -0,0 +1,141 @@
+use std::fmt;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// Motor channel selector. It crosses the Tauri boundary as the channel number (1 or 2,
+// "M1"/"M2" are accepted too), so an out-of-range index is rejected while the command
+// arguments are decoded instead of falling through to M2.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Motor {
+    M1,
+    M2,
+}
+
+impl Motor {
+    pub const ALL: [Motor; 2] = [Motor::M1, Motor::M2];
+
+    // Slot in per-motor arrays
+    pub fn index(self) -> usize {
+        match self {
+            Motor::M1 => 0,
+            Motor::M2 => 1,
+        }
+    }
+
+    // Channel number as used by the protocol docs and the frontend
+    pub fn number(self) -> u8 {
+        self.index() as u8 + 1
+    }
+
+    // Choose between the M1 and M2 variant of a value (e.g. a pair of command codes)
+    pub fn pick<T>(self, m1: T, m2: T) -> T {
+        match self {
+            Motor::M1 => m1,
+            Motor::M2 => m2,
+        }
+    }
+}
+
+impl TryFrom<i64> for Motor {
+    type Error = String;
+
+    fn try_from(n: i64) -> Result<Self, String> {
+        match n {
+            1 => Ok(Motor::M1),
+            2 => Ok(Motor::M2),
+            _ => Err(format!("Invalid motor index: {} (expected 1 or 2)", n)),
+        }
+    }
+}
+
+impl fmt::Display for Motor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "M{}", self.number())
+    }
+}
+
+// Number (1, 2) or name ("M1", "m2", "both") as sent by the frontend
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Repr {
+    Number(i64),
+    Name(String),
+}
+
+fn parse_name(name: &str) -> Option<MotorSelect> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "1" | "m1" => Some(MotorSelect::One(Motor::M1)),
+        "2" | "m2" => Some(MotorSelect::One(Motor::M2)),
+        "both" | "m1m2" => Some(MotorSelect::Both),
+        _ => None,
+    }
+}
+
+impl Serialize for Motor {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u8(self.number())
+    }
+}
+
+impl<'de> Deserialize<'de> for Motor {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        match Repr::deserialize(d)? {
+            Repr::Number(n) => Motor::try_from(n).map_err(serde::de::Error::custom),
+            Repr::Name(name) => match parse_name(&name) {
+                Some(MotorSelect::One(m)) => Ok(m),
+                _ => Err(serde::de::Error::custom(format!("Invalid motor \"{}\" (expected 1, 2, \"M1\" or \"M2\")", name))),
+            },
+        }
+    }
+}
+
+// One motor or both, for the commands that have a combined M1/M2 form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotorSelect {
+    One(Motor),
+    Both,
+}
+
+impl From<Motor> for MotorSelect {
+    fn from(m: Motor) -> Self {
+        MotorSelect::One(m)
+    }
+}
+
+impl Serialize for MotorSelect {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self {
+            MotorSelect::One(m) => m.serialize(s),
+            MotorSelect::Both => s.serialize_str("both"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MotorSelect {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        match Repr::deserialize(d)? {
+            Repr::Number(n) => Motor::try_from(n).map(MotorSelect::One).map_err(serde::de::Error::custom),
+            Repr::Name(name) => parse_name(&name)
+                .ok_or_else(|| serde::de::Error::custom(format!("Invalid motor \"{}\" (expected 1, 2, \"M1\", \"M2\" or \"both\")", name))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_and_rejects_motor_indices() {
+        assert_eq!(serde_json::from_str::<Motor>("1").unwrap(), Motor::M1);
+        assert_eq!(serde_json::from_str::<Motor>("\"M2\"").unwrap(), Motor::M2);
+        for bad in ["0", "3", "7", "-1", "\"both\"", "\"M3\""] {
+            assert!(serde_json::from_str::<Motor>(bad).is_err(), "{} accepted", bad);
+        }
+        assert_eq!(serde_json::to_string(&Motor::M2).unwrap(), "2");
+
+        assert_eq!(serde_json::from_str::<MotorSelect>("\"both\"").unwrap(), MotorSelect::Both);
+        assert_eq!(serde_json::from_str::<MotorSelect>("1").unwrap(), MotorSelect::One(Motor::M1));
+        assert!(serde_json::from_str::<MotorSelect>("3").is_err());
+    }
+}
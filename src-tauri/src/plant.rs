@@ -0,0 +1,343 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use crate::disturbance::Load;
+use serde::{Serialize, Deserialize};
+
+// Plant models for the simulator. The linear models use the motor's steady-state `gain`
+// (and `tau` where they have a first-order stage); the DC motor carries its own parameters.
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum PlantModel {
+    #[default]
+    FirstOrder,
+    // first-order lag behind a pure input delay
+    FirstOrderDeadTime {
+        #[serde(alias = "deadTime", alias = "dead_time")]
+        dead_time_s: f32,
+    },
+    // gain * wn² / (s² + 2ζ wn s + wn²); underdamped for ζ < 1
+    SecondOrder {
+        #[serde(alias = "damping")]
+        zeta: f32,
+        #[serde(alias = "omegaN", alias = "wn")]
+        omega_n: f32, // rad/s
+    },
+    // two cascaded lags, `tau` then `tau2`
+    TwoStageLag {
+        #[serde(alias = "tau2S")]
+        tau2: f32,
+    },
+    DcMotor(DcMotorParams),
+}
+
+impl PlantModel {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            PlantModel::FirstOrder => Ok(()),
+            PlantModel::FirstOrderDeadTime { dead_time_s } => {
+                if !dead_time_s.is_finite() || !(0.0..=MAX_DEAD_TIME_S).contains(dead_time_s) {
+                    return Err(format!("dead_time_s must be within 0..{} s (got {})", MAX_DEAD_TIME_S, dead_time_s));
+                }
+                Ok(())
+            }
+            PlantModel::SecondOrder { zeta, omega_n } => {
+                if !zeta.is_finite() || *zeta < 0.0 { return Err(format!("zeta must be >= 0 (got {})", zeta)); }
+                if !omega_n.is_finite() || *omega_n <= 0.0 || *omega_n > MAX_OMEGA_N {
+                    return Err(format!("omega_n must be within 0..{} rad/s (got {})", MAX_OMEGA_N, omega_n));
+                }
+                Ok(())
+            }
+            PlantModel::TwoStageLag { tau2 } => {
+                if !tau2.is_finite() || *tau2 <= 0.0 { return Err(format!("tau2 must be > 0 (got {})", tau2)); }
+                Ok(())
+            }
+            PlantModel::DcMotor(p) => p.validate(),
+        }
+    }
+}
+
+// Longest supported dead time (sets the size of the input delay line)
+pub const MAX_DEAD_TIME_S: f32 = 2.0;
+// Fastest second-order mode, so the 1 ms integration step stays well inside stability
+pub const MAX_OMEGA_N: f32 = 200.0;
+
+// Internal plant state beyond the velocity kept in `MotorSim::vel`
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlantState {
+    pub current_a: f32,     // armature current (DC motor model)
+    pub x1: f32,            // second state: rate of change (second order) or first stage (two-stage lag)
+    pub delay: VecDeque<f32>, // delayed inputs (dead time)
+    pub u_applied: f32,     // duty after the rate limit
+    pub motor_pos: f64,     // motor and output shaft positions (counts), for backlash
+    pub output_pos: f64,
+    pub output_vel: f32,
+    #[serde(default)]
+    pub u_drive: f32,       // duty scaled by the supply voltage (what the bridge applies)
+}
+
+// Per-motor nonlinearities, applied around any plant model. Friction is expressed as the
+// duty it takes to overcome it, so it works the same for every model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Nonlinearities {
+    pub deadband: f32,         // duty (0..1) below which the motor gets no drive
+    pub static_friction: f32,  // duty (0..1) needed to break a stopped motor loose
+    pub coulomb_friction: f32, // duty (0..1) lost to constant friction while moving
+    pub backlash: f32,         // play between motor and output shaft (encoder counts)
+    pub rate_limit: f32,       // fastest change of the applied duty, full scale per second (0 = off)
+    pub forward_gain: f32,     // gain multipliers per direction
+    pub reverse_gain: f32,
+}
+
+impl Default for Nonlinearities {
+    fn default() -> Self {
+        Nonlinearities {
+            deadband: 0.0,
+            static_friction: 0.0,
+            coulomb_friction: 0.0,
+            backlash: 0.0,
+            rate_limit: 0.0,
+            forward_gain: 1.0,
+            reverse_gain: 1.0,
+        }
+    }
+}
+
+impl Nonlinearities {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, v) in [("deadband", self.deadband), ("static_friction", self.static_friction), ("coulomb_friction", self.coulomb_friction)] {
+            if !v.is_finite() || !(0.0..1.0).contains(&v) { return Err(format!("{} must be within 0..1 (got {})", name, v)); }
+        }
+        for (name, v) in [("backlash", self.backlash), ("rate_limit", self.rate_limit), ("forward_gain", self.forward_gain), ("reverse_gain", self.reverse_gain)] {
+            if !v.is_finite() || v < 0.0 { return Err(format!("{} must be >= 0 (got {})", name, v)); }
+        }
+        Ok(())
+    }
+
+    // Duty reaching the motor: slew-limited, minus the deadband, scaled per direction
+    pub fn shape_input(&self, state: &mut PlantState, u: f32, h: f32) -> f32 {
+        state.u_applied = if self.rate_limit > 0.0 {
+            let max_step = self.rate_limit * h;
+            state.u_applied + (u - state.u_applied).clamp(-max_step, max_step)
+        } else {
+            u
+        };
+        let u = state.u_applied;
+        let u = if u.abs() <= self.deadband { 0.0 } else { u - self.deadband * u.signum() };
+        if u >= 0.0 { u * self.forward_gain } else { u * self.reverse_gain }
+    }
+
+    // Static friction holds a stopped motor while the drive stays below the breakaway duty
+    pub fn holds(&self, vel: f32, u: f32) -> bool {
+        self.static_friction > 0.0 && vel == 0.0 && u.abs() <= self.static_friction
+    }
+
+    // Drive left after Coulomb friction, which opposes the motion
+    pub fn after_friction(&self, vel: f32, u: f32) -> f32 {
+        if vel == 0.0 { u } else { u - self.coulomb_friction * vel.signum() }
+    }
+
+    // Friction stops a motor that slows through zero unless the drive keeps it turning
+    pub fn stops(&self, vel_before: f32, vel_after: f32, u: f32) -> bool {
+        let friction = self.static_friction.max(self.coulomb_friction);
+        friction > 0.0 && vel_before != 0.0 && vel_before.signum() != vel_after.signum() && u.abs() <= friction
+    }
+
+    // Move the output shaft through the backlash; returns the output shaft speed
+    pub fn backlash_output(&self, state: &mut PlantState, vel: f32, h: f32) -> f32 {
+        if self.backlash <= 0.0 { return vel; }
+        let half = self.backlash as f64 / 2.0;
+        let before = state.output_pos;
+        state.motor_pos += vel as f64 * h as f64;
+        state.output_pos = state.output_pos.clamp(state.motor_pos - half, state.motor_pos + half);
+        ((state.output_pos - before) / h as f64) as f32
+    }
+}
+
+impl PlantState {
+    // Input `u` delayed by `dead_time_s`; zero until the delay line has filled
+    pub fn delayed(&mut self, u: f32, dead_time_s: f32, h: f32) -> f32 {
+        let n = (dead_time_s / h).round() as usize;
+        if n == 0 { return u; }
+        self.delay.push_back(u);
+        if self.delay.len() > n { self.delay.pop_front().unwrap_or(0.0) } else { 0.0 }
+    }
+}
+
+// One step of a first-order lag towards `target`
+pub fn lag(y: f32, target: f32, tau: f32, h: f32) -> f32 {
+    y + (h / tau).min(1.0) * (target - y)
+}
+
+// One semi-implicit Euler step of y'' = wn² (target - y) - 2ζ wn y' (y' kept in `state.x1`)
+pub fn second_order(state: &mut PlantState, y: f32, target: f32, zeta: f32, omega_n: f32, h: f32) -> f32 {
+    let accel = omega_n * omega_n * (target - y) - 2.0 * zeta * omega_n * state.x1;
+    state.x1 += h * accel;
+    y + h * state.x1
+}
+
+// Brushed DC motor driven by the H-bridge: armature R-L circuit with back-EMF, rotor plus
+// reflected load inertia and viscous friction. The encoder sits on the motor shaft.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DcMotorParams {
+    pub resistance_ohm: f32,
+    pub inductance_h: f32,
+    pub torque_constant: f32,   // N·m/A
+    pub back_emf_constant: f32, // V·s/rad
+    pub rotor_inertia: f32,     // kg·m²
+    pub load_inertia: f32,      // kg·m², at the output shaft
+    pub viscous_friction: f32,  // N·m·s/rad, at the motor shaft
+    pub supply_v: f32,
+    pub gear_ratio: f32,  // motor revolutions per output revolution
+    pub encoder_cpr: f32, // counts per motor revolution
+}
+
+impl Default for DcMotorParams {
+    // A small 12 V gearmotor
+    fn default() -> Self {
+        DcMotorParams {
+            resistance_ohm: 1.0,
+            inductance_h: 0.5e-3,
+            torque_constant: 0.02,
+            back_emf_constant: 0.02,
+            rotor_inertia: 1.0e-5,
+            load_inertia: 0.0,
+            viscous_friction: 1.0e-5,
+            supply_v: 12.0,
+            gear_ratio: 1.0,
+            encoder_cpr: 48.0,
+        }
+    }
+}
+
+impl DcMotorParams {
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = [
+            ("resistance_ohm", self.resistance_ohm),
+            ("torque_constant", self.torque_constant),
+            ("back_emf_constant", self.back_emf_constant),
+            ("rotor_inertia", self.rotor_inertia),
+            ("supply_v", self.supply_v),
+            ("gear_ratio", self.gear_ratio),
+            ("encoder_cpr", self.encoder_cpr),
+        ];
+        for (name, v) in positive {
+            if !v.is_finite() || v <= 0.0 { return Err(format!("{} must be > 0 (got {})", name, v)); }
+        }
+        for (name, v) in [("inductance_h", self.inductance_h), ("load_inertia", self.load_inertia), ("viscous_friction", self.viscous_friction)] {
+            if !v.is_finite() || v < 0.0 { return Err(format!("{} must be >= 0 (got {})", name, v)); }
+        }
+        Ok(())
+    }
+
+    // Inertia seen by the motor shaft
+    pub fn inertia(&self) -> f32 {
+        self.rotor_inertia + self.load_inertia / (self.gear_ratio * self.gear_ratio)
+    }
+
+    fn counts_per_rad(&self) -> f32 {
+        self.encoder_cpr / (2.0 * PI)
+    }
+
+    // No-load encoder speed at full duty (pps)
+    pub fn full_scale_pps(&self) -> f32 {
+        let (r, kt, ke, b) = (self.resistance_ohm, self.torque_constant, self.back_emf_constant, self.viscous_friction);
+        self.supply_v * kt / (r * b + kt * ke) * self.counts_per_rad()
+    }
+
+    // Torque of the stalled motor at full duty (N·m)
+    fn stall_torque(&self) -> f32 {
+        self.torque_constant * self.supply_v / self.resistance_ohm
+    }
+
+    // Advance by `h` seconds at duty `u` (-1..1) against `load`. Returns the new encoder
+    // speed (pps) and armature current (A). The current is integrated exactly over the step
+    // (its time constant L/R is usually shorter than the step), the rotor implicitly. A
+    // positive `current_limit_a` clamps the current like the controller's current limiting.
+    pub fn step(&self, vel: f32, current_a: f32, u: f32, current_limit_a: f32, load: Load, h: f32) -> (f32, f32) {
+        let omega = vel / self.counts_per_rad();
+        let i_ss = (u * self.supply_v - self.back_emf_constant * omega) / self.resistance_ohm;
+        let decay = if self.inductance_h > 0.0 { (-h * self.resistance_ohm / self.inductance_h).exp() } else { 0.0 };
+        let mut i = i_ss + (current_a - i_ss) * decay;
+        if current_limit_a > 0.0 { i = i.clamp(-current_limit_a, current_limit_a); }
+        let j = self.inertia() * load.inertia_scale;
+        let torque = self.torque_constant * i - load.torque * self.stall_torque();
+        let omega = (omega + h / j * torque) / (1.0 + h * self.viscous_friction / j);
+        (omega * self.counts_per_rad(), i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(p: &DcMotorParams, u: f32, limit_a: f32, secs: f32, start: (f32, f32)) -> (f32, f32) {
+        let h = 0.001;
+        let mut s = start;
+        for _ in 0..(secs / h) as usize { s = p.step(s.0, s.1, u, limit_a, Load::default(), h); }
+        s
+    }
+
+    #[test]
+    fn linear_models_match_their_step_responses() {
+        let h = 0.001;
+        let steps = |secs: f32| (secs / h).round() as usize;
+        // dead time: nothing moves for the delay, then the usual first-order rise
+        let mut st = PlantState::default();
+        let mut y = 0.0;
+        let mut trace = Vec::new();
+        for _ in 0..steps(0.3) {
+            y = lag(y, 100.0 * st.delayed(1.0, 0.05, h), 0.1, h);
+            trace.push(y);
+        }
+        assert_eq!(trace[steps(0.05) - 1], 0.0);
+        assert!(trace[steps(0.05) + 1] > 0.0);
+        let expect = 100.0 * (1.0 - (-(0.15_f32) / 0.1).exp());
+        assert!((trace[steps(0.2) - 1] - expect).abs() < 1.5, "{} vs {}", trace[steps(0.2) - 1], expect);
+
+        // underdamped second order overshoots by exp(-ζπ/sqrt(1-ζ²))
+        let (zeta, wn) = (0.3_f32, 20.0_f32);
+        let mut st = PlantState::default();
+        let mut y = 0.0_f32;
+        let mut peak = 0.0_f32;
+        for _ in 0..steps(2.0) {
+            y = second_order(&mut st, y, 100.0, zeta, wn, h);
+            peak = peak.max(y);
+        }
+        let overshoot = (-zeta * PI / (1.0 - zeta * zeta).sqrt()).exp() * 100.0;
+        assert!((peak - 100.0 - overshoot).abs() < 1.5, "peak {} overshoot {}", peak, overshoot);
+        assert!((y - 100.0).abs() < 1.0);
+
+        // two-stage lag starts with zero slope
+        let (mut x1, mut y) = (0.0, 0.0);
+        for _ in 0..steps(0.01) { x1 = lag(x1, 100.0, 0.1, h); y = lag(y, x1, 0.1, h); }
+        assert!(y < 0.1 * x1);
+
+        assert!(PlantModel::SecondOrder { zeta: 0.5, omega_n: 1000.0 }.validate().is_err());
+        assert!(PlantModel::FirstOrderDeadTime { dead_time_s: -0.1 }.validate().is_err());
+    }
+
+    #[test]
+    fn dc_motor_speed_current_and_braking() {
+        let p = DcMotorParams::default();
+        // spins up to the no-load speed, current falls to the friction load
+        let (vel, i) = run(&p, 1.0, 0.0, 1.0, (0.0, 0.0));
+        assert!((vel - p.full_scale_pps()).abs() / p.full_scale_pps() < 0.01, "{} vs {}", vel, p.full_scale_pps());
+        assert!(i > 0.0 && i < 0.5, "{}", i);
+        // the first milliseconds draw close to stall current V/R
+        let (_, i0) = run(&p, 1.0, 0.0, 0.003, (0.0, 0.0));
+        assert!(i0 > 0.8 * p.supply_v / p.resistance_ohm, "{}", i0);
+        // current limit caps the current and slows the spin-up
+        let (v_lim, i_lim) = run(&p, 1.0, 2.0, 0.02, (0.0, 0.0));
+        let (v_free, _) = run(&p, 1.0, 0.0, 0.02, (0.0, 0.0));
+        assert!(i_lim <= 2.0 && v_lim < v_free);
+        // zero duty shorts the windings: negative (braking) current, stops faster than coasting
+        let (v_brake, i_brake) = run(&p, 0.0, 0.0, 0.005, (vel, i));
+        assert!(i_brake < 0.0 && v_brake < vel);
+        // heavier load (reflected through the gearbox) spins up more slowly
+        let geared = DcMotorParams { load_inertia: 1.0e-3, gear_ratio: 5.0, ..p.clone() };
+        assert!(run(&geared, 1.0, 0.0, 0.02, (0.0, 0.0)).0 < v_free);
+    }
+}
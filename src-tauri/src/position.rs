@@ -0,0 +1,125 @@
+use serde::{Serialize, Deserialize};
+
+use crate::device::PositionPidParams;
+
+// Position moves for the simulator: a trapezoidal profile, as generated by the controller
+// for its speed/accel/decel position commands, tracked by the stored position PID.
+
+// Drive to `position` (encoder counts). Speed in counts/s, accel and decel in counts/s²;
+// a zero speed jumps the setpoint straight to the target, a zero accel or decel is unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PositionCommand {
+    pub accel: u32,
+    pub speed: u32,
+    pub decel: u32,
+    pub position: i32,
+}
+
+// A position command in progress
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PositionMove {
+    pub target: f64,       // commanded position after the min/max limits
+    pub setpoint: f64,     // profile position (counts)
+    pub setpoint_vel: f64, // profile speed (counts/s)
+    command: PositionCommand,
+    integral: f64,
+    last_err: f64,
+}
+
+// Move `v` toward `target` by at most `step`
+fn approach(v: f64, target: f64, step: f64) -> f64 {
+    v + (target - v).clamp(-step, step)
+}
+
+impl PositionMove {
+    // Start from the current encoder position; the target is held within the PID's
+    // min/max position limits when they are set
+    pub fn new(command: PositionCommand, position: i64, pid: &PositionPidParams) -> Self {
+        let mut target = command.position as f64;
+        if pid.min < pid.max { target = target.clamp(pid.min as f64, pid.max as f64); }
+        PositionMove { target, setpoint: position as f64, setpoint_vel: 0.0, command, integral: 0.0, last_err: 0.0 }
+    }
+
+    fn advance_profile(&mut self, h: f64) {
+        let c = &self.command;
+        if c.speed == 0 {
+            self.setpoint = self.target;
+            self.setpoint_vel = 0.0;
+            return;
+        }
+        let rate = |r: u32| if r > 0 { r as f64 } else { f64::INFINITY };
+        let (accel, decel, speed) = (rate(c.accel), rate(c.decel), c.speed as f64);
+        let remaining = self.target - self.setpoint;
+        let dir = remaining.signum();
+        let v = self.setpoint_vel;
+        let stopping = if decel.is_finite() { v * v / (2.0 * decel) } else { 0.0 };
+        self.setpoint_vel = if v * dir < 0.0 || remaining.abs() <= stopping {
+            // moving away from the target, or time to brake
+            approach(v, 0.0, decel * h)
+        } else {
+            approach(v, dir * speed, accel * h)
+        };
+        if remaining.abs() <= (self.setpoint_vel * h).abs() || (remaining.abs() < 0.5 && self.setpoint_vel == 0.0) {
+            self.setpoint = self.target;
+            self.setpoint_vel = 0.0;
+        } else {
+            self.setpoint += self.setpoint_vel * h;
+        }
+    }
+
+    // Position error (setpoint - position), as reported in Read All Status
+    pub fn error(&self, position: i64) -> f64 {
+        self.setpoint - position as f64
+    }
+
+    // True once the profile has reached the target
+    pub fn profile_done(&self) -> bool {
+        self.setpoint == self.target && self.setpoint_vel == 0.0
+    }
+
+    // Advance the profile and return the position PID output (duty, -1..1). The gains are
+    // 16.16 fixed point and act on counts, with 32767 = full duty. Within the deadzone the
+    // output is zero and the integrator holds; MaxI bounds the integral term.
+    pub fn control(&mut self, pid: &PositionPidParams, position: i64, h: f64) -> f32 {
+        self.advance_profile(h);
+        let err = self.error(position);
+        let deriv = (err - self.last_err) / h;
+        self.last_err = err;
+        if err.abs() <= pid.deadzone as f64 { return 0.0; }
+        let (p, i, d) = (pid.p as f64 / 65536.0, pid.i as f64 / 65536.0, pid.d as f64 / 65536.0);
+        self.integral += err * h;
+        if i > 0.0 {
+            let bound = pid.max_i.max(0) as f64 / i;
+            self.integral = self.integral.clamp(-bound, bound);
+        }
+        let out = p * err + i * self.integral + d * deriv;
+        (out / 32767.0).clamp(-1.0, 1.0) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_is_trapezoidal_and_limited() {
+        let pid = PositionPidParams { p: 0, i: 0, d: 0, max_i: 0, deadzone: 0, min: -1000, max: 5000 };
+        let cmd = PositionCommand { accel: 2000, speed: 1000, decel: 4000, position: 2000 };
+        let mut mv = PositionMove::new(cmd, 0, &pid);
+        let h = 0.001;
+        let mut peak: f64 = 0.0;
+        let mut t = 0.0;
+        while !mv.profile_done() && t < 10.0 {
+            mv.control(&pid, 0, h);
+            peak = peak.max(mv.setpoint_vel);
+            t += h;
+        }
+        // 0.5 s up (250 counts), 0.25 s down (125 counts), cruise for the remaining 1625
+        assert!((peak - 1000.0).abs() < 1e-6);
+        assert!((t - (0.5 + 0.25 + 1.625)).abs() < 0.01, "{}", t);
+        assert_eq!(mv.setpoint, 2000.0);
+        // targets beyond the limits are clamped
+        let mv = PositionMove::new(PositionCommand { position: 9000, ..cmd }, 0, &pid);
+        assert_eq!(mv.target, 5000.0);
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::traffic::{hex, ReplaySession, REPLAY_SESSION_VERSION};
+
+// Replay transport: feeds a recorded session back to the device layer and fails as soon
+// as a request differs from the capture. The session format lives in traffic.rs, which
+// exports it from the traffic log.
+
+pub fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = s.bytes().filter(|c| !c.is_ascii_whitespace()).collect();
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let text = std::str::from_utf8(pair).map_err(|_| format!("Invalid hex in \"{}\"", s))?;
+            if pair.len() != 2 {
+                return Err(format!("Odd number of hex digits in \"{}\"", s));
+            }
+            u8::from_str_radix(text, 16).map_err(|e| format!("Invalid hex \"{}\": {}", text, e))
+        })
+        .collect()
+}
+
+impl ReplaySession {
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let session: ReplaySession = serde_json::from_str(text).map_err(|e| format!("Invalid replay session: {}", e))?;
+        if session.version != REPLAY_SESSION_VERSION {
+            return Err(format!("Unsupported replay session version {} (expected {})", session.version, REPLAY_SESSION_VERSION));
+        }
+        Ok(session)
+    }
+}
+
+// Request/reply frame pairs still to be replayed
+type Frames = VecDeque<(Vec<u8>, Vec<u8>)>;
+
+// Shared view of the exchanges still to be replayed; stays readable after the transport
+// is boxed into ROBOCLAW.
+#[derive(Clone)]
+pub struct ReplayCursor(Arc<Mutex<Frames>>);
+
+impl ReplayCursor {
+    pub fn remaining(&self) -> usize {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    fn next(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).pop_front()
+    }
+}
+
+pub struct ReplayTransport {
+    exchanges: ReplayCursor,
+    pending_reply: Vec<u8>,
+    pub consumed: usize,
+    pub mismatch: Option<String>,
+}
+
+impl ReplayTransport {
+    pub fn new(session: &ReplaySession) -> Result<Self, String> {
+        let mut exchanges = Frames::new();
+        for (i, ex) in session.exchanges.iter().enumerate() {
+            let tx = parse_hex(&ex.tx).map_err(|e| format!("exchange {}: {}", i, e))?;
+            let rx = parse_hex(&ex.rx).map_err(|e| format!("exchange {}: {}", i, e))?;
+            exchanges.push_back((tx, rx));
+        }
+        let exchanges = ReplayCursor(Arc::new(Mutex::new(exchanges)));
+        Ok(ReplayTransport { exchanges, pending_reply: Vec::new(), consumed: 0, mismatch: None })
+    }
+
+    pub fn cursor(&self) -> ReplayCursor {
+        self.exchanges.clone()
+    }
+}
+
+impl Write for ReplayTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let (expected, reply) = match self.exchanges.next() {
+            Some(ex) => ex,
+            None => {
+                let msg = format!("replay: unexpected request {} after end of capture", hex(buf));
+                self.mismatch = Some(msg.clone());
+                return Err(io::Error::other(msg));
+            }
+        };
+        if expected != buf {
+            let msg = format!("replay: request {} differs from capture: expected {}, got {}", self.consumed, hex(&expected), hex(buf));
+            self.mismatch = Some(msg.clone());
+            return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        }
+        self.consumed += 1;
+        self.pending_reply = reply;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for ReplayTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // An empty recorded reply reads as a timeout, like the serial port with no data
+        let n = self.pending_reply.len().min(buf.len());
+        buf[..n].copy_from_slice(&self.pending_reply[..n]);
+        self.pending_reply.drain(..n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{self, ROBOCLAW};
+    use crate::motor::Motor;
+    use crate::sim::set_simulation_mode_sync;
+    use crate::sim::tests::TEST_MUTEX;
+
+    // Install the capture as the device transport, run `f`, then report how many
+    // exchanges it had and how many were left unplayed
+    fn with_replay<T>(capture: &str, f: impl FnOnce() -> T) -> (T, usize, usize) {
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        set_simulation_mode_sync(false).unwrap();
+        let session = ReplaySession::from_json(capture).expect("valid capture");
+        let transport = ReplayTransport::new(&session).expect("valid frames");
+        let cursor = transport.cursor();
+        let total = cursor.remaining();
+        {
+            let mut rc = ROBOCLAW.lock().unwrap_or_else(|e| e.into_inner());
+            let rc = rc.as_mut().expect("roboclaw initialized");
+            rc.addr = 0x80;
+            rc.port = Some(Box::new(transport));
+        }
+        let out = f();
+        ROBOCLAW.lock().unwrap_or_else(|e| e.into_inner()).as_mut().unwrap().port = None;
+        (out, total, cursor.remaining())
+    }
+
+    #[test]
+    fn replay_read_all_status() {
+        let (res, _, remaining) = with_replay(include_str!("../captures/read_all_status.json"), device::read_all_status_sync);
+        let v = res.expect("read_all_status decodes");
+        assert_eq!(remaining, 0);
+        assert_eq!(v["timertick"], 123456);
+        assert_eq!(v["temp1"], 285);
+        assert_eq!(v["main_batt"], 121);
+        assert_eq!(v["m1_pwm"], 16384);
+        assert_eq!(v["m2_pwm"], -8192);
+        assert_eq!(v["m1_current"], 150);
+        assert_eq!(v["m1_encoder"], 100000);
+        assert_eq!(v["m2_encoder"], -2500);
+        assert_eq!(v["m1_speed"], 4400);
+        assert_eq!(v["m2_speed"], -1200);
+    }
+
+    #[test]
+    fn replay_read_pids_and_speed() {
+        let (res, total, remaining) = with_replay(include_str!("../captures/read_pids.json"), || {
+            let pos = device::read_position_pid_sync(Motor::M1)?;
+            let vel = device::read_velocity_pid_sync(Motor::M2)?;
+            let speed = device::read_speed_sync(Motor::M1)?;
+            Ok::<_, String>((pos, vel, speed))
+        });
+        let (pos, vel, speed) = res.expect("decodes");
+        assert_eq!(total, 3);
+        assert_eq!(remaining, 0);
+        assert_eq!((pos.p, pos.i, pos.d), (0x00020000, 0x00001000, 0x00000800));
+        assert_eq!((pos.max_i, pos.deadzone, pos.min, pos.max), (5000, 10, -100000, 100000));
+        assert_eq!((vel.p, vel.i, vel.d, vel.qpps), (0x00018000, 0x00004000, 0, 44000));
+        assert_eq!(speed, -3000);
+    }
+
+    #[test]
+    fn replay_detects_request_mismatch() {
+        // Capture recorded for M1, test asks for M2
+        let (res, _, remaining) = with_replay(include_str!("../captures/read_pids.json"), || device::read_position_pid_sync(Motor::M2));
+        let err = res.expect_err("request mismatch must fail");
+        assert!(err.contains("differs from capture"), "{}", err);
+        assert!(remaining > 0);
+    }
+}
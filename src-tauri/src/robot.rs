@@ -0,0 +1,296 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+
+use crate::motor::Motor;
+use crate::settings::{self, DeviceProfile, WheelGeometry};
+use crate::sim::{sim_update, MotorSim, SimFault, SIM_STATE};
+
+// Differential-drive robot on top of the two simulated motors. Wheel speeds become a 2D
+// pose through the wheel geometry and slip, and the robot body can couple the wheels: its
+// mass and yaw inertia are shared by both drives. The pose is ground truth, to check
+// odometry and velocity commands against.
+
+const MAX_EVENTS: usize = 2000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RobotConfig {
+    pub left: Motor, // motor driving the left wheel; the other one drives the right
+    pub invert_left: bool, // positive speed drives that wheel backwards
+    pub invert_right: bool,
+    pub wheel: Option<WheelGeometry>,     // from the active profile when omitted
+    pub counts_per_rev: Option<[f32; 2]>, // encoder counts per wheel revolution, per motor (profile cpr * gear ratio when omitted)
+    pub slip: [f32; 2],       // fraction of the wheel's surface speed lost to slip (0..1), per motor
+    pub linear_inertia: f32,  // body inertia shared by the wheels when driving straight, in multiples of one drive's own (0 = off)
+    pub yaw_inertia: f32,     // the same when turning on the spot
+    pub pose_period_s: f64,   // sim time between recorded pose samples (polled with get_sim_pose_events)
+}
+
+impl Default for RobotConfig {
+    fn default() -> Self {
+        RobotConfig {
+            left: Motor::M1,
+            invert_left: false,
+            invert_right: false,
+            wheel: None,
+            counts_per_rev: None,
+            slip: [0.0, 0.0],
+            linear_inertia: 0.0,
+            yaw_inertia: 0.0,
+            pose_period_s: 0.02,
+        }
+    }
+}
+
+// Position (m) and heading (rad, counter-clockwise from +x, within -pi..pi)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Pose2D {
+    pub x: f64,
+    pub y: f64,
+    pub theta: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PoseEvent {
+    pub t_s: f64,
+    #[serde(flatten)]
+    pub pose: Pose2D,
+    pub v: f64,     // forward speed, m/s
+    pub omega: f64, // turn rate, rad/s
+    pub distance_m: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RobotSim {
+    pub config: RobotConfig,
+    pub wheel: WheelGeometry,
+    pub counts_per_rev: [f32; 2],
+    pub pose: Pose2D,
+    pub v: f64,
+    pub omega: f64,
+    pub distance_m: f64, // path length travelled
+    next_event_s: f64,
+    #[serde(skip)]
+    pub events: VecDeque<PoseEvent>,
+}
+
+fn wrap_angle(a: f64) -> f64 {
+    (a + PI).rem_euclid(2.0 * PI) - PI
+}
+
+impl RobotSim {
+    // Resolve the geometry left out of `config` from `profile` (defaults without one)
+    pub fn new(config: RobotConfig, profile: Option<&DeviceProfile>, time_s: f64) -> Result<RobotSim, String> {
+        let profile = profile.cloned().unwrap_or_default();
+        let wheel = config.wheel.clone().unwrap_or(profile.wheel);
+        let counts_per_rev = config.counts_per_rev.unwrap_or_else(|| {
+            [0, 1].map(|i| profile.encoder_cpr[i] as f32 * profile.gear_ratio[i])
+        });
+        let robot = RobotSim {
+            next_event_s: time_s + config.pose_period_s,
+            config,
+            wheel,
+            counts_per_rev,
+            pose: Pose2D::default(),
+            v: 0.0,
+            omega: 0.0,
+            distance_m: 0.0,
+            events: VecDeque::new(),
+        };
+        robot.validate()?;
+        Ok(robot)
+    }
+
+    // Check the resolved geometry and the config (also for robots restored from a snapshot)
+    pub fn validate(&self) -> Result<(), String> {
+        let (config, wheel, counts_per_rev) = (&self.config, &self.wheel, &self.counts_per_rev);
+        if !wheel.wheel_diameter_m.is_finite() || wheel.wheel_diameter_m <= 0.0 || !wheel.track_width_m.is_finite() || wheel.track_width_m <= 0.0 {
+            return Err("Wheel diameter and track width must be > 0".into());
+        }
+        if counts_per_rev.iter().any(|c| !c.is_finite() || *c <= 0.0) {
+            return Err(format!("counts_per_rev must be > 0 (got {:?})", counts_per_rev));
+        }
+        if config.slip.iter().any(|s| !s.is_finite() || !(0.0..1.0).contains(s)) {
+            return Err(format!("slip must be within 0..1 (got {:?})", config.slip));
+        }
+        for (name, v) in [("linear_inertia", config.linear_inertia), ("yaw_inertia", config.yaw_inertia)] {
+            if !v.is_finite() || v < 0.0 { return Err(format!("{} must be >= 0 (got {})", name, v)); }
+        }
+        if !config.pose_period_s.is_finite() || config.pose_period_s <= 0.0 {
+            return Err(format!("pose_period_s must be > 0 (got {})", config.pose_period_s));
+        }
+        Ok(())
+    }
+
+    // Left and right motor, with the sign that turns their speed into forward wheel speed
+    fn sides(&self) -> [(Motor, f64); 2] {
+        let sign = |inverted: bool| if inverted { -1.0 } else { 1.0 };
+        [
+            (self.config.left, sign(self.config.invert_left)),
+            (self.config.left.pick(Motor::M2, Motor::M1), sign(self.config.invert_right)),
+        ]
+    }
+
+    // Wheel revolutions per encoder count, signed forward
+    fn revs_per_count(&self, (m, sign): (Motor, f64)) -> f64 {
+        sign / self.counts_per_rev[m.index()] as f64
+    }
+
+    // Ground speed of the left and right wheel (m/s), after slip
+    pub fn wheel_speeds(&self, motors: &[MotorSim; 2]) -> [f64; 2] {
+        self.sides().map(|side| {
+            let m = side.0;
+            let surface = motors[m.index()].output_vel() as f64 * self.revs_per_count(side) * PI * self.wheel.wheel_diameter_m as f64;
+            surface * (1.0 - self.config.slip[m.index()] as f64)
+        })
+    }
+
+    // Share the body inertia between the drives: the common (straight) and differential
+    // (turning) parts of this step's wheel acceleration are slowed by the body's share.
+    // A locked wheel stays locked and the other drive carries the body alone.
+    fn couple(&self, motors: &mut [MotorSim; 2], before: [f32; 2]) {
+        let (linear, yaw) = (self.config.linear_inertia as f64, self.config.yaw_inertia as f64);
+        if (linear == 0.0 && yaw == 0.0) || motors.iter().any(|m| m.fault == SimFault::Stall) { return; }
+        let sides = self.sides();
+        let [dl, dr] = sides.map(|side| {
+            let i = side.0.index();
+            (motors[i].vel - before[i]) as f64 * self.revs_per_count(side)
+        });
+        let common = (dl + dr) / 2.0 / (1.0 + linear);
+        let diff = (dr - dl) / 2.0 / (1.0 + yaw);
+        for (side, d) in sides.into_iter().zip([common - diff, common + diff]) {
+            let i = side.0.index();
+            motors[i].vel = before[i] + (d / self.revs_per_count(side)) as f32;
+        }
+    }
+
+    // One sim step of `h` ending at `time_s`: couple the wheels, then integrate the pose
+    // along the arc driven during the step
+    pub fn step(&mut self, motors: &mut [MotorSim; 2], before: [f32; 2], h: f64, time_s: f64) {
+        self.couple(motors, before);
+        let [vl, vr] = self.wheel_speeds(motors);
+        self.v = (vl + vr) / 2.0;
+        self.omega = (vr - vl) / self.wheel.track_width_m as f64;
+        let heading = self.pose.theta + self.omega * h / 2.0;
+        self.pose.x += self.v * h * heading.cos();
+        self.pose.y += self.v * h * heading.sin();
+        self.pose.theta = wrap_angle(self.pose.theta + self.omega * h);
+        self.distance_m += self.v.abs() * h;
+        // edges get a little slack so whole clock steps land on the event times
+        if time_s + 1e-9 >= self.next_event_s {
+            while self.events.len() >= MAX_EVENTS { self.events.pop_front(); }
+            self.events.push_back(self.event(time_s));
+            self.next_event_s += self.config.pose_period_s;
+            if self.next_event_s <= time_s { self.next_event_s = time_s + self.config.pose_period_s; }
+        }
+    }
+
+    pub fn event(&self, t_s: f64) -> PoseEvent {
+        PoseEvent { t_s, pose: self.pose, v: self.v, omega: self.omega, distance_m: self.distance_m }
+    }
+
+    // Put the robot at `pose` and start a new event trace
+    pub fn place(&mut self, pose: Pose2D, time_s: f64) {
+        self.pose = Pose2D { theta: wrap_angle(pose.theta), ..pose };
+        self.distance_m = 0.0;
+        self.events.clear();
+        self.next_event_s = time_s + self.config.pose_period_s;
+    }
+}
+
+/// Enable the robot simulation with `config` (geometry from the active profile where it
+/// is left out), or disable it with `None`. The robot starts at the origin.
+pub fn set_sim_robot_sync(config: Option<RobotConfig>) -> Result<Option<RobotSim>, String> {
+    let robot = match config {
+        Some(config) => Some(RobotSim::new(config, settings::active_profile().as_ref(), 0.0)?),
+        None => None,
+    };
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    sim_update(&mut sim);
+    let now = sim.time_s;
+    sim.robot = robot.map(|mut r| {
+        r.place(Pose2D::default(), now);
+        r
+    });
+    match &sim.robot {
+        Some(r) => println!("[SIM] robot: {:?}, wheel {:?}, {:?} counts/rev", r.config, r.wheel, r.counts_per_rev),
+        None => println!("[SIM] robot: off"),
+    }
+    Ok(sim.robot.clone())
+}
+
+fn with_robot<T>(f: impl FnOnce(&mut RobotSim, f64) -> T) -> Result<T, String> {
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    sim_update(&mut sim);
+    let now = sim.time_s;
+    let robot = sim.robot.as_mut().ok_or("Robot simulation is not enabled")?;
+    Ok(f(robot, now))
+}
+
+/// Ground-truth pose of the simulated robot now.
+pub fn get_sim_pose_sync() -> Result<PoseEvent, String> {
+    with_robot(|robot, now| robot.event(now))
+}
+
+/// Pose samples recorded every `pose_period_s` of sim time (oldest first). They are not
+/// pushed to the frontend; poll this to follow the robot.
+pub fn get_sim_pose_events_sync() -> Result<Vec<PoseEvent>, String> {
+    with_robot(|robot, _| robot.events.iter().copied().collect())
+}
+
+/// Place the robot at `pose` (the origin when omitted) and clear its events.
+pub fn reset_sim_pose_sync(pose: Option<Pose2D>) -> Result<(), String> {
+    with_robot(|robot, now| robot.place(pose.unwrap_or_default(), now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{initial_sim_state, sim_advance};
+
+    #[test]
+    fn wheel_speeds_drive_the_pose() {
+        let mut sim = initial_sim_state();
+        let config = RobotConfig {
+            wheel: Some(WheelGeometry { wheel_diameter_m: 0.1, track_width_m: 0.3 }),
+            counts_per_rev: Some([100.0, 100.0]),
+            invert_right: true,
+            pose_period_s: 0.1,
+            ..Default::default()
+        };
+        sim.robot = Some(RobotSim::new(config.clone(), None, 0.0).unwrap());
+        assert!(RobotSim::new(RobotConfig { slip: [1.0, 0.0], ..config.clone() }, None, 0.0).is_err());
+
+        // both wheels forward (the right motor is mounted mirrored): straight along +x
+        for m in Motor::ALL { sim[m].mode_pwm = true; }
+        sim[Motor::M1].pwm = 32767;
+        sim[Motor::M2].pwm = -32767;
+        sim_advance(&mut sim, 2.0);
+        let robot = sim.robot.as_ref().unwrap();
+        let expected = 100.0 / 100.0 * PI * 0.1; // gain 100 pps -> 1 rev/s
+        assert!((robot.v - expected).abs() < 1e-3, "{}", robot.v);
+        assert!(robot.pose.y.abs() < 1e-9 && robot.pose.theta.abs() < 1e-9);
+        assert!((robot.pose.x - robot.distance_m).abs() < 1e-9);
+        assert_eq!(robot.events.len(), 20);
+        assert!((robot.events.back().unwrap().t_s - 2.0).abs() < 1e-6);
+
+        // both motors forward: the mirrored right wheel runs backwards and the robot spins clockwise
+        sim[Motor::M2].pwm = 32767;
+        sim_advance(&mut sim, 2.0);
+        let robot = sim.robot.as_ref().unwrap();
+        assert!((robot.omega + 2.0 * expected / 0.3).abs() < 1e-3, "{}", robot.omega);
+        assert!(robot.v.abs() < 1e-3);
+
+        // shared body inertia slows acceleration when driving straight, not the wheels' top speed
+        let mut coupled = initial_sim_state();
+        coupled.robot = Some(RobotSim::new(RobotConfig { linear_inertia: 1.0, ..config }, None, 0.0).unwrap());
+        for m in Motor::ALL { coupled[m].mode_pwm = true; }
+        coupled[Motor::M1].pwm = 32767;
+        coupled[Motor::M2].pwm = -32767;
+        sim_advance(&mut coupled, 0.1);
+        assert!((coupled[Motor::M1].vel - 100.0 * (1.0 - (-0.5f32).exp())).abs() < 1.0, "{}", coupled[Motor::M1].vel);
+        sim_advance(&mut coupled, 3.0);
+        assert!((coupled[Motor::M1].vel - 100.0).abs() < 1.0);
+    }
+}
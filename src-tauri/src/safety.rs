@@ -0,0 +1,287 @@
+use once_cell::sync::Lazy;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::motor::Motor;
+use crate::position::PositionCommand;
+
+// Per-motor safety envelope applied to every outgoing drive command (real device and sim).
+// Duty is the signed 16-bit PWM value (±32767 = 100%). 7-bit speed commands (0..127, 64 = stop)
+// are checked through their duty equivalent, plus their own `max_speed` limit. The slew limit
+// is not checked per command: it is sent along as the controller's duty acceleration, so the
+// applied duty ramps over time (see `duty_accel`). Position
+// moves are checked by their direction of travel, and their speed and acceleration
+// against the duty limits scaled to the motor's qpps.
+
+pub const MAX_DUTY: i16 = 32767;
+const MAX_EVENTS: usize = 500;
+// Fastest duty acceleration the controller accepts (full reverse to full forward in 100 ms)
+pub const MAX_DUTY_ACCEL: u32 = 655_359;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AllowedDirection {
+    Both,
+    ForwardOnly,
+    ReverseOnly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationPolicy {
+    Clamp,  // send the nearest allowed value
+    Reject, // refuse the command with an error
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MotorSafety {
+    pub max_duty: i16,              // |duty| limit, 0..32767
+    pub max_speed: u8,              // |speed - 64| limit for 7-bit speed commands, 0..63
+    pub max_slew_per_s: Option<f32>, // duty ramp rate in duty units per second (None = unlimited)
+    pub direction: AllowedDirection,
+    pub policy: ViolationPolicy,
+}
+
+impl Default for MotorSafety {
+    fn default() -> Self {
+        MotorSafety {
+            max_duty: MAX_DUTY,
+            max_speed: 63,
+            max_slew_per_s: None,
+            direction: AllowedDirection::Both,
+            policy: ViolationPolicy::Clamp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SafetyEvent {
+    pub timestamp_ms: u64,
+    pub motor_index: Motor,
+    pub limit: String, // "max_duty", "max_speed", "slew" or "direction"
+    pub requested: i32,
+    pub applied: Option<i32>, // None when rejected
+    pub message: String,
+}
+
+pub struct SafetyState {
+    pub motors: [MotorSafety; 2],
+    pub events: VecDeque<SafetyEvent>,
+}
+
+pub static SAFETY: Lazy<Mutex<SafetyState>> = Lazy::new(|| Mutex::new(SafetyState {
+    motors: [MotorSafety::default(), MotorSafety::default()],
+    events: VecDeque::new(),
+}));
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+impl SafetyState {
+    fn record(&mut self, motor: Motor, limit: &str, requested: i32, applied: Option<i32>) -> SafetyEvent {
+        let message = match applied {
+            Some(v) => format!("{} {} limit: clamped {} -> {}", motor, limit, requested, v),
+            None => format!("{} {} limit: rejected {}", motor, limit, requested),
+        };
+        eprintln!("[SAFETY] {}", message);
+        let ev = SafetyEvent { timestamp_ms: now_ms(), motor_index: motor, limit: limit.to_string(), requested, applied, message };
+        while self.events.len() >= MAX_EVENTS { self.events.pop_front(); }
+        self.events.push_back(ev.clone());
+        ev
+    }
+
+    // Apply one limit: `allowed` is the nearest permitted value for `value`
+    fn enforce(&mut self, motor: Motor, limit: &str, value: i32, allowed: i32) -> Result<i32, String> {
+        if allowed == value { return Ok(value); }
+        let policy = self.motors[motor.index()].policy;
+        match policy {
+            ViolationPolicy::Clamp => {
+                self.record(motor, limit, value, Some(allowed));
+                Ok(allowed)
+            }
+            ViolationPolicy::Reject => Err(self.record(motor, limit, value, None).message),
+        }
+    }
+
+    // Check a duty command against direction and max duty; returns the duty to send
+    pub fn check_duty(&mut self, motor: Motor, duty: i16) -> Result<i16, String> {
+        let cfg = self.motors[motor.index()].clone();
+        let mut d = duty.clamp(-MAX_DUTY, MAX_DUTY) as i32;
+
+        let dir_allowed = match cfg.direction {
+            AllowedDirection::Both => d,
+            AllowedDirection::ForwardOnly => d.max(0),
+            AllowedDirection::ReverseOnly => d.min(0),
+        };
+        d = self.enforce(motor, "direction", d, dir_allowed)?;
+
+        let max = cfg.max_duty.clamp(0, MAX_DUTY) as i32;
+        d = self.enforce(motor, "max_duty", d, d.clamp(-max, max))?;
+        Ok(d as i16)
+    }
+
+    // Check a 7-bit speed command (0..127, 64 = stop); returns the command to send
+    pub fn check_speed(&mut self, motor: Motor, speed: u8) -> Result<u8, String> {
+        let i = motor.index();
+        let max = self.motors[i].max_speed.min(63) as i32;
+        let rel = speed.min(127) as i32 - 64;
+        let rel = self.enforce(motor, "max_speed", rel, rel.clamp(-max, max))?;
+        // run the duty equivalent through the duty checks, then map back
+        let duty = ((rel as f32 / 63.0) * MAX_DUTY as f32).round() as i16;
+        let duty = self.check_duty(motor, duty)?;
+        let rel = ((duty as f32 / MAX_DUTY as f32) * 63.0).round() as i32;
+        Ok((64 + rel).clamp(0, 127) as u8)
+    }
+
+    // Check a position move (commands 65/66) starting from encoder count `position`, with
+    // `qpps` the counts/s at full duty; returns the move to send
+    pub fn check_position(&mut self, motor: Motor, command: PositionCommand, position: i64, qpps: i32) -> Result<PositionCommand, String> {
+        let cfg = self.motors[motor.index()].clone();
+        let mut command = command;
+        let from = position.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        let dir_allowed = match cfg.direction {
+            AllowedDirection::Both => command.position,
+            AllowedDirection::ForwardOnly => command.position.max(from),
+            AllowedDirection::ReverseOnly => command.position.min(from),
+        };
+        command.position = self.enforce(motor, "direction", command.position, dir_allowed)?;
+
+        // duty limits as counts/s (speed) and counts/s² (accel); a zero speed or accel is unlimited
+        let at_duty = |duty: f32| (qpps.max(0) as f32 * duty / MAX_DUTY as f32).round().max(1.0) as i32;
+        let requested = |v: u32| if v == 0 { i32::MAX } else { v.min(i32::MAX as u32) as i32 };
+        if cfg.max_duty < MAX_DUTY {
+            let v = requested(command.speed);
+            command.speed = self.enforce(motor, "max_duty", v, v.min(at_duty(cfg.max_duty.max(0) as f32)))? as u32;
+        }
+        if let Some(rate) = cfg.max_slew_per_s {
+            let a = requested(command.accel);
+            command.accel = self.enforce(motor, "slew", a, a.min(at_duty(rate.max(0.0))))? as u32;
+        }
+        Ok(command)
+    }
+}
+
+// Convenience wrappers used by the drive paths
+pub fn filter_duty(motor: Motor, duty: i16) -> Result<i16, String> {
+    let mut s = SAFETY.lock().map_err(|e| format!("Failed to lock safety config: {}", e))?;
+    s.check_duty(motor, duty)
+}
+
+pub fn filter_speed(motor: Motor, speed: u8) -> Result<u8, String> {
+    let mut s = SAFETY.lock().map_err(|e| format!("Failed to lock safety config: {}", e))?;
+    s.check_speed(motor, speed)
+}
+
+// Duty acceleration (duty units/s) to send with the motor's duty commands, from its slew
+// limit. The controller ramps the applied duty at this rate; stop commands go out without.
+pub fn duty_accel(motor: Motor) -> Result<Option<u32>, String> {
+    let s = SAFETY.lock().map_err(|e| format!("Failed to lock safety config: {}", e))?;
+    Ok(s.motors[motor.index()].max_slew_per_s.map(|r| r.round().clamp(1.0, MAX_DUTY_ACCEL as f32) as u32))
+}
+
+pub fn filter_position(motor: Motor, command: PositionCommand, position: i64, qpps: i32) -> Result<PositionCommand, String> {
+    let mut s = SAFETY.lock().map_err(|e| format!("Failed to lock safety config: {}", e))?;
+    s.check_position(motor, command, position, qpps)
+}
+
+pub fn get_safety_config_sync() -> Result<[MotorSafety; 2], String> {
+    let s = SAFETY.lock().map_err(|e| format!("Failed to lock safety config: {}", e))?;
+    Ok(s.motors.clone())
+}
+
+pub fn set_safety_config_sync(motor: Motor, config: MotorSafety) -> Result<(), String> {
+    let i = motor.index();
+    if config.max_duty < 0 { return Err("max_duty must be >= 0".into()); }
+    if config.max_slew_per_s.is_some_and(|r| r <= 0.0) { return Err("max_slew_per_s must be > 0".into()); }
+    let mut s = SAFETY.lock().map_err(|e| format!("Failed to lock safety config: {}", e))?;
+    println!("[SAFETY] {} config: {:?}", motor, config);
+    s.motors[i] = config;
+    Ok(())
+}
+
+pub fn get_safety_events_sync() -> Result<Vec<SafetyEvent>, String> {
+    let s = SAFETY.lock().map_err(|e| format!("Failed to lock safety config: {}", e))?;
+    Ok(s.events.iter().cloned().collect())
+}
+
+pub fn clear_safety_events_sync() -> Result<(), String> {
+    let mut s = SAFETY.lock().map_err(|e| format!("Failed to lock safety config: {}", e))?;
+    s.events.clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(cfg: MotorSafety) -> SafetyState {
+        SafetyState { motors: [cfg.clone(), cfg], events: VecDeque::new() }
+    }
+
+    #[test]
+    fn clamps_duty_and_direction() {
+        let mut s = state(MotorSafety { max_duty: 16000, direction: AllowedDirection::ForwardOnly, ..Default::default() });
+        assert_eq!(s.check_duty(Motor::M1, 32767).unwrap(), 16000);
+        assert_eq!(s.check_duty(Motor::M1, -5000).unwrap(), 0);
+        assert_eq!(s.check_duty(Motor::M2, 1000).unwrap(), 1000);
+        assert_eq!(s.events.len(), 2);
+        // 7-bit speed: full reverse becomes stop, full forward is limited by max_duty
+        assert_eq!(s.check_speed(Motor::M1, 0).unwrap(), 64);
+        assert_eq!(s.check_speed(Motor::M1, 127).unwrap(), 64 + 31);
+    }
+
+    #[test]
+    fn rejects_and_ramps_slew() {
+        let mut s = state(MotorSafety { max_duty: 10000, policy: ViolationPolicy::Reject, ..Default::default() });
+        assert!(s.check_duty(Motor::M1, 20000).is_err());
+        assert_eq!(s.events.back().unwrap().applied, None);
+
+        // the slew limit does not clamp commands, however close together
+        let mut s = state(MotorSafety { max_slew_per_s: Some(16384.0), ..Default::default() });
+        assert_eq!(s.check_duty(Motor::M1, 30000).unwrap(), 30000);
+        assert_eq!(s.check_duty(Motor::M1, 32767).unwrap(), 32767);
+        assert!(s.events.is_empty());
+
+        // it ramps the applied duty instead: half of full duty after half a second
+        let _guard = crate::sim::tests::TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        crate::sim::set_simulation_mode_sync(true).unwrap();
+        crate::sim::tests::with_fast_clock(|| {
+            crate::scenario::reset_sim_sync().unwrap();
+            set_safety_config_sync(Motor::M1, MotorSafety { max_slew_per_s: Some(16384.0), ..Default::default() }).unwrap();
+            crate::device::drive_pwm_sync(32767, Motor::M1).unwrap();
+            crate::sim::step_sim_sync(500).unwrap();
+            let duty = crate::sim::SIM_STATE.lock().unwrap()[Motor::M1].applied_duty();
+            assert!((duty as i32 - 8192).abs() < 200, "{}", duty);
+            crate::sim::step_sim_sync(1500).unwrap();
+            assert_eq!(crate::sim::SIM_STATE.lock().unwrap()[Motor::M1].applied_duty(), 32767);
+            // a stop applies at once
+            crate::device::drive_pwm_sync(0, Motor::M1).unwrap();
+            crate::sim::step_sim_sync(1).unwrap();
+            assert_eq!(crate::sim::SIM_STATE.lock().unwrap()[Motor::M1].applied_duty(), 0);
+            set_safety_config_sync(Motor::M1, MotorSafety::default()).unwrap();
+        });
+        crate::sim::set_simulation_mode_sync(false).unwrap();
+    }
+
+    #[test]
+    fn limits_position_moves() {
+        let cmd = PositionCommand { accel: 0, speed: 40000, decel: 20000, position: -5000 };
+        // the default envelope passes a move unchanged
+        assert_eq!(state(MotorSafety::default()).check_position(Motor::M1, cmd, 0, 44000).unwrap(), cmd);
+
+        // forward only: no travel below the start; half duty: half the qpps; slew: bounded accel
+        let mut s = state(MotorSafety { max_duty: 16384, max_slew_per_s: Some(32767.0), direction: AllowedDirection::ForwardOnly, ..Default::default() });
+        let limited = s.check_position(Motor::M1, cmd, 1000, 32767).unwrap();
+        assert_eq!(limited, PositionCommand { accel: 32767, speed: 16384, decel: 20000, position: 1000 });
+        assert_eq!(s.events.len(), 3);
+        // an unprofiled move (speed 0) is limited as well
+        assert_eq!(s.check_position(Motor::M1, PositionCommand { speed: 0, ..cmd }, -9000, 32767).unwrap().speed, 16384);
+
+        let mut s = state(MotorSafety { direction: AllowedDirection::ForwardOnly, policy: ViolationPolicy::Reject, ..Default::default() });
+        assert!(s.check_position(Motor::M1, cmd, 0, 44000).is_err());
+    }
+}
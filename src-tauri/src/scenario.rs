@@ -0,0 +1,427 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value as JsonValue;
+
+use crate::battery::{Battery, BatteryConfig};
+use crate::disturbance::Disturbance;
+use crate::injection::FaultInjection;
+use crate::motor::Motor;
+use crate::position::{PositionCommand, PositionMove};
+use crate::thermal::{MotorThermal, Thermal, ThermalConfig};
+use crate::robot::Pose2D;
+use crate::sim::{self, sim_advance, SimFault, SimPacing, SimState, SIM_STATE};
+
+// Reproducible simulator runs: snapshots of the complete sim state (plants, stored PIDs,
+// encoder counts, velocities, injected faults, generator state) and scenario files that
+// replay a timeline of events from a snapshot and record what the motors did.
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SimSnapshot {
+    pub version: u32,
+    pub created_ms: u64, // unix time
+    pub state: SimState,
+}
+
+// Read a versioned JSON document, refusing ones written by a newer build
+fn load_versioned<T: serde::de::DeserializeOwned>(path: &str, what: &str, max_version: u32) -> Result<T, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let value: JsonValue = serde_json::from_str(&text).map_err(|e| format!("Invalid {} {}: {}", what, path, e))?;
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(1);
+    if version > max_version as u64 {
+        return Err(format!("{} {} is version {}, this build reads up to {}", what, path, version, max_version));
+    }
+    serde_json::from_value(value).map_err(|e| format!("Invalid {} {}: {}", what, path, e))
+}
+
+fn save_json<T: Serialize>(value: &T, path: &str) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(value).map_err(|e| format!("Failed to serialize {}: {}", path, e))?;
+    std::fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+impl SimSnapshot {
+    pub fn of(state: &SimState) -> Self {
+        let created_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        SimSnapshot { version: SNAPSHOT_VERSION, created_ms, state: state.clone() }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        load_versioned(path, "Snapshot", SNAPSHOT_VERSION)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        save_json(self, path)
+    }
+
+    // Snapshots are plain JSON, so check everything the setters would have refused
+    pub fn validate(&self) -> Result<(), String> {
+        if self.version > SNAPSHOT_VERSION {
+            return Err(format!("Snapshot version {} is newer than supported ({})", self.version, SNAPSHOT_VERSION));
+        }
+        let s = &self.state;
+        if !(s.clock.step_s > 0.0 && s.clock.step_s <= 0.01) {
+            return Err(format!("Invalid sim step {} s (expected 0 < step <= 0.01)", s.clock.step_s));
+        }
+        s.injection.validate()?;
+        for m in Motor::ALL {
+            let ms = &s[m];
+            if !ms.tau.is_finite() || ms.tau <= 0.0 { return Err(format!("{} tau must be > 0 (got {})", m, ms.tau)); }
+            ms.plant.validate()?;
+            ms.nonlinear.validate()?;
+            ms.velocity_loop.validate()?;
+            for d in &ms.disturbances { d.validate()?; }
+        }
+        s.battery.config.validate()?;
+        s.thermal.config.validate()?;
+        if let Some(robot) = &s.robot { robot.validate()?; }
+        Ok(())
+    }
+}
+
+/// Copy of the live simulator state.
+pub fn take_sim_snapshot_sync() -> Result<SimSnapshot, String> {
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    sim::sim_update(&mut sim);
+    Ok(SimSnapshot::of(&sim))
+}
+
+/// Replace the live simulator state with a snapshot. The clock setting (pacing and step)
+/// stays as it is: it belongs to the session, not to the state.
+pub fn restore_sim_snapshot_sync(snapshot: &SimSnapshot) -> Result<(), String> {
+    snapshot.validate()?;
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    let clock = sim.clock;
+    *sim = snapshot.state.clone();
+    sim.clock = clock;
+    sim.last_update = None;
+    println!("[SIM] restored snapshot from {} (t={:.3} s)", snapshot.created_ms, sim.time_s);
+    Ok(())
+}
+
+/// Back to the power-on state, keeping the clock setting and the robot (back at the origin).
+pub fn reset_sim_sync() -> Result<(), String> {
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    let clock = sim.clock;
+    let robot = sim.robot.take();
+    *sim = sim::initial_sim_state();
+    sim.clock = clock;
+    sim.robot = robot.map(|mut r| {
+        r.place(Pose2D::default(), 0.0);
+        r
+    });
+    println!("[SIM] reset to power-on state");
+    Ok(())
+}
+
+// Something that happens to the simulated controller during a scenario. Commands go
+// straight to the simulated controller, without the app's safety limits or fault monitor.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ScenarioAction {
+    Pwm { motor: Motor, duty: i16 },
+    Speed { motor: Motor, speed: u8 }, // 7-bit, 64 = stop
+    Position { motor: Motor, command: PositionCommand },
+    Stop {
+        #[serde(default)]
+        motor: Option<Motor>, // both when omitted
+    },
+    Fault { motor: Motor, fault: SimFault },
+    // replaces the motor's schedule; its times count from the event
+    Disturbances { motor: Motor, schedule: Vec<Disturbance> },
+    FaultInjection { injection: FaultInjection },
+    // swaps in a battery (at its initial charge)
+    Battery { config: BatteryConfig },
+    // swaps in a thermal model (everything at ambient)
+    Thermal { config: ThermalConfig },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScenarioEvent {
+    pub at_s: f64,
+    #[serde(flatten)]
+    pub action: ScenarioAction,
+}
+
+pub const SCENARIO_VERSION: u32 = 1;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Scenario {
+    #[serde(default = "scenario_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub initial: Option<SimSnapshot>, // power-on state when omitted
+    pub duration_s: f64,
+    #[serde(default = "default_sample_ms")]
+    pub sample_ms: u32,
+    #[serde(default)]
+    pub events: Vec<ScenarioEvent>,
+}
+
+fn scenario_version() -> u32 {
+    SCENARIO_VERSION
+}
+
+fn default_sample_ms() -> u32 {
+    10
+}
+
+// Longest scenario, to keep traces (and runs) bounded
+pub const MAX_SCENARIO_S: f64 = 3600.0;
+
+impl Scenario {
+    pub fn load(path: &str) -> Result<Self, String> {
+        load_versioned(path, "Scenario", SCENARIO_VERSION)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.duration_s > 0.0 && self.duration_s <= MAX_SCENARIO_S) {
+            return Err(format!("duration_s must be within 0..{} s (got {})", MAX_SCENARIO_S, self.duration_s));
+        }
+        if self.sample_ms == 0 { return Err("sample_ms must be > 0".into()); }
+        if let Some(initial) = &self.initial { initial.validate()?; }
+        for e in &self.events {
+            if !(e.at_s >= 0.0 && e.at_s <= self.duration_s) {
+                return Err(format!("Event at {} s is outside the scenario (0..{} s)", e.at_s, self.duration_s));
+            }
+            match &e.action {
+                ScenarioAction::Speed { speed, .. } if *speed > 127 => return Err(format!("Speed {} out of range (0..127)", speed)),
+                ScenarioAction::Disturbances { schedule, .. } => for d in schedule { d.validate()?; },
+                ScenarioAction::FaultInjection { injection } => injection.validate()?,
+                ScenarioAction::Battery { config } => config.validate()?,
+                ScenarioAction::Thermal { config } => config.validate()?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MotorSample {
+    pub duty: i16,    // applied by the controller (±32767)
+    pub speed: i32,   // as read back, with the injected measurement effects
+    pub encoder: i64,
+    pub current: u32, // 10 mA units, as read back
+    pub load_torque: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceSample {
+    pub t_s: f64,
+    pub motors: [MotorSample; 2],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pose: Option<Pose2D>, // ground truth, when the robot simulation is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub battery: Option<BatterySample>, // when the battery model is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thermal: Option<[MotorThermal; 2]>, // when the thermal model is on
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BatterySample {
+    pub voltage: f32,
+    pub current_a: f32,
+    pub soc: f32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScenarioTrace {
+    pub name: String,
+    pub samples: Vec<TraceSample>,
+    pub final_state: SimSnapshot,
+}
+
+impl ScenarioTrace {
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        save_json(self, path)
+    }
+}
+
+fn apply(sim: &mut SimState, action: &ScenarioAction) {
+    let now = sim.time_s;
+    match action {
+        ScenarioAction::Pwm { motor, duty } => {
+            let m = &mut sim[*motor];
+            m.pwm = *duty;
+            m.mode_pwm = true;
+            m.position_move = None;
+        }
+        ScenarioAction::Speed { motor, speed } => {
+            let m = &mut sim[*motor];
+            m.speed = *speed;
+            m.mode_pwm = false;
+            m.position_move = None;
+        }
+        ScenarioAction::Position { motor, command } => {
+            let m = &mut sim[*motor];
+            m.position_move = Some(PositionMove::new(*command, m.encoder, &m.position_pid));
+        }
+        ScenarioAction::Stop { motor } => {
+            for &m in motor.as_ref().map(std::slice::from_ref).unwrap_or(&Motor::ALL) { sim[m].stop(); }
+        }
+        ScenarioAction::Fault { motor, fault } => sim[*motor].fault = *fault,
+        ScenarioAction::Disturbances { motor, schedule } => {
+            let m = &mut sim[*motor];
+            m.disturbances = schedule.clone();
+            m.begin_disturbances(now);
+        }
+        ScenarioAction::FaultInjection { injection } => sim.set_fault_injection(*injection),
+        ScenarioAction::Battery { config } => sim.battery = Battery::new(config.clone()),
+        ScenarioAction::Thermal { config } => sim.thermal = Thermal::new(config.clone()),
+    }
+}
+
+fn sample(sim: &mut SimState, t_s: f64) -> TraceSample {
+    let mut read = |m: Motor| MotorSample {
+        duty: sim[m].applied_duty(),
+        speed: sim.measured_speed(m),
+        encoder: sim[m].encoder,
+        current: sim.measured_current(m),
+        load_torque: sim[m].load.torque,
+    };
+    let motors = [read(Motor::M1), read(Motor::M2)];
+    let pose = sim.robot.as_ref().map(|r| r.pose);
+    let b = &sim.battery;
+    let battery = b.config.enabled.then_some(BatterySample { voltage: b.voltage, current_a: b.current_a, soc: b.soc });
+    let thermal = sim.thermal.config.enabled.then_some(sim.thermal.motors);
+    TraceSample { t_s, motors, pose, battery, thermal }
+}
+
+/// Run a scenario on its own copy of the simulator, as fast as possible, and record a
+/// sample every `sample_ms`. Events apply at their exact time (to the sim step); the
+/// live simulator is not touched, so the same scenario always gives the same trace.
+pub fn run_scenario_sync(scenario: &Scenario) -> Result<ScenarioTrace, String> {
+    scenario.validate()?;
+    let mut sim = match &scenario.initial {
+        Some(snapshot) => snapshot.state.clone(),
+        None => sim::initial_sim_state(),
+    };
+    sim.clock.pacing = SimPacing::FastAsPossible;
+    sim.last_update = None;
+    let mut events: Vec<&ScenarioEvent> = scenario.events.iter().collect();
+    events.sort_by(|a, b| a.at_s.total_cmp(&b.at_s)); // stable: same-time events keep file order
+
+    let (t0, h) = (sim.time_s, sim.clock.step_s);
+    let sample_s = scenario.sample_ms as f64 / 1000.0;
+    let mut next_event = 0;
+    let mut next_sample = 0.0;
+    let mut samples = Vec::new();
+    loop {
+        let t = sim.time_s - t0;
+        while next_event < events.len() && events[next_event].at_s <= t + h / 2.0 {
+            apply(&mut sim, &events[next_event].action);
+            next_event += 1;
+        }
+        if t + h / 2.0 >= next_sample {
+            samples.push(sample(&mut sim, t));
+            next_sample += sample_s;
+        }
+        if t + h / 2.0 >= scenario.duration_s { break; }
+        let next = events.get(next_event).map_or(f64::INFINITY, |e| e.at_s).min(next_sample).min(scenario.duration_s);
+        // at least one step, so an event between steps cannot stall the loop
+        sim_advance(&mut sim, (next - t).max(h));
+    }
+    println!("[SIM] scenario \"{}\": {} samples over {} s", scenario.name, samples.len(), scenario.duration_s);
+    let final_state = SimSnapshot { version: SNAPSHOT_VERSION, created_ms: 0, state: sim };
+    Ok(ScenarioTrace { name: scenario.name.clone(), samples, final_state })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::tests::{with_fast_clock, TEST_MUTEX};
+
+    #[test]
+    fn snapshots_restore_the_full_state() {
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        with_fast_clock(snapshot_round_trip);
+    }
+
+    fn snapshot_round_trip() {
+        {
+            let mut sim = SIM_STATE.lock().unwrap();
+            sim[Motor::M1].encoder = 1234;
+            sim[Motor::M1].vel = 56.0;
+            sim[Motor::M2].velocity_pid.qpps = 3000;
+            sim[Motor::M2].fault = SimFault::Stall;
+            sim.set_fault_injection(FaultInjection { seed: 9, speed_noise_std: 2.0, ..Default::default() });
+        }
+        let snap = take_sim_snapshot_sync().unwrap();
+        let path = std::env::temp_dir().join(format!("sim_snapshot_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        snap.save(path).unwrap();
+        let noise_after_snapshot = SIM_STATE.lock().unwrap().measured_speed(Motor::M1);
+
+        reset_sim_sync().unwrap();
+        assert_eq!(SIM_STATE.lock().unwrap()[Motor::M1].encoder, 0);
+        restore_sim_snapshot_sync(&SimSnapshot::load(path).unwrap()).unwrap();
+        {
+            let mut sim = SIM_STATE.lock().unwrap();
+            assert_eq!((sim[Motor::M1].encoder, sim[Motor::M1].vel), (1234, 56.0));
+            assert_eq!(sim[Motor::M2].velocity_pid.qpps, 3000);
+            assert_eq!(sim[Motor::M2].fault, SimFault::Stall);
+            // the generator resumes where it was, so the noise repeats
+            assert_eq!(sim.measured_speed(Motor::M1), noise_after_snapshot);
+        }
+        let mut bad = snap.clone();
+        bad.state.motors[0].tau = 0.0;
+        assert!(restore_sim_snapshot_sync(&bad).is_err());
+        let mut bad = snap.clone();
+        bad.state.battery.config.nominal_v = 0.0;
+        assert!(restore_sim_snapshot_sync(&bad).is_err());
+        let mut bad = snap.clone();
+        bad.state.thermal.config.shutdown_c = bad.state.thermal.config.warn_c;
+        assert!(restore_sim_snapshot_sync(&bad).is_err());
+        let mut bad = snap.clone();
+        let mut robot = crate::robot::RobotSim::new(Default::default(), None, 0.0).unwrap();
+        robot.counts_per_rev[1] = 0.0;
+        bad.state.robot = Some(robot);
+        assert!(restore_sim_snapshot_sync(&bad).is_err());
+        reset_sim_sync().unwrap();
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn scenarios_replay_deterministically() {
+        let scenario: Scenario = serde_json::from_value(serde_json::json!({
+            "name": "step and load",
+            "duration_s": 2.0,
+            "sample_ms": 20,
+            "events": [
+                { "at_s": 0.1, "action": "pwm", "motor": 1, "duty": 16384 },
+                { "at_s": 0.0, "action": "fault_injection", "injection": { "seed": 3, "speed_noise_std": 1.5 } },
+                { "at_s": 1.0, "action": "disturbances", "motor": 1, "schedule": [{ "kind": "torque_step", "at_s": 0.0, "torque": 0.2 }] },
+                { "at_s": 0.5, "action": "fault", "motor": 2, "fault": "runaway" },
+                { "at_s": 1.5, "action": "stop" },
+            ]
+        }))
+        .unwrap();
+        let a = run_scenario_sync(&scenario).unwrap();
+        let b = run_scenario_sync(&scenario).unwrap();
+        assert_eq!(a.samples, b.samples);
+        assert_eq!(a.samples.len(), 101);
+
+        let at = |t: f64| &a.samples[(t / 0.02).round() as usize];
+        assert_eq!(at(0.08).motors[0].duty, 0);
+        assert_eq!(at(0.1).motors[0].duty, 16384);
+        // first-order plant: 50 pps at half duty, less the 20% load after 1 s
+        assert!((at(0.98).motors[0].speed - 50).abs() <= 5, "{:?}", at(0.98));
+        assert!((at(1.48).motors[0].speed - 30).abs() <= 5, "{:?}", at(1.48));
+        assert_eq!(at(1.48).motors[0].load_torque, 0.2);
+        assert!(at(1.0).motors[1].speed > 50); // runaway
+        assert_eq!(at(1.6).motors[0].duty, 0);
+
+        // the final state continues the run
+        let more = Scenario { initial: Some(a.final_state.clone()), events: Vec::new(), duration_s: 0.1, ..scenario.clone() };
+        let next = run_scenario_sync(&more).unwrap();
+        assert_eq!(next.samples[0].motors[0].encoder, a.final_state.state[Motor::M1].encoder);
+
+        let bad = Scenario { events: vec![ScenarioEvent { at_s: 5.0, action: ScenarioAction::Stop { motor: None } }], ..scenario };
+        assert!(run_scenario_sync(&bad).is_err());
+    }
+}
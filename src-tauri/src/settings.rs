@@ -0,0 +1,394 @@
+use once_cell::sync::Lazy;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use serde::{Serialize, Deserialize};
+
+use crate::backup::DeviceConfigBackup;
+use crate::device::{self, ROBOCLAW};
+use crate::motor::Motor;
+use crate::plant::{Nonlinearities, PlantModel};
+use crate::safety::{self, MotorSafety};
+use crate::sim::{self, SIM_STATE};
+
+// Persistent settings: named device profiles stored as JSON in the app config dir.
+// Every field has a default so files written by older versions still load.
+
+pub const SETTINGS_VERSION: u32 = 1;
+pub const SETTINGS_FILE: &str = "settings.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WheelGeometry {
+    pub wheel_diameter_m: f32,
+    pub track_width_m: f32, // distance between the left and right wheel contact points
+}
+
+impl Default for WheelGeometry {
+    fn default() -> Self {
+        WheelGeometry { wheel_diameter_m: 0.1, track_width_m: 0.3 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimPlant {
+    pub tau: f32,  // s
+    pub gain: f32, // pps at full command
+    pub model: PlantModel,
+    pub nonlinear: Nonlinearities,
+}
+
+impl Default for SimPlant {
+    fn default() -> Self {
+        let m = sim::initial_motor_sim();
+        SimPlant { tau: m.tau, gain: m.gain, model: m.plant, nonlinear: m.nonlinear }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceProfile {
+    pub name: String,
+    pub port: String, // prefer the /dev/serial/by-id link; "SIMULATED" selects the simulator
+    pub baud_rate: u32,
+    pub address: u8,
+    pub encoder_cpr: [u32; 2], // encoder counts per motor shaft revolution, per motor
+    pub gear_ratio: [f32; 2],  // motor revolutions per wheel revolution, per motor
+    pub wheel: WheelGeometry,
+    pub safety: [MotorSafety; 2],
+    pub sim_plant: [SimPlant; 2],
+    pub device_config: Option<DeviceConfigBackup>, // controller settings, for diff/restore
+}
+
+impl Default for DeviceProfile {
+    fn default() -> Self {
+        DeviceProfile {
+            name: String::new(),
+            port: String::new(),
+            baud_rate: 115_200,
+            address: 0x80,
+            encoder_cpr: [2048, 2048],
+            gear_ratio: [1.0, 1.0],
+            wheel: WheelGeometry::default(),
+            safety: [MotorSafety::default(), MotorSafety::default()],
+            sim_plant: [SimPlant::default(), SimPlant::default()],
+            device_config: None,
+        }
+    }
+}
+
+impl DeviceProfile {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() { return Err("Profile name must not be empty".into()); }
+        if !(0x80..=0x87).contains(&self.address) {
+            return Err(format!("Invalid address 0x{:02X} (expected 0x80..0x87)", self.address));
+        }
+        if self.baud_rate == 0 { return Err("baud_rate must be > 0".into()); }
+        if self.encoder_cpr.contains(&0) { return Err("encoder_cpr must be > 0".into()); }
+        if self.gear_ratio.iter().any(|g| *g <= 0.0) { return Err("gear_ratio must be > 0".into()); }
+        if self.wheel.wheel_diameter_m <= 0.0 || self.wheel.track_width_m <= 0.0 {
+            return Err("Wheel diameter and track width must be > 0".into());
+        }
+        if self.sim_plant.iter().any(|p| p.tau <= 0.0) { return Err("Sim tau must be > 0".into()); }
+        for p in &self.sim_plant {
+            p.model.validate()?;
+            p.nonlinear.validate()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    pub last_profile: Option<String>,
+    pub auto_connect: bool, // connect to `last_profile` on startup
+    pub profiles: Vec<DeviceProfile>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings { version: SETTINGS_VERSION, last_profile: None, auto_connect: true, profiles: Vec::new() }
+    }
+}
+
+impl Settings {
+    pub fn profile(&self, name: &str) -> Option<&DeviceProfile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    // Insert or replace the profile with the same name
+    pub fn upsert_profile(&mut self, profile: DeviceProfile) -> Result<(), String> {
+        profile.validate()?;
+        match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+        Ok(())
+    }
+
+    pub fn remove_profile(&mut self, name: &str) -> Result<(), String> {
+        let before = self.profiles.len();
+        self.profiles.retain(|p| p.name != name);
+        if self.profiles.len() == before { return Err(format!("No profile named \"{}\"", name)); }
+        if self.last_profile.as_deref() == Some(name) { self.last_profile = None; }
+        Ok(())
+    }
+
+    // A missing file is not an error: first start gets the defaults
+    pub fn load_from(path: &Path) -> Result<Settings, String> {
+        if !path.exists() { return Ok(Settings::default()); }
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let settings: Settings = serde_json::from_str(&text).map_err(|e| format!("Invalid settings file {}: {}", path.display(), e))?;
+        if settings.version > SETTINGS_VERSION {
+            return Err(format!("Settings file version {} is newer than supported ({})", settings.version, SETTINGS_VERSION));
+        }
+        Ok(settings)
+    }
+
+    // Write through a temp file so a crash mid-write never leaves a truncated settings file
+    pub fn save_to(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let text = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to encode settings: {}", e))?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, text).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+        std::fs::rename(&tmp, path).map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+    }
+}
+
+// Set once at startup from the Tauri app config dir
+static SETTINGS_DIR: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn init_settings_dir(dir: PathBuf) {
+    println!("[SETTINGS] using {}", dir.display());
+    *SETTINGS_DIR.lock().unwrap_or_else(|e| e.into_inner()) = Some(dir);
+}
+
+fn settings_path() -> Result<PathBuf, String> {
+    let dir = SETTINGS_DIR.lock().map_err(|e| format!("Failed to lock settings dir: {}", e))?;
+    dir.as_ref().map(|d| d.join(SETTINGS_FILE)).ok_or_else(|| "Settings directory not initialized".to_string())
+}
+
+pub fn get_settings_sync() -> Result<Settings, String> {
+    Settings::load_from(&settings_path()?)
+}
+
+// Held across each load-modify-save, so concurrent commands do not drop each other's changes
+static UPDATE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+fn update_settings(f: impl FnOnce(&mut Settings) -> Result<(), String>) -> Result<Settings, String> {
+    let _update = UPDATE_LOCK.lock().map_err(|e| format!("Failed to lock settings: {}", e))?;
+    let path = settings_path()?;
+    let mut settings = Settings::load_from(&path)?;
+    f(&mut settings)?;
+    settings.version = SETTINGS_VERSION;
+    settings.save_to(&path)?;
+    Ok(settings)
+}
+
+pub fn save_profile_sync(profile: DeviceProfile) -> Result<(), String> {
+    let name = profile.name.clone();
+    update_settings(|s| s.upsert_profile(profile))?;
+    println!("[SETTINGS] saved profile \"{}\"", name);
+    Ok(())
+}
+
+// Attach a controller configuration backup to an existing profile
+pub fn store_device_config_sync(name: &str, config: DeviceConfigBackup) -> Result<(), String> {
+    update_settings(|s| {
+        let profile = s.profiles.iter_mut().find(|p| p.name == name).ok_or_else(|| format!("No profile named \"{}\"", name))?;
+        profile.device_config = Some(config);
+        Ok(())
+    })
+    .map(|_| ())
+}
+
+// Save fitted sim plants (see twin.rs) under `name`. A new profile starts from the live
+// connection but runs on the simulator.
+pub fn store_sim_twin_sync(name: &str, plants: &[(Motor, SimPlant)]) -> Result<DeviceProfile, String> {
+    let captured = capture_profile_sync(name).unwrap_or_else(|_| DeviceProfile { name: name.to_string(), ..Default::default() });
+    let settings = update_settings(|s| {
+        let mut profile = match s.profile(name) {
+            Some(p) => p.clone(),
+            None => DeviceProfile { port: crate::SIMULATED_PORT.into(), ..captured },
+        };
+        for (m, plant) in plants { profile.sim_plant[m.index()] = plant.clone(); }
+        s.upsert_profile(profile)
+    })?;
+    println!("[SETTINGS] saved twin to profile \"{}\"", name);
+    settings.profile(name).cloned().ok_or_else(|| format!("No profile named \"{}\"", name))
+}
+
+pub fn delete_profile_sync(name: &str) -> Result<(), String> {
+    update_settings(|s| s.remove_profile(name)).map(|_| ())
+}
+
+pub fn set_auto_connect_sync(enabled: bool) -> Result<(), String> {
+    update_settings(|s| { s.auto_connect = enabled; Ok(()) }).map(|_| ())
+}
+
+// Build a profile from the live connection, safety limits and sim plant. Fields the
+// app does not track at runtime (encoder CPR, gearing, wheels) come from the saved
+// profile of the same name, or the defaults.
+pub fn capture_profile_sync(name: &str) -> Result<DeviceProfile, String> {
+    let mut profile = get_settings_sync()
+        .ok()
+        .and_then(|s| s.profile(name).cloned())
+        .unwrap_or_default();
+    profile.name = name.to_string();
+    {
+        let guard = ROBOCLAW.lock().map_err(|e| format!("Failed to acquire lock: {}", e))?;
+        let rc = guard.as_ref().ok_or("Roboclaw not initialized")?;
+        profile.port = rc.port_name.clone();
+        profile.baud_rate = rc.baud_rate;
+        profile.address = rc.addr;
+    }
+    profile.safety = safety::get_safety_config_sync()?;
+    let sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    for m in Motor::ALL {
+        let s = &sim[m];
+        profile.sim_plant[m.index()] = SimPlant { tau: s.tau, gain: s.gain, model: s.plant.clone(), nonlinear: s.nonlinear.clone() };
+    }
+    Ok(profile)
+}
+
+// Apply a profile: limits and sim plant first, so they are in force before the port opens
+pub fn apply_profile(profile: &DeviceProfile) -> Result<(), String> {
+    profile.validate()?;
+    for m in Motor::ALL {
+        safety::set_safety_config_sync(m, profile.safety[m.index()].clone())?;
+        let plant = &profile.sim_plant[m.index()];
+        sim::set_sim_params_sync(m, plant.tau, plant.gain)?;
+        sim::set_sim_plant_sync(m, plant.model.clone())?;
+        sim::set_sim_nonlinearities_sync(m, plant.nonlinear.clone())?;
+    }
+    device::configure_address_sync(profile.address)?;
+    device::configure_port_sync(profile.port.clone(), Some(profile.baud_rate))
+}
+
+// Apply the named profile and remember it as the last used one (only if it connected)
+pub fn load_profile_sync(name: &str) -> Result<DeviceProfile, String> {
+    let settings = get_settings_sync()?;
+    let profile = settings.profile(name).cloned().ok_or_else(|| format!("No profile named \"{}\"", name))?;
+    apply_profile(&profile)?;
+    update_settings(|s| { s.last_profile = Some(name.to_string()); Ok(()) })?;
+    println!("[SETTINGS] loaded profile \"{}\" ({})", name, profile.port);
+    Ok(profile)
+}
+
+// The last used profile, for modules that need geometry or encoder resolution
+pub fn active_profile() -> Option<DeviceProfile> {
+    let settings = get_settings_sync().ok()?;
+    settings.last_profile.as_deref().and_then(|n| settings.profile(n)).cloned()
+}
+
+// Called once at startup; returns the profile name if one was connected
+pub fn auto_connect_sync() -> Result<Option<String>, String> {
+    let settings = get_settings_sync()?;
+    let name = match (&settings.last_profile, settings.auto_connect) {
+        (Some(name), true) => name.clone(),
+        _ => return Ok(None),
+    };
+    load_profile_sync(&name)?;
+    Ok(Some(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::tests::TEST_MUTEX;
+
+    // Settings directory for one test; the previous one is put back when dropped
+    struct ScopedSettingsDir(Option<PathBuf>);
+
+    impl Drop for ScopedSettingsDir {
+        fn drop(&mut self) {
+            *SETTINGS_DIR.lock().unwrap_or_else(|e| e.into_inner()) = self.0.take();
+        }
+    }
+
+    fn use_settings_dir(dir: PathBuf) -> ScopedSettingsDir {
+        ScopedSettingsDir(SETTINGS_DIR.lock().unwrap_or_else(|e| e.into_inner()).replace(dir))
+    }
+
+    fn temp_dir(tag: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("motion_studio_{}_{}", tag, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn profiles_round_trip_and_validate() {
+        let path = temp_dir("roundtrip").join(SETTINGS_FILE);
+        let mut s = Settings::load_from(&path).unwrap();
+        assert!(s.profiles.is_empty());
+
+        let bench = DeviceProfile { name: "bench".into(), port: "/dev/serial/by-id/usb-rc".into(), address: 0x81, ..Default::default() };
+        s.upsert_profile(bench.clone()).unwrap();
+        s.upsert_profile(DeviceProfile { baud_rate: 38400, ..bench }).unwrap();
+        assert_eq!(s.profiles.len(), 1);
+        assert!(s.upsert_profile(DeviceProfile { name: "bad".into(), address: 0x10, ..Default::default() }).is_err());
+        s.last_profile = Some("bench".into());
+        s.save_to(&path).unwrap();
+
+        let loaded = Settings::load_from(&path).unwrap();
+        let p = loaded.profile("bench").unwrap();
+        assert_eq!((p.baud_rate, p.address), (38400, 0x81));
+        assert_eq!(loaded.last_profile.as_deref(), Some("bench"));
+
+        // files from older versions may lack fields
+        std::fs::write(&path, r#"{"profiles":[{"name":"old","port":"/dev/ttyACM0"}]}"#).unwrap();
+        let old = Settings::load_from(&path).unwrap();
+        assert_eq!(old.profile("old").unwrap().baud_rate, 115_200);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn load_profile_applies_and_auto_connects() {
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = temp_dir("apply");
+        let _settings_dir = use_settings_dir(dir.clone());
+
+        let mut profile = DeviceProfile { name: "sim".into(), port: crate::SIMULATED_PORT.into(), ..Default::default() };
+        profile.sim_plant[1] = SimPlant { tau: 0.25, gain: 80.0, model: PlantModel::TwoStageLag { tau2: 0.05 }, ..Default::default() };
+        profile.safety[0].max_duty = 12000;
+        save_profile_sync(profile).unwrap();
+        assert!(load_profile_sync("missing").is_err());
+        // concurrent saves all land
+        let saves: Vec<_> = (0..8)
+            .map(|i| std::thread::spawn(move || save_profile_sync(DeviceProfile { name: format!("bench{}", i), ..Default::default() })))
+            .collect();
+        for save in saves { save.join().unwrap().unwrap(); }
+        assert_eq!(get_settings_sync().unwrap().profiles.len(), 9);
+
+        assert_eq!(auto_connect_sync().unwrap(), None); // nothing used yet
+        load_profile_sync("sim").unwrap();
+        // simulate a restart: reset live state, then auto-connect
+        sim::set_sim_params_sync(Motor::M2, 0.1, 100.0).unwrap();
+        sim::set_simulation_mode_sync(false).unwrap();
+        assert_eq!(auto_connect_sync().unwrap().as_deref(), Some("sim"));
+        assert!(sim::is_simulation_enabled());
+        assert_eq!(SIM_STATE.lock().unwrap()[Motor::M2].tau, 0.25);
+        assert_eq!(safety::get_safety_config_sync().unwrap()[0].max_duty, 12000);
+        assert_eq!(capture_profile_sync("sim").unwrap().sim_plant[1].gain, 80.0);
+        assert_eq!(SIM_STATE.lock().unwrap()[Motor::M2].plant, PlantModel::TwoStageLag { tau2: 0.05 });
+
+        // a fitted twin saved under a new name becomes a simulator profile
+        let twin = SimPlant { nonlinear: Nonlinearities { deadband: 0.1, ..Default::default() }, ..Default::default() };
+        let saved = store_sim_twin_sync("twin", &[(Motor::M1, twin.clone())]).unwrap();
+        assert_eq!(saved.port, crate::SIMULATED_PORT);
+        assert_eq!(get_settings_sync().unwrap().profile("twin").unwrap().sim_plant[0], twin);
+
+        // restore defaults for the other tests
+        for m in Motor::ALL {
+            safety::set_safety_config_sync(m, MotorSafety::default()).unwrap();
+            sim::set_sim_params_sync(m, 0.1, 100.0).unwrap();
+            sim::set_sim_plant_sync(m, PlantModel::FirstOrder).unwrap();
+        }
+        sim::set_simulation_mode_sync(false).unwrap();
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,151 +1,593 @@
 use once_cell::sync::Lazy;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Mutex;
-use std::time::Instant;
+use std::ops::{Index, IndexMut};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 use serde_json::Value as JsonValue;
-use crate::device::{VelocityPidParams, PositionPidParams};
+use serde::{Serialize, Deserialize};
+use crate::device::{CurrentLimits, PositionPidParams, VelocityPidParams, VoltageLimits};
+use crate::motor::Motor;
+use crate::disturbance::{self, Disturbance, DisturbanceSample, Load};
+use crate::injection::{quantize, FaultInjection, SimRng, SpeedWindow};
+use crate::firmware_pid::{FirmwarePidState, VelocityLoop};
+use crate::position::PositionMove;
+use crate::plant::{self, Nonlinearities, PlantModel, PlantState};
+use crate::robot::RobotSim;
+use crate::battery::{Battery, BatteryConfig};
+use crate::thermal::{Thermal, ThermalConfig};
 
-#[derive(Default, Clone)]
-pub struct SimState {
-    pub m1_speed: u8, // speed vs vel?
-    pub m2_speed: u8,
-    pub m1_pwm: i16,
-    pub m2_pwm: i16,
-    pub m1_mode_pwm: bool,
-    pub m2_mode_pwm: bool,
-    pub m1_vel: f32,
-    pub m2_vel: f32,
-
-    // Encoder counts (cumulative pulses)
-    pub m1_encoder: i64,
-    pub m2_encoder: i64,
+// Fault injected into one simulated motor, for exercising the fault monitor
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimFault {
+    #[default]
+    None,
+    Stall,               // rotor locked: velocity held at zero
+    Runaway,             // plant drives itself at full forward regardless of command
+    EncoderDisconnected, // motor moves, encoder reports nothing
+    EncoderInverted,     // encoder channels swapped: reported speed/counts negated
+}
+
+// Current drawn at stall per unit duty (10 mA units, as reported by Read Motor Currents)
+pub const SIM_STALL_CURRENT: f32 = 2000.0;
+
+// Per-motor simulator state: command, plant and the controller's stored PIDs
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct MotorSim {
+    pub speed: u8, // 7-bit speed command, 64 = stop
+    pub pwm: i16,
+    pub mode_pwm: bool,
+    pub duty_accel: Option<u32>, // ramp of the applied duty (duty units/s) sent with the last duty command (38/39/40)
+    pub position_move: Option<PositionMove>, // position mode, overrides speed/PWM while set
+    pub vel: f32,
+
+    // Encoder counts (cumulative pulses) and the fraction of a count not yet reported
+    pub encoder: i64,
+    pub encoder_frac: f64,
+    pub speed_window: SpeedWindow,
 
     // Stored PID params for simulation (velocity & position)
-    pub m1_velocity_pid: VelocityPidParams,
-    pub m2_velocity_pid: VelocityPidParams,
-    pub m1_position_pid: PositionPidParams,
-    pub m2_position_pid: PositionPidParams,
+    pub velocity_pid: VelocityPidParams,
+    pub position_pid: PositionPidParams,
+
+    // Stored configuration that has no effect on the plant
+    pub encoder_mode: u8,
+    pub current_limits: CurrentLimits,
+
+    // Internal integrator/last error for velocity PID
+    pub vi: f32,
+    pub v_last_err: f32,
+    pub velocity_loop: VelocityLoop,
+    pub firmware_pid: FirmwarePidState,
+
+    // Actuator command applied on the last update (-1..1)
+    pub u: f32,
+
+    pub fault: SimFault,
+
+    // First-order lag parameters, used when `plant` is FirstOrder
+    pub tau: f32,
+    pub gain: f32,
+
+    pub plant: PlantModel,
+    pub plant_state: PlantState,
+    pub nonlinear: Nonlinearities,
+
+    // Scheduled load disturbances, timed from `disturbance_t0` (sim time), the load they
+    // apply now, and the load seen at each sample of the last experiment
+    pub disturbances: Vec<Disturbance>,
+    pub disturbance_t0: f64,
+    pub load: Load,
+    pub disturbance_trace: Vec<DisturbanceSample>,
+}
+
+// How simulated time relates to the wall clock
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimPacing {
+    #[default]
+    RealTime,       // follows the wall clock; experiments take as long as on hardware
+    FastAsPossible, // time only moves when stepped; experiments finish instantly
+}
 
-    // Internal integrators/last errors for velocity PID
-    pub m1_vi: f32,
-    pub m2_vi: f32,
-    pub m1_v_last_err: f32,
-    pub m2_v_last_err: f32,
+// Simulator clock. The plant is always integrated in fixed steps of `step_s`, so a run
+// in either pacing gives the same samples for the same inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimClock {
+    pub pacing: SimPacing,
+    pub step_s: f64,
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        SimClock { pacing: SimPacing::RealTime, step_s: 0.001 }
+    }
+}
+
+// Longest wall-clock gap caught up in one update; longer idle periods are skipped
+const MAX_CATCH_UP_S: f64 = 0.2;
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct SimState {
+    pub motors: [MotorSim; 2],
+    pub clock: SimClock,
+    pub time_s: f64, // simulated time since power-on
+    #[serde(skip)]
+    pub last_update: Option<Instant>, // wall-clock instant matching `time_s` (real-time pacing)
+
+    // Controller-wide settings, stored for read-back only
+    pub config_word: u16,
+    pub main_battery_limits: VoltageLimits,
+    pub logic_battery_limits: VoltageLimits,
+    pub eeprom_writes: u32,
+
+    pub injection: FaultInjection,
+    pub rng: SimRng,
+
+    // Optional differential-drive robot driven by the two motors
+    #[serde(default)]
+    pub robot: Option<RobotSim>,
+    #[serde(default)]
+    pub battery: Battery,
+    #[serde(default)]
+    pub thermal: Thermal,
+}
+
+impl Index<Motor> for SimState {
+    type Output = MotorSim;
+    fn index(&self, m: Motor) -> &MotorSim {
+        &self.motors[m.index()]
+    }
+}
 
-    pub last_update: Option<Instant>,
-    pub tau_m1: f32,
-    pub gain_m1: f32,
-    pub tau_m2: f32,
-    pub gain_m2: f32,
+impl IndexMut<Motor> for SimState {
+    fn index_mut(&mut self, m: Motor) -> &mut MotorSim {
+        &mut self.motors[m.index()]
+    }
 }
 
 pub static SIMULATION_ENABLED: AtomicBool = AtomicBool::new(false);
-pub static SIM_STATE: Lazy<Mutex<SimState>> = Lazy::new(|| Mutex::new(SimState {
-    m1_speed: 64, // 64 -> 0 speed
-    m2_speed: 64,
-    m1_pwm: 0,
-    m2_pwm: 0,
-    m1_mode_pwm: false,
-    m2_mode_pwm: false,
-    m1_vel: 0.0,
-    m2_vel: 0.0,
-
-    m1_encoder: 0,
-    m2_encoder: 0,
-
-    m1_velocity_pid: VelocityPidParams { p: 0x00010000, i: 0x00008000, d: 0x00004000, qpps: 44000 },
-    m2_velocity_pid: VelocityPidParams { p: 0x00010000, i: 0x00008000, d: 0x00004000, qpps: 44000 },
-    m1_position_pid: PositionPidParams { p: 0x00010000, i: 0x00008000, d: 0x00004000, max_i: 0x00002000, deadzone: 0, min: -32767, max: 32767 },
-    m2_position_pid: PositionPidParams { p: 0x00010000, i: 0x00008000, d: 0x00004000, max_i: 0x00002000, deadzone: 0, min: -32767, max: 32767 },
-
-    m1_vi: 0.0,
-    m2_vi: 0.0,
-    m1_v_last_err: 0.0,
-    m2_v_last_err: 0.0,
-
-    last_update: None,
-    tau_m1: 0.10_f32,
-    gain_m1: 100.0_f32,
-    tau_m2: 0.10_f32,
-    gain_m2: 100.0_f32,
-}));
+pub static SIM_STATE: Lazy<Mutex<SimState>> = Lazy::new(|| Mutex::new(initial_sim_state()));
 
-pub fn sim_update(sim: &mut SimState) {
-    let now = Instant::now();
-    let dt = if let Some(last) = sim.last_update {
-        let raw_dt = (now - last).as_secs_f32();
-        let max_dt = 0.2_f32;
-        let dt_total = raw_dt.clamp(0.0_f32, max_dt);
-        if dt_total <= 1e-6_f32 {
-            sim.last_update = Some(now);
-            return;
-        }
-        dt_total
-    } else {
-        sim.last_update = Some(now);
-        return;
-    };
+// Power-on state of one simulated motor (stopped, default PIDs and plant)
+pub fn initial_motor_sim() -> MotorSim {
+    MotorSim {
+        speed: 64, // 64 -> 0 speed
+        pwm: 0,
+        mode_pwm: false,
+        duty_accel: None,
+        position_move: None,
+        vel: 0.0,
+        encoder: 0,
+        encoder_frac: 0.0,
+        speed_window: SpeedWindow::default(),
+        velocity_pid: VelocityPidParams { p: 0x00010000, i: 0x00008000, d: 0x00004000, qpps: 44000 },
+        position_pid: PositionPidParams { p: 0x00010000, i: 0x00008000, d: 0x00004000, max_i: 0x00002000, deadzone: 0, min: -32767, max: 32767 },
+        encoder_mode: 0, // quadrature
+        current_limits: CurrentLimits::default(),
+        vi: 0.0,
+        v_last_err: 0.0,
+        velocity_loop: VelocityLoop::Continuous,
+        firmware_pid: FirmwarePidState::default(),
+        u: 0.0,
+        fault: SimFault::None,
+        tau: 0.10_f32,
+        gain: 100.0_f32,
+        plant: PlantModel::FirstOrder,
+        plant_state: PlantState::default(),
+        nonlinear: Nonlinearities::default(),
+        disturbances: Vec::new(),
+        disturbance_t0: 0.0,
+        load: Load::default(),
+        disturbance_trace: Vec::new(),
+    }
+}
+
+// Packet serial, 115200 baud, address 0x80
+pub const SIM_CONFIG_WORD: u16 = 0x00A3;
 
-    let tau_m1 = sim.tau_m1;
-    let gain_m1 = sim.gain_m1;
-    let tau_m2 = sim.tau_m2;
-    let gain_m2 = sim.gain_m2;
-
-    // 32767 -> 100% duty
-    // Compute actuator command u for each motor.
-    let m1_u = if sim.m1_mode_pwm {
-        (sim.m1_pwm as f32 / 32767.0).clamp(-1.0, 1.0)
-    } else {
-        // Use velocity PID controller to compute normalized u in speed mode
-        let params = &sim.m1_velocity_pid;
-        let set_v = ((sim.m1_speed as f32 - 64.0) / 63.0) * (params.qpps as f32);
-        let err = set_v - sim.m1_vel;
+// Power-on state of a simulated controller
+pub fn initial_sim_state() -> SimState {
+    SimState {
+        motors: [initial_motor_sim(), initial_motor_sim()],
+        clock: SimClock::default(),
+        time_s: 0.0,
+        last_update: None,
+        config_word: SIM_CONFIG_WORD,
+        main_battery_limits: VoltageLimits::default(),
+        logic_battery_limits: VoltageLimits::default(),
+        eeprom_writes: 0,
+        injection: FaultInjection::default(),
+        rng: SimRng::default(),
+        robot: None,
+        battery: Battery::default(),
+        thermal: Thermal::default(),
+    }
+}
+
+impl MotorSim {
+    // Actuator command u (-1..1): the position PID output in position mode, the duty in
+    // PWM mode, otherwise the velocity PID output
+    fn control(&mut self, dt: f32) -> f32 {
+        if self.position_move.is_some() || self.mode_pwm {
+            // the firmware loop restarts from here when speed mode resumes
+            self.firmware_pid = FirmwarePidState::idle(self.encoder);
+        }
+        if let Some(mv) = self.position_move.as_mut() {
+            return mv.control(&self.position_pid, self.encoder, dt as f64);
+        }
+        // 32767 -> 100% duty
+        if self.mode_pwm {
+            return (self.pwm as f32 / 32767.0).clamp(-1.0, 1.0);
+        }
+        let params = &self.velocity_pid;
+        let set_v = ((self.speed as f32 - 64.0) / 63.0) * (params.qpps as f32);
+        if let VelocityLoop::Firmware { loop_hz } = self.velocity_loop {
+            return self.firmware_pid.step(params, set_v, self.encoder, loop_hz, dt as f64);
+        }
+        let err = set_v - self.vel;
         // PID gains are in 16.16 fixed point
         let p = (params.p as f32) / 65536.0;
         let i = (params.i as f32) / 65536.0;
         let d = (params.d as f32) / 65536.0;
         // integrate
-        sim.m1_vi += err * dt;
+        self.vi += err * dt;
         // derivative
-        let deriv = (err - sim.m1_v_last_err) / dt;
-        sim.m1_v_last_err = err;
+        let deriv = (err - self.v_last_err) / dt;
+        self.v_last_err = err;
         // control (in pps units)
-        let control = p * err + i * sim.m1_vi + d * deriv;
+        let control = p * err + i * self.vi + d * deriv;
         // normalize by qpps to get -1..1 scale
         (control / (params.qpps as f32)).clamp(-1.0, 1.0)
-    };
+    }
 
-    let m2_u = if sim.m2_mode_pwm {
-        (sim.m2_pwm as f32 / 32767.0).clamp(-1.0, 1.0)
-    } else {
-        let params = &sim.m2_velocity_pid;
-        let set_v = ((sim.m2_speed as f32 - 64.0) / 63.0) * (params.qpps as f32);
-        let err = set_v - sim.m2_vel;
-        let p = (params.p as f32) / 65536.0;
-        let i = (params.i as f32) / 65536.0;
-        let d = (params.d as f32) / 65536.0;
-        sim.m2_vi += err * dt;
-        let deriv = (err - sim.m2_v_last_err) / dt;
-        sim.m2_v_last_err = err;
-        let control = p * err + i * sim.m2_vi + d * deriv;
-        (control / (params.qpps as f32)).clamp(-1.0, 1.0)
-    };
+    // One fixed integration step: controller, plant, encoder. `supply` is the bus voltage
+    // over nominal: the bridge applies the duty to whatever the battery delivers.
+    fn step(&mut self, h: f32, supply: f32) {
+        let u = self.control(h);
+        self.u = self.ramp(u, h);
+        let u = if self.fault == SimFault::Runaway { 1.0 } else { self.u };
+        let u = u * supply;
+        self.plant_state.u_drive = u;
+        let nl = &self.nonlinear;
+        let u = nl.shape_input(&mut self.plant_state, u, h);
+        // the DC motor takes the load as a torque; the linear models as lost drive
+        let dc_motor = matches!(self.plant, PlantModel::DcMotor(_));
+        let u = if dc_motor { u } else { u - self.load.torque };
+        let tau = self.tau * self.load.inertia_scale;
+        let held = nl.holds(self.vel, u);
+        let vel_before = self.vel;
+        let u = if held { u } else { nl.after_friction(self.vel, u) };
+        match &self.plant {
+            PlantModel::FirstOrder => self.vel = plant::lag(self.vel, self.gain * u, tau, h),
+            PlantModel::FirstOrderDeadTime { dead_time_s } => {
+                let delayed = self.plant_state.delayed(u, *dead_time_s, h);
+                self.vel = plant::lag(self.vel, self.gain * delayed, tau, h);
+            }
+            PlantModel::SecondOrder { zeta, omega_n } => {
+                let omega_n = omega_n / self.load.inertia_scale.sqrt();
+                self.vel = plant::second_order(&mut self.plant_state, self.vel, self.gain * u, *zeta, omega_n, h);
+            }
+            PlantModel::TwoStageLag { tau2 } => {
+                self.plant_state.x1 = plant::lag(self.plant_state.x1, self.gain * u, tau, h);
+                self.vel = plant::lag(self.vel, self.plant_state.x1, *tau2, h);
+            }
+            PlantModel::DcMotor(p) => {
+                // the controller's current limit (10 mA units) is enforced by the bridge
+                let limit_a = self.current_limits.max as f32 / 100.0;
+                let (vel, current_a) = p.step(self.vel, self.plant_state.current_a, u, limit_a, self.load, h);
+                self.vel = vel;
+                self.plant_state.current_a = current_a;
+            }
+        }
+        if held || self.nonlinear.stops(vel_before, self.vel, u) { self.vel = 0.0; }
+        if self.fault == SimFault::Stall { self.vel = 0.0; }
+        self.plant_state.output_vel = self.nonlinear.backlash_output(&mut self.plant_state, self.vel, h);
+        // integrate encoder counts: pulses = velocity (pps) * dt, carrying the fraction
+        self.encoder_frac += self.reported_vel() as f64 * h as f64;
+        let whole = self.encoder_frac.trunc();
+        self.encoder = self.encoder.wrapping_add(whole as i64);
+        self.encoder_frac -= whole;
+    }
+
+    // Velocity of the output shaft (behind the backlash, if any)
+    pub fn output_vel(&self) -> f32 {
+        if self.nonlinear.backlash > 0.0 { self.plant_state.output_vel } else { self.vel }
+    }
+
+    // Velocity as seen through the (possibly faulty) encoder, on the output shaft when
+    // there is backlash
+    pub fn reported_vel(&self) -> f32 {
+        let vel = self.output_vel();
+        match self.fault {
+            SimFault::EncoderDisconnected => 0.0,
+            SimFault::EncoderInverted => -vel,
+            _ => vel,
+        }
+    }
+
+    // Encoder speed at full duty with no load: the first-order gain or the motor's no-load speed
+    pub fn full_scale_pps(&self) -> f32 {
+        match &self.plant {
+            PlantModel::DcMotor(p) => p.full_scale_pps(),
+            _ => self.gain,
+        }
+    }
+
+    // Motor current (10 mA units). The DC motor model has a real armature current; the
+    // first-order lag uses a load term plus an armature term that grows with the gap
+    // between command and speed, so a locked rotor draws stall current.
+    pub fn current(&self) -> u32 {
+        if let PlantModel::DcMotor(_) = self.plant {
+            return (self.plant_state.current_a.abs() * 100.0).round() as u32;
+        }
+        let slip = if self.gain.abs() > 1e-6 { (self.plant_state.u_drive - self.vel / self.gain).abs().min(1.0) } else { 0.0 };
+        (self.vel.abs() * 15.0 + slip * SIM_STALL_CURRENT) as u32
+    }
+
+    // Draw on the battery (A): through the bridge it sees the motor current times the duty
+    pub fn supply_current(&self) -> f32 {
+        let duty = if self.fault == SimFault::Runaway { 1.0 } else { self.u.abs().min(1.0) };
+        self.current() as f32 / 100.0 * duty
+    }
+
+    // Output moving toward `u` at the commanded duty acceleration; without one, and for
+    // stop commands and position moves (which have their own profile), `u` at once
+    fn ramp(&self, u: f32, h: f32) -> f32 {
+        let stop = if self.mode_pwm { self.pwm == 0 } else { self.speed == 64 };
+        match self.duty_accel {
+            Some(accel) if !stop && self.position_move.is_none() => {
+                let step = accel as f32 / 32767.0 * h;
+                u.clamp(self.u - step, self.u + step)
+            }
+            _ => u,
+        }
+    }
+
+    // Duty the controller is applying, also in speed mode where the PID sets it and while
+    // a duty command is still ramping
+    pub fn applied_duty(&self) -> i16 {
+        if self.mode_pwm && self.duty_accel.is_none() { self.pwm } else { (self.u * 32767.0).round() as i16 }
+    }
+
+    // Restart the disturbance schedule at `time_s` (start of an experiment) and clear its trace
+    pub fn begin_disturbances(&mut self, time_s: f64) {
+        self.disturbance_t0 = time_s;
+        self.disturbance_trace.clear();
+        self.load = disturbance::load_at(&self.disturbances, 0.0);
+    }
+
+    // Note the load acting at experiment time `t_ms`
+    pub fn record_disturbance(&mut self, t_ms: i64) {
+        if self.disturbances.is_empty() { return; }
+        self.disturbance_trace.push(DisturbanceSample { t_ms, torque: self.load.torque, inertia_scale: self.load.inertia_scale });
+    }
+
+    // Position error of the move in progress, as reported by Read All Status
+    pub fn position_error(&self) -> i16 {
+        match &self.position_move {
+            Some(mv) => mv.error(self.encoder).round().clamp(i16::MIN as f64, i16::MAX as f64) as i16,
+            None => 0,
+        }
+    }
+
+    // Reset Encoders (command 20): zero the counters; the motor keeps its command and speed
+    pub fn reset_encoder(&mut self) {
+        self.encoder = 0;
+        self.encoder_frac = 0.0;
+        self.speed_window.restart(0);
+        self.firmware_pid = FirmwarePidState::idle(0);
+    }
 
-    let m1_target = gain_m1 * m1_u;
-    let m2_target = gain_m2 * m2_u;
+    // Stop command: duty mode at zero duty, as after the e-stop's zero-duty frames (the
+    // speed command is reset to its stop value too)
+    pub fn stop(&mut self) {
+        self.position_move = None;
+        self.pwm = 0;
+        self.mode_pwm = true;
+        self.duty_accel = None;
+        self.speed = 64;
+    }
+}
+
+impl SimState {
+    // New injection settings, with the generator restarted from their seed
+    pub fn set_fault_injection(&mut self, injection: FaultInjection) {
+        self.injection = injection;
+        self.rng = SimRng::new(injection.seed);
+        for m in self.motors.iter_mut() { m.speed_window.restart(m.encoder); }
+    }
+
+    // Warning and error bits of Read All Status: supply voltages and driver temperatures
+    pub fn status_bits(&self) -> u32 {
+        self.battery.status_bits(&self.main_battery_limits, &self.logic_battery_limits) | self.thermal.status_bits()
+    }
+
+    // Speed as read back from the controller, with the injected measurement effects
+    pub fn measured_speed(&mut self, motor: Motor) -> i32 {
+        let inj = self.injection;
+        let m = &self.motors[motor.index()];
+        let mut v = if inj.speed_window_s > 0.0 { m.speed_window.latched } else { m.reported_vel() };
+        if inj.speed_noise_std > 0.0 { v += inj.speed_noise_std * self.rng.gaussian() as f32; }
+        quantize(v, inj.speed_quantum).round() as i32
+    }
 
-    let sub_step = 0.01_f32;
-    let steps = (dt / sub_step).ceil() as u32;
-    let sub_dt = dt / (steps as f32);
+    // Motor current as read back (10 mA units), with the injected measurement effects
+    pub fn measured_current(&mut self, motor: Motor) -> u32 {
+        let inj = self.injection;
+        let mut c = self.motors[motor.index()].current() as f32;
+        if inj.current_noise_std > 0.0 { c += inj.current_noise_std * self.rng.gaussian() as f32; }
+        quantize(c, inj.current_quantum).round().max(0.0) as u32
+    }
+}
+
+// Advance simulated time by `dt_s`, rounded to whole clock steps
+pub fn sim_advance(sim: &mut SimState, dt_s: f64) {
+    let h = sim.clock.step_s;
+    let steps = (dt_s / h).round() as u64;
     for _ in 0..steps {
-        sim.m1_vel += (sub_dt / tau_m1) * (m1_target - sim.m1_vel);
-        sim.m2_vel += (sub_dt / tau_m2) * (m2_target - sim.m2_vel);
-        // integrate encoder counts: pulses = velocity (pps) * dt
-        sim.m1_encoder = sim.m1_encoder.wrapping_add((sim.m1_vel * sub_dt) as i64);
-        sim.m2_encoder = sim.m2_encoder.wrapping_add((sim.m2_vel * sub_dt) as i64);
+        let before = [sim.motors[0].vel, sim.motors[1].vel];
+        let supply = sim.battery.drive_scale();
+        for (m, motor) in sim.motors.iter_mut().zip(Motor::ALL) {
+            m.step(h as f32, supply * sim.thermal.derate(motor));
+            if sim.injection.speed_window_s > 0.0 { m.speed_window.update(m.encoder, sim.injection.speed_window_s, h); }
+        }
+        sim.thermal.update(&sim.motors, h as f32);
+        let load: f32 = sim.motors.iter().map(|m| m.supply_current()).sum();
+        if sim.battery.update(load, h, &sim.main_battery_limits, &sim.logic_battery_limits) {
+            // brownout: the controller restarts, its commands and encoder counts gone
+            for m in sim.motors.iter_mut() {
+                m.stop();
+                m.reset_encoder();
+            }
+        }
+        sim.time_s += h;
+        if let Some(robot) = sim.robot.as_mut() { robot.step(&mut sim.motors, before, h, sim.time_s); }
+        // load for the next step, so it also reads back as the load acting now
+        for m in sim.motors.iter_mut().filter(|m| !m.disturbances.is_empty()) {
+            m.load = disturbance::load_at(&m.disturbances, sim.time_s - m.disturbance_t0);
+        }
     }
+}
+
+// Catch simulated time up with the wall clock. Does nothing in fast pacing, where only
+// `sim_advance`/`sim_wait` move time.
+pub fn sim_update(sim: &mut SimState) {
+    if sim.clock.pacing == SimPacing::FastAsPossible { return; }
+    let now = Instant::now();
+    let last = match sim.last_update {
+        Some(last) => last,
+        None => {
+            sim.last_update = Some(now);
+            return;
+        }
+    };
+    let elapsed = now.saturating_duration_since(last).as_secs_f64();
+    let h = sim.clock.step_s;
+    let steps = (elapsed.min(MAX_CATCH_UP_S) / h).floor();
+    sim_advance(sim, steps * h);
+    // keep the unsimulated remainder for the next update, unless the gap was skipped
+    sim.last_update = Some(if elapsed > MAX_CATCH_UP_S { now } else { last + Duration::from_secs_f64(steps * h) });
+}
 
-    sim.last_update = Some(now);
+// Let `dt` of simulated time pass in an experiment loop: sleeps and catches up in
+// real-time pacing, steps the plant directly in fast pacing
+pub fn sim_wait(sim: &mut SimState, dt: Duration) {
+    let dt = jittered(sim, dt);
+    match sim.clock.pacing {
+        SimPacing::RealTime => {
+            std::thread::sleep(dt);
+            sim_update(sim);
+        }
+        SimPacing::FastAsPossible => sim_advance(sim, dt.as_secs_f64()),
+    }
+}
+
+// Same for experiment loops holding the global sim lock: in real-time pacing the lock is
+// released while sleeping, so an e-stop or another command can get in between samples
+pub fn sim_wait_unlocked(mut sim: MutexGuard<'static, SimState>, dt: Duration) -> MutexGuard<'static, SimState> {
+    if sim.clock.pacing == SimPacing::FastAsPossible {
+        sim_wait(&mut sim, dt);
+        return sim;
+    }
+    let dt = jittered(&mut sim, dt);
+    drop(sim);
+    std::thread::sleep(dt);
+    let mut sim = SIM_STATE.lock().unwrap_or_else(|e| e.into_inner());
+    sim_update(&mut sim);
+    sim
+}
+
+fn jittered(sim: &mut SimState, dt: Duration) -> Duration {
+    let jitter_ms = sim.injection.sample_jitter_ms;
+    if jitter_ms > 0.0 { dt + Duration::from_secs_f64(jitter_ms * sim.rng.uniform() / 1000.0) } else { dt }
+}
+
+// Serial read timeout of the device layer, spent waiting for a dropped reply
+const LINK_TIMEOUT: Duration = Duration::from_millis(100);
+
+// One request/reply over the simulated link: adds the injected latency, then fails the
+// way the serial path does when the reply is dropped or corrupted
+pub fn sim_link(sim: &mut SimState) -> Result<(), String> {
+    let inj = sim.injection;
+    if inj.latency_ms > 0.0 { sim_wait(sim, Duration::from_secs_f64(inj.latency_ms / 1000.0)); }
+    if sim.rng.chance(inj.drop_rate) {
+        sim_wait(sim, LINK_TIMEOUT);
+        return Err("No data received (timeout)".into());
+    }
+    if sim.rng.chance(inj.crc_error_rate) { return Err("CRC mismatch".into()); }
+    Ok(())
+}
+
+// Replace the fault injection settings and restart the generator from their seed
+pub fn set_sim_fault_injection_sync(injection: FaultInjection) -> Result<(), String> {
+    injection.validate()?;
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    sim.set_fault_injection(injection);
+    println!("[SIM] fault injection: {:?}", injection);
+    Ok(())
+}
+
+pub fn set_sim_clock_sync(clock: SimClock) -> Result<(), String> {
+    if !(clock.step_s > 0.0 && clock.step_s <= 0.01) {
+        return Err(format!("Invalid sim step {} s (expected 0 < step <= 0.01)", clock.step_s));
+    }
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    sim.clock = clock;
+    // restart the wall-clock anchor so switching to real time does not catch up a gap
+    sim.last_update = None;
+    println!("[SIM] clock: {:?}, step {} s", clock.pacing, clock.step_s);
+    Ok(())
+}
+
+// Manually advance a fast-paced simulator (e.g. while driving by hand from the UI)
+pub fn step_sim_sync(duration_ms: u32) -> Result<f64, String> {
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    if sim.clock.pacing != SimPacing::FastAsPossible {
+        return Err("Sim clock follows real time; switch to fast pacing to step it".into());
+    }
+    sim_advance(&mut sim, duration_ms as f64 / 1000.0);
+    Ok(sim.time_s)
+}
+
+pub fn set_sim_fault_sync(motor: Motor, fault: SimFault) -> Result<(), String> {
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    sim[motor].fault = fault;
+    println!("[SIM] motor={} fault={:?}", motor, fault);
+    Ok(())
+}
+
+// Replace the battery; it starts at the configured charge
+pub fn set_sim_battery_sync(config: BatteryConfig) -> Result<(), String> {
+    config.validate()?;
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    sim_update(&mut sim);
+    println!("[SIM] battery: {:?}", config);
+    sim.battery = Battery::new(config);
+    Ok(())
+}
+
+pub fn get_sim_battery_sync() -> Result<Battery, String> {
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    sim_update(&mut sim);
+    Ok(sim.battery.clone())
+}
+
+// Replace the thermal model; everything starts at ambient
+pub fn set_sim_thermal_sync(config: ThermalConfig) -> Result<(), String> {
+    config.validate()?;
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    sim_update(&mut sim);
+    println!("[SIM] thermal: {:?}", config);
+    sim.thermal = Thermal::new(config);
+    Ok(())
+}
+
+pub fn get_sim_thermal_sync() -> Result<Thermal, String> {
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    sim_update(&mut sim);
+    Ok(sim.thermal.clone())
 }
 
 pub fn is_simulation_enabled() -> bool {
@@ -157,19 +599,60 @@ pub fn set_simulation_mode_sync(enabled: bool) -> Result<(), String> {
     Ok(())
 }
 
-pub fn set_sim_params_sync(motor_index: u8, tau: f32, gain: f32) -> Result<(), String> {
+pub fn set_sim_params_sync(motor: Motor, tau: f32, gain: f32) -> Result<(), String> {
     let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
-    if motor_index == 1 {
-        sim.tau_m1 = tau;
-        sim.gain_m1 = gain;
-        println!("[SIM] set_sim_params: motor=1 tau={} s, gain={} pps per ±1", tau, gain);
-    } else if motor_index == 2 {
-        sim.tau_m2 = tau;
-        sim.gain_m2 = gain;
-        println!("[SIM] set_sim_params: motor=2 tau={} s, gain={} pps per ±1", tau, gain);
-    } else {
-        return Err("Invalid motor index".into());
-    }
+    // the time since the last update ran on the old parameters
+    sim_update(&mut sim);
+    sim[motor].tau = tau;
+    sim[motor].gain = gain;
+    println!("[SIM] set_sim_params: motor={} tau={} s, gain={} pps per ±1", motor, tau, gain);
+    Ok(())
+}
+
+pub fn set_sim_plant_sync(motor: Motor, plant: PlantModel) -> Result<(), String> {
+    plant.validate()?;
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    sim_update(&mut sim);
+    println!("[SIM] motor={} plant={:?}", motor, plant);
+    sim[motor].plant = plant;
+    sim[motor].plant_state = PlantState::default();
+    Ok(())
+}
+
+pub fn set_sim_nonlinearities_sync(motor: Motor, nonlinear: Nonlinearities) -> Result<(), String> {
+    nonlinear.validate()?;
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    sim_update(&mut sim);
+    println!("[SIM] motor={} nonlinearities={:?}", motor, nonlinear);
+    sim[motor].nonlinear = nonlinear;
+    Ok(())
+}
+
+// Replace a motor's disturbance schedule; its times count from now
+pub fn set_sim_disturbances_sync(motor: Motor, schedule: Vec<Disturbance>) -> Result<(), String> {
+    for d in &schedule { d.validate()?; }
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    sim_update(&mut sim);
+    println!("[SIM] motor={} disturbances={:?}", motor, schedule);
+    let now = sim.time_s;
+    let m = &mut sim[motor];
+    m.disturbances = schedule;
+    m.begin_disturbances(now);
+    Ok(())
+}
+
+pub fn get_sim_disturbance_trace_sync(motor: Motor) -> Result<Vec<DisturbanceSample>, String> {
+    let sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    Ok(sim[motor].disturbance_trace.clone())
+}
+
+pub fn set_sim_velocity_loop_sync(motor: Motor, velocity_loop: VelocityLoop) -> Result<(), String> {
+    velocity_loop.validate()?;
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    println!("[SIM] motor={} velocity loop={:?}", motor, velocity_loop);
+    let m = &mut sim[motor];
+    m.velocity_loop = velocity_loop;
+    m.firmware_pid = FirmwarePidState::idle(m.encoder);
     Ok(())
 }
 
@@ -206,80 +689,344 @@ pub fn set_sim_params_js_sync(params: JsonValue) -> Result<(), String> {
     };
 
     let motor_i = get_i64(&["motor_index", "motorIndex", "motor"]).ok_or("Missing motor index: provide motor_index/motorIndex/motor")?;
-    if motor_i != 1 && motor_i != 2 {
-        return Err(format!("Invalid motor index: {} (expected 1 or 2)", motor_i));
+    let motor = Motor::try_from(motor_i)?;
+    let tau = get_f64(&["tau", "tau_s", "tauMs"]).map(|t| t as f32);
+    let gain = get_f64(&["gain", "max_vel", "maxVel"]).map(|g| g as f32);
+
+    // Optional plant model selection ("model": "second_order", "zeta": .., "omega_n": ..).
+    // Without it the plant stays as it is and only tau/gain change.
+    let plant = match params.get("model") {
+        Some(_) => Some(serde_json::from_value::<PlantModel>(params.clone()).map_err(|e| format!("Invalid plant model: {}", e))?),
+        None => None,
+    };
+    if plant.is_none() {
+        tau.ok_or("Missing tau: provide tau/tau_s/tauMs")?;
+        gain.ok_or("Missing gain: provide gain/max_vel/maxVel")?;
     }
-    let tau = get_f64(&["tau", "tau_s", "tauMs"]).ok_or("Missing tau: provide tau/tau_s/tauMs")? as f32;
-    let gain = get_f64(&["gain", "max_vel", "maxVel"]).ok_or("Missing gain: provide gain/max_vel/maxVel")? as f32;
 
-    println!("[SIM JS] parsed motor={}, tau={}, gain={}", motor_i, tau, gain);
+    println!("[SIM JS] parsed motor={}, tau={:?}, gain={:?}, plant={:?}", motor_i, tau, gain, plant);
 
-    set_sim_params_sync(motor_i as u8, tau, gain)
+    if let Some(plant) = &plant { plant.validate()?; }
+    if tau.is_some() || gain.is_some() {
+        let (cur_tau, cur_gain) = {
+            let sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+            (sim[motor].tau, sim[motor].gain)
+        };
+        set_sim_params_sync(motor, tau.unwrap_or(cur_tau), gain.unwrap_or(cur_gain))?;
+    }
+    match plant {
+        Some(plant) => set_sim_plant_sync(motor, plant),
+        None => Ok(()),
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use std::time::{Duration, Instant};
     use once_cell::sync::Lazy;
     use std::sync::Mutex;
 
-    static TEST_MUTEX: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+    // Serializes tests that touch the global SIM_STATE / ROBOCLAW / SIMULATION_ENABLED
+    pub(crate) static TEST_MUTEX: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+    // Run `f` with the global sim in fast pacing, restoring real time afterwards
+    pub(crate) fn with_fast_clock<T>(f: impl FnOnce() -> T) -> T {
+        set_sim_clock_sync(SimClock { pacing: SimPacing::FastAsPossible, ..Default::default() }).unwrap();
+        let out = f();
+        set_sim_clock_sync(SimClock::default()).unwrap();
+        out
+    }
 
     #[test]
     fn velocity_pid_changes_response() {
-        let mut sim = SimState {
-            m1_speed: 64,
-            m2_speed: 64,
-            m1_pwm: 0,
-            m2_pwm: 0,
-            m1_mode_pwm: false,
-            m2_mode_pwm: false,
-            m1_vel: 0.0,
-            m2_vel: 0.0,
-            m1_encoder: 0,
-            m2_encoder: 0,
-            m1_velocity_pid: VelocityPidParams { p: 0x00010000, i: 0x0, d: 0x0, qpps: 44000 },
-            m2_velocity_pid: VelocityPidParams::default(),
-            m1_position_pid: PositionPidParams::default(),
-            m2_position_pid: PositionPidParams::default(),
-            m1_vi: 0.0,
-            m2_vi: 0.0,
-            m1_v_last_err: 0.0,
-            m2_v_last_err: 0.0,
-            last_update: Some(Instant::now() - Duration::from_millis(200)),
-            tau_m1: 0.10_f32,
-            gain_m1: 100.0_f32,
-            tau_m2: 0.10_f32,
-            gain_m2: 100.0_f32,
+        let mut sim = initial_sim_state();
+        sim[Motor::M1].velocity_pid = VelocityPidParams { p: 0x00010000, i: 0x0, d: 0x0, qpps: 44000 };
+
+        // Command half speed in speed mode and run 0.2 s of sim time
+        sim[Motor::M1].speed = 96;
+        sim[Motor::M1].mode_pwm = false;
+        let mut sim2 = sim.clone();
+        sim_advance(&mut sim, 0.2);
+        // With a P-only controller, velocity should be > 0
+        assert!(sim[Motor::M1].vel > 0.0);
+
+        // Now increase P and ensure it responds faster (higher vel after same time)
+        sim2[Motor::M1].velocity_pid.p = 0x00020000; // P *2
+        sim_advance(&mut sim2, 0.2);
+        assert!(sim2[Motor::M1].vel >= sim[Motor::M1].vel);
+        assert!((sim.time_s - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fixed_step_clock_is_deterministic() {
+        // real-time pacing integrates whole steps and carries the remainder
+        let mut rt = initial_sim_state();
+        rt[Motor::M1].pwm = 16000;
+        rt[Motor::M1].mode_pwm = true;
+        rt.last_update = Some(Instant::now() - Duration::from_millis(50));
+        sim_update(&mut rt);
+        assert!(rt.time_s >= 0.05 - 1e-9 && rt.time_s < 0.06, "{}", rt.time_s);
+        // fast pacing ignores the wall clock
+        let mut fast = initial_sim_state();
+        fast.clock.pacing = SimPacing::FastAsPossible;
+        fast.last_update = Some(Instant::now() - Duration::from_millis(50));
+        sim_update(&mut fast);
+        assert_eq!(fast.time_s, 0.0);
+
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        set_simulation_mode_sync(true).unwrap();
+        let run = || {
+            *SIM_STATE.lock().unwrap() = initial_sim_state();
+            with_fast_clock(|| {
+                let started = Instant::now();
+                let samples = tauri::async_runtime::block_on(crate::run_step_response_async(Motor::M1, 100, 2000, 10, 100)).unwrap();
+                assert!(started.elapsed() < Duration::from_millis(1500), "fast run took {:?}", started.elapsed());
+                samples
+            })
         };
+        let (a, b) = (run(), run());
+        assert_eq!(a, b);
+        assert_eq!(a.len(), 211);
+        assert_eq!((a[10].0, a[10].2), (100, 100));
+        assert!(a.last().unwrap().1 > 0);
+        set_simulation_mode_sync(false).unwrap();
+    }
 
-        let _guard = TEST_MUTEX.lock().unwrap();
+    #[test]
+    fn dc_motor_plant_reports_current_and_obeys_limit() {
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        set_simulation_mode_sync(true).unwrap();
+        *SIM_STATE.lock().unwrap() = initial_sim_state();
+        let params = crate::plant::DcMotorParams::default();
+        assert!(set_sim_plant_sync(Motor::M2, PlantModel::DcMotor(crate::plant::DcMotorParams { resistance_ohm: 0.0, ..params.clone() })).is_err());
+        set_sim_plant_sync(Motor::M2, PlantModel::DcMotor(params.clone())).unwrap();
+        crate::device::set_current_limits_sync(Motor::M2, CurrentLimits { max: 500, min: 0 }).unwrap();
+
+        with_fast_clock(|| {
+            crate::device::drive_pwm_sync(32767, Motor::M2).unwrap();
+            step_sim_sync(5).unwrap();
+            // inrush is held at the 5 A limit
+            assert_eq!(crate::device::read_motor_currents_sync().unwrap().1, 500);
+            step_sim_sync(1000).unwrap();
+            let sim = SIM_STATE.lock().unwrap();
+            assert!((sim[Motor::M2].vel - params.full_scale_pps()).abs() < 0.01 * params.full_scale_pps());
+            assert!(sim[Motor::M2].current() < 50);
+            assert_eq!(sim[Motor::M1].vel, 0.0);
+        });
+
+        *SIM_STATE.lock().unwrap() = initial_sim_state();
+        set_simulation_mode_sync(false).unwrap();
+    }
 
-        // Set PWM to max forward and enable PWM mode
-        sim.m1_pwm = 32767;
-        sim.m1_mode_pwm = true;
-        // call update several times
-        for _ in 0..10 {
-            sim_update(&mut sim);
+    #[test]
+    fn js_payload_selects_plant_model() {
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        *SIM_STATE.lock().unwrap() = initial_sim_state();
+        let set = |v: JsonValue| set_sim_params_js_sync(v);
+        set(serde_json::json!({ "motorIndex": 2, "model": "second_order", "zeta": 0.2, "omegaN": 15.0, "gain": 80.0 })).unwrap();
+        {
+            let sim = SIM_STATE.lock().unwrap();
+            assert_eq!(sim[Motor::M2].plant, PlantModel::SecondOrder { zeta: 0.2, omega_n: 15.0 });
+            assert_eq!((sim[Motor::M2].tau, sim[Motor::M2].gain), (0.1, 80.0));
         }
-        // With a P-only controller, velocity should be > 0
-        assert!(sim.m1_vel > 0.0);
+        set(serde_json::json!({ "motor": 2, "model": "first_order_dead_time", "deadTime": 0.04, "tau": 0.2 })).unwrap();
+        // plain tau/gain updates keep the model
+        set(serde_json::json!({ "motor": 2, "tau": 0.3, "gain": 90.0 })).unwrap();
+        assert_eq!(SIM_STATE.lock().unwrap()[Motor::M2].plant, PlantModel::FirstOrderDeadTime { dead_time_s: 0.04 });
+        assert!(set(serde_json::json!({ "motor": 2, "model": "two_stage_lag" })).is_err());
+        assert!(set(serde_json::json!({ "motor": 2, "model": "third_order", "tau": 0.1 })).is_err());
+        assert!(set(serde_json::json!({ "motor": 1, "tau": 0.1 })).is_err());
+        *SIM_STATE.lock().unwrap() = initial_sim_state();
+    }
 
-        // Now increase P and ensure it responds faster (higher vel after same steps)
-        let mut sim2 = sim.clone();
-        sim2.m1_velocity_pid.p = 0x00020000; // P *2
-        for _ in 0..10 { sim_update(&mut sim2); }
-        assert!(sim2.m1_vel >= sim.m1_vel);
+    #[test]
+    fn nonlinearities_shape_the_response() {
+        let pwm_run = |nl: Nonlinearities, duties: &[(i16, f64)]| {
+            let mut sim = initial_sim_state();
+            sim[Motor::M1].nonlinear = nl;
+            sim[Motor::M1].mode_pwm = true;
+            let mut trace = Vec::new();
+            for &(duty, secs) in duties {
+                sim[Motor::M1].pwm = duty;
+                sim_advance(&mut sim, secs);
+                trace.push((sim[Motor::M1].vel, sim[Motor::M1].reported_vel(), sim[Motor::M1].encoder));
+            }
+            trace
+        };
+        let half = 16384;
+        // deadband: 10% duty does nothing, 50% gives 40% of the gain
+        let t = pwm_run(Nonlinearities { deadband: 0.2, ..Default::default() }, &[(3277, 1.0), (half, 1.0)]);
+        assert_eq!(t[0].0, 0.0);
+        assert!((t[1].0 - 30.0).abs() < 0.5, "{}", t[1].0);
+        // static friction holds a stopped motor; Coulomb friction stops it dead when the drive is removed
+        let nl = Nonlinearities { static_friction: 0.2, coulomb_friction: 0.1, ..Default::default() };
+        let t = pwm_run(nl, &[(3277, 1.0), (half, 1.0), (0, 1.0)]);
+        assert_eq!(t[0].0, 0.0);
+        assert!((t[1].0 - 40.0).abs() < 0.5, "{}", t[1].0);
+        assert_eq!(t[2].0, 0.0);
+        // asymmetric gain
+        let t = pwm_run(Nonlinearities { reverse_gain: 0.5, ..Default::default() }, &[(-half, 1.0)]);
+        assert!((t[0].0 + 25.0).abs() < 0.5);
+        // rate limit: full duty is reached only after 1 s at 1 full scale/s
+        let t = pwm_run(Nonlinearities { rate_limit: 1.0, ..Default::default() }, &[(32767, 0.1)]);
+        let unlimited = pwm_run(Nonlinearities::default(), &[(32767, 0.1)]);
+        assert!(t[0].0 < 0.2 * unlimited[0].0);
+        // backlash: the output shaft trails the motor by half the play, on the other side after reversing
+        let duties = [(half, 1.0), (-half, 1.0)];
+        let t = pwm_run(Nonlinearities { backlash: 20.0, ..Default::default() }, &duties);
+        let plain = pwm_run(Nonlinearities::default(), &duties);
+        assert!((t[0].2 - plain[0].2 + 10).abs() <= 1, "{} {}", t[0].2, plain[0].2);
+        assert!((t[1].2 - plain[1].2 - 10).abs() <= 1, "{} {}", t[1].2, plain[1].2);
+        assert!((t[1].1 - t[1].0).abs() < 0.5);
+    }
+
+    #[test]
+    fn fault_injection_is_seeded() {
+        let run = |injection: FaultInjection| {
+            let mut sim = initial_sim_state();
+            sim.clock.pacing = SimPacing::FastAsPossible;
+            sim.injection = injection;
+            sim.rng = SimRng::new(injection.seed);
+            sim[Motor::M1].pwm = 16384;
+            sim[Motor::M1].mode_pwm = true;
+            let mut samples = Vec::new();
+            for _ in 0..300 {
+                sim_wait(&mut sim, Duration::from_millis(10));
+                samples.push(sim_link(&mut sim).map(|_| (sim.measured_speed(Motor::M1), sim.measured_current(Motor::M1))));
+            }
+            (samples, sim.time_s)
+        };
+        let faults = FaultInjection { seed: 7, speed_noise_std: 5.0, current_noise_std: 3.0, sample_jitter_ms: 2.0, drop_rate: 0.2, crc_error_rate: 0.1, ..Default::default() };
+        let (a, t) = run(faults);
+        assert_eq!(run(faults), (a.clone(), t));
+        assert_ne!(run(FaultInjection { seed: 8, ..faults }).0, a);
+        let count = |msg: &str| a.iter().filter(|r| r.as_ref().err().map(|e| e.as_str()) == Some(msg)).count();
+        let (dropped, corrupted) = (count("No data received (timeout)"), count("CRC mismatch"));
+        assert!((30..90).contains(&dropped), "{}", dropped);
+        assert!((10..45).contains(&corrupted), "{}", corrupted);
+        // jitter and reply timeouts stretch the sampling
+        assert!(t > 3.0 + 0.1 * dropped as f64, "{}", t);
+        // noise averages out around the 50 pps steady state
+        let speeds: Vec<i32> = a[150..].iter().filter_map(|r| r.as_ref().ok()).map(|s| s.0).collect();
+        let mean = speeds.iter().sum::<i32>() as f64 / speeds.len() as f64;
+        assert!((mean - 50.0).abs() < 3.0, "{}", mean);
+        assert!(speeds.iter().any(|&v| (v - 50).abs() > 5));
+        // windowed speed comes in whole counts per window
+        let (w, _) = run(FaultInjection { speed_window_s: 0.05, ..Default::default() });
+        assert!(w[100..].iter().all(|r| r.as_ref().unwrap().0 % 20 == 0 && (r.as_ref().unwrap().0 - 50).abs() <= 20));
+    }
+
+    #[test]
+    fn load_disturbances_follow_their_schedule() {
+        let mut sim = initial_sim_state();
+        let m = &mut sim[Motor::M1];
+        m.pwm = 16384;
+        m.mode_pwm = true;
+        m.disturbances = serde_json::from_value(serde_json::json!([
+            { "kind": "torque_step", "at_s": 1.0, "until_s": 2.0, "torque": 0.2 },
+            { "kind": "inertia_change", "at_s": 2.0, "factor": 4.0 },
+        ]))
+        .unwrap();
+        m.begin_disturbances(0.0);
+        let at = |sim: &mut SimState, t_s: f64| {
+            sim_advance(sim, t_s - sim.time_s);
+            sim[Motor::M1].record_disturbance((t_s * 1000.0).round() as i64);
+            sim[Motor::M1].vel
+        };
+        assert!((at(&mut sim, 1.0) - 50.0).abs() < 0.5);
+        // the load takes 20% of the drive
+        assert!((at(&mut sim, 2.0) - 30.0).abs() < 0.5);
+        // it recovers with four times the time constant
+        let expect = 30.0 + 20.0 * (1.0 - (-0.25_f32).exp());
+        assert!((at(&mut sim, 2.1) - expect).abs() < 0.5, "{}", sim[Motor::M1].vel);
+        let trace = &sim[Motor::M1].disturbance_trace;
+        assert_eq!(trace.iter().map(|d| (d.t_ms, d.torque, d.inertia_scale)).collect::<Vec<_>>(), vec![(1000, 0.2, 1.0), (2000, 0.0, 4.0), (2100, 0.0, 4.0)]);
+
+        // the DC motor fights a load torque with current (20% of the 12 A stall current)
+        let mut sim = initial_sim_state();
+        let m = &mut sim[Motor::M2];
+        m.plant = PlantModel::DcMotor(crate::plant::DcMotorParams::default());
+        m.current_limits.max = 0;
+        m.pwm = 16384;
+        m.mode_pwm = true;
+        sim_advance(&mut sim, 1.0);
+        let (free_vel, free_current) = (sim[Motor::M2].vel, sim[Motor::M2].current());
+        sim[Motor::M2].disturbances = vec![Disturbance { at_s: 0.0, until_s: None, kind: crate::disturbance::DisturbanceKind::TorqueStep { torque: 0.2 } }];
+        let now = sim.time_s;
+        sim[Motor::M2].begin_disturbances(now);
+        sim_advance(&mut sim, 1.0);
+        assert!(sim[Motor::M2].vel < 0.7 * free_vel);
+        assert!(sim[Motor::M2].current() > free_current + 200, "{} vs {}", sim[Motor::M2].current(), free_current);
+    }
+
+    #[test]
+    fn position_commands_follow_the_profile() {
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        set_simulation_mode_sync(true).unwrap();
+        *SIM_STATE.lock().unwrap() = initial_sim_state();
+        SIM_STATE.lock().unwrap()[Motor::M1].gain = 2000.0;
+        let pid = PositionPidParams { p: 164 << 16, i: 0, d: 0, max_i: 0, deadzone: 5, min: -10000, max: 10000 };
+        crate::device::set_position_pid_sync(Motor::M1, pid).unwrap();
+
+        with_fast_clock(|| {
+            let cmd = crate::position::PositionCommand { accel: 4000, speed: 1000, decel: 4000, position: 2000 };
+            crate::device::drive_position_sync(Motor::M1, cmd).unwrap();
+            step_sim_sync(1000).unwrap();
+            // cruising: the P-only loop trails the setpoint by speed / loop gain (about 100 counts)
+            let status = crate::device::read_all_status_sync().unwrap();
+            let err = status["m1_pos_err"].as_i64().unwrap();
+            assert!((60..140).contains(&err), "{}", err);
+            assert!((status["m1_speed"].as_i64().unwrap() - 1000).abs() < 50);
+            step_sim_sync(2000).unwrap();
+            let sim = SIM_STATE.lock().unwrap();
+            assert!((sim[Motor::M1].encoder - 2000).abs() <= 6, "{}", sim[Motor::M1].encoder);
+            assert!(sim[Motor::M1].position_error().abs() <= 6);
+            drop(sim);
+            // a target past the max limit stops at the limit
+            crate::device::drive_position_sync(Motor::M1, crate::position::PositionCommand { position: 50000, ..cmd }).unwrap();
+            step_sim_sync(12000).unwrap();
+            assert!((SIM_STATE.lock().unwrap()[Motor::M1].encoder - 10000).abs() <= 6);
+            // any other drive command leaves position mode
+            crate::device::drive_pwm_sync(0, Motor::M1).unwrap();
+            assert!(SIM_STATE.lock().unwrap()[Motor::M1].position_move.is_none());
+        });
+
+        *SIM_STATE.lock().unwrap() = initial_sim_state();
+        set_simulation_mode_sync(false).unwrap();
+    }
+
+    #[test]
+    fn firmware_velocity_loop_uses_qpps_feedforward() {
+        let run = |velocity_loop: VelocityLoop, pid: VelocityPidParams| {
+            let mut sim = initial_sim_state();
+            let m = &mut sim[Motor::M1];
+            m.velocity_loop = velocity_loop;
+            m.velocity_pid = pid;
+            m.speed = 80;
+            sim_advance(&mut sim, 1.0);
+            sim[Motor::M1].vel
+        };
+        let firmware = VelocityLoop::Firmware { loop_hz: 250 };
+        // QPPS matching the plant: the feedforward alone reaches the set speed (16/63 of 100 pps)
+        let no_gains = VelocityPidParams { p: 0, i: 0, d: 0, qpps: 100 };
+        assert!((run(firmware, no_gains.clone()) - 25.4).abs() < 0.5);
+        assert_eq!(run(VelocityLoop::Continuous, no_gains), 0.0);
+        // with QPPS overstated, the integrator makes up the difference
+        // (at 100 pps a tick sees 0 or 1 count, so the integrator also averages out the measurement)
+        let i_only = VelocityPidParams { p: 0, i: 20 << 16, d: 0, qpps: 200 };
+        let v = run(firmware, i_only);
+        assert!((v - 50.8).abs() < 2.0, "{}", v);
+        assert!(VelocityLoop::Firmware { loop_hz: 0 }.validate().is_err());
     }
 
     #[test]
     fn measure_qpps_simulation_uses_pwm() {
         let mut sim = SimState::default();
         // ensure starting state
-        sim.m1_encoder = 0;
-        sim.m1_pwm = 0;
-        sim.m1_mode_pwm = false;
+        sim[Motor::M1].encoder = 0;
+        sim[Motor::M1].pwm = 0;
+        sim[Motor::M1].mode_pwm = false;
         sim.last_update = Some(Instant::now() - Duration::from_millis(200));
 
         let _guard = TEST_MUTEX.lock().unwrap();
@@ -287,7 +1034,7 @@ mod tests {
         // Enable simulation mode for the duration of this test
         set_simulation_mode_sync(true).expect("enable sim");
         // call measure function via device.measure_qpps_sync (simulation path)
-        let res = crate::device::measure_qpps_sync(1, 500).expect("measure qpps failed");
+        let res = with_fast_clock(|| crate::device::measure_qpps_sync(Motor::M1, 500)).expect("measure qpps failed");
         // Disable simulation mode
         set_simulation_mode_sync(false).expect("disable sim");
         // in sim, result is JSON with qpps and encoder_samples
@@ -300,18 +1047,18 @@ mod tests {
 
     #[test]
     fn autotune_step_sim() {
+        let _guard = TEST_MUTEX.lock().unwrap();
+
         // Enable simulation and set plant params
         set_simulation_mode_sync(true).unwrap();
-        set_sim_params_sync(1, 0.10, 100.0).unwrap();
-
-        let _guard = TEST_MUTEX.lock().unwrap();
+        set_sim_params_sync(Motor::M1, 0.10, 100.0).unwrap();
 
         // Ensure ROBOCLAW port is None to force simulated behavior in concurrent tests
         let mut guard = crate::device::ROBOCLAW.lock().unwrap();
         if let Some(rc) = guard.as_mut() { rc.port = None; }
 
         // Run autotune (blocking call to the async command)
-        let res = tauri::async_runtime::block_on(crate::autotune_velocity_step_async(1, 16000, 2000, 100, 50, Some(0.5), Some(false)));
+        let res = with_fast_clock(|| tauri::async_runtime::block_on(crate::autotune_velocity_step_async(Motor::M1, 16000, 2000, 100, 50, Some(0.5), Some(false))));
         assert!(res.is_ok(), "autotune returned error: {:?}", res.err());
         let v = res.unwrap();
         assert!(v.get("suggested_pid").is_some(), "missing suggested_pid");
@@ -323,18 +1070,18 @@ mod tests {
 
     #[test]
     fn autotune_frf_sim() {
+        let _guard = TEST_MUTEX.lock().unwrap();
+
         // Enable simulation and set plant params
         set_simulation_mode_sync(true).unwrap();
-        set_sim_params_sync(1, 0.10, 100.0).unwrap();
-
-        let _guard = TEST_MUTEX.lock().unwrap();
+        set_sim_params_sync(Motor::M1, 0.10, 100.0).unwrap();
 
         // Ensure ROBOCLAW port None
         let mut guard = crate::device::ROBOCLAW.lock().unwrap();
         if let Some(rc) = guard.as_mut() { rc.port = None; }
 
         // Run FRF autotune (blocking)
-        let res = tauri::async_runtime::block_on(crate::autotune_velocity_frf_async(1, 0.5, 20.0, 8, 20.0, 3, 100, 0.001, 2.0, 30, Some(0.5), Some(false)));
+        let res = with_fast_clock(|| tauri::async_runtime::block_on(crate::autotune_velocity_frf_async(Motor::M1, 0.5, 20.0, 8, 20.0, 3, 100, 0.001, 2.0, 30, Some(0.5), Some(false))));
         assert!(res.is_ok(), "autotune frf returned error: {:?}", res.err());
         let v = res.unwrap();
         assert!(v.get("suggested_pid").is_some(), "missing suggested_pid");
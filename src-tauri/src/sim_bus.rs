@@ -0,0 +1,198 @@
+use once_cell::sync::Lazy;
+use std::io::{Read, Write};
+use std::sync::Mutex;
+use serde::{Serialize, Deserialize};
+
+use crate::emulator::Emulator;
+use crate::injection::FaultInjection;
+use crate::motor::Motor;
+use crate::scenario::SimSnapshot;
+use crate::settings::SimPlant;
+use crate::sim::{self, SimFault, SimPacing};
+
+// Simulated multi-drop bus: several emulated controllers, each at its own address with
+// its own plant, settings and faults, behind one transport. Every controller sees every
+// frame and only the addressed one answers, so the regular serial path of the device
+// layer runs unchanged (address selection, discovery, emergency stop of every board).
+
+pub static SIM_BUS: Lazy<Mutex<Vec<Emulator>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+// One virtual controller. It starts from `snapshot` (power-on state when omitted), then
+// `plants`, `faults` and `injection` are applied on top.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SimControllerConfig {
+    pub address: u8,
+    #[serde(default)]
+    pub snapshot: Option<SimSnapshot>,
+    #[serde(default)]
+    pub plants: Option<[SimPlant; 2]>,
+    #[serde(default)]
+    pub faults: [SimFault; 2],
+    #[serde(default)]
+    pub injection: Option<FaultInjection>,
+}
+
+impl SimControllerConfig {
+    fn build(&self) -> Result<Emulator, String> {
+        if !(0x80..=0x87).contains(&self.address) {
+            return Err(format!("Invalid address 0x{:02X} (expected 0x80..0x87)", self.address));
+        }
+        let mut state = match &self.snapshot {
+            Some(snapshot) => {
+                snapshot.validate()?;
+                snapshot.state.clone()
+            }
+            None => sim::initial_sim_state(),
+        };
+        // the bus is polled like hardware, in wall-clock time
+        state.clock.pacing = SimPacing::RealTime;
+        state.last_update = None;
+        for m in Motor::ALL {
+            if let Some(plants) = &self.plants {
+                let p = &plants[m.index()];
+                if !p.tau.is_finite() || p.tau <= 0.0 { return Err(format!("{} tau must be > 0 (got {})", m, p.tau)); }
+                p.model.validate()?;
+                p.nonlinear.validate()?;
+                let ms = &mut state[m];
+                (ms.tau, ms.gain, ms.plant, ms.nonlinear) = (p.tau, p.gain, p.model.clone(), p.nonlinear.clone());
+            }
+            state[m].fault = self.faults[m.index()];
+        }
+        let mut emu = Emulator::with_state(self.address, state);
+        if let Some(injection) = self.injection { emu.set_fault_injection(injection)?; }
+        Ok(emu)
+    }
+}
+
+fn lock_bus() -> Result<std::sync::MutexGuard<'static, Vec<Emulator>>, String> {
+    SIM_BUS.lock().map_err(|e| format!("Failed to lock sim bus: {}", e))
+}
+
+/// Replace the controllers on the simulated bus. Returns their addresses.
+pub fn configure_sim_bus_sync(controllers: &[SimControllerConfig]) -> Result<Vec<u8>, String> {
+    let mut built = Vec::new();
+    for c in controllers {
+        if built.iter().any(|e: &Emulator| e.addr == c.address) {
+            return Err(format!("Two controllers at address 0x{:02X}", c.address));
+        }
+        built.push(c.build()?);
+    }
+    let addrs: Vec<u8> = built.iter().map(|e| e.addr).collect();
+    *lock_bus()? = built;
+    println!("[SIM BUS] controllers at {:02X?}", addrs);
+    Ok(addrs)
+}
+
+// Run `f` on the controller at `addr`
+fn with_controller<T>(addr: u8, f: impl FnOnce(&mut Emulator) -> T) -> Result<T, String> {
+    let mut bus = lock_bus()?;
+    let emu = bus.iter_mut().find(|e| e.addr == addr).ok_or_else(|| format!("No simulated controller at 0x{:02X}", addr))?;
+    Ok(f(emu))
+}
+
+pub fn get_sim_bus_snapshot_sync(addr: u8) -> Result<SimSnapshot, String> {
+    with_controller(addr, |emu| {
+        sim::sim_update(emu.state_mut());
+        SimSnapshot::of(emu.state())
+    })
+}
+
+pub fn set_sim_bus_fault_sync(addr: u8, motor: Motor, fault: SimFault) -> Result<(), String> {
+    with_controller(addr, |emu| emu.state_mut()[motor].fault = fault)?;
+    println!("[SIM BUS] 0x{:02X} motor={} fault={:?}", addr, motor, fault);
+    Ok(())
+}
+
+// Put a power-on controller at `addr` on an empty bus, so connecting always finds one
+pub fn ensure_controller(addr: u8) -> Result<(), String> {
+    let mut bus = lock_bus()?;
+    if bus.is_empty() { bus.push(Emulator::new(addr)); }
+    Ok(())
+}
+
+// Transport end of the bus. Replies are ready as soon as the frame is written; a frame
+// nobody answers reads as a timeout straight away rather than after the serial timeout.
+#[derive(Default)]
+pub struct SimBusPort {
+    reply: Vec<u8>,
+}
+
+impl Write for SimBusPort {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut bus = SIM_BUS.lock().map_err(|e| std::io::Error::other(format!("Failed to lock sim bus: {}", e)))?;
+        // a new request: whatever was not read of the last reply is gone
+        self.reply.clear();
+        let mut latency = std::time::Duration::ZERO;
+        for emu in bus.iter_mut() {
+            let reply = emu.feed(buf);
+            if !reply.is_empty() {
+                latency = latency.max(emu.latency());
+                self.reply.extend_from_slice(&reply);
+            }
+        }
+        drop(bus);
+        std::thread::sleep(latency);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for SimBusPort {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        let n = out.len().min(self.reply.len());
+        out[..n].copy_from_slice(&self.reply[..n]);
+        self.reply.drain(..n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{self, ROBOCLAW};
+    use crate::sim::tests::TEST_MUTEX;
+
+    #[test]
+    fn controllers_on_the_bus_are_independent() {
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        let fast = SimPlant { tau: 0.05, gain: 400.0, ..Default::default() };
+        let controllers = [
+            SimControllerConfig { address: 0x80, snapshot: None, plants: None, faults: Default::default(), injection: None },
+            SimControllerConfig { address: 0x82, snapshot: None, plants: Some([fast.clone(), fast]), faults: [SimFault::None, SimFault::Stall], injection: None },
+        ];
+        assert!(configure_sim_bus_sync(&[controllers[0].clone(), controllers[0].clone()]).is_err());
+        assert_eq!(configure_sim_bus_sync(&controllers).unwrap(), vec![0x80, 0x82]);
+        device::configure_port_sync(crate::SIMULATED_BUS_PORT.into(), None).unwrap();
+        assert!(!sim::is_simulation_enabled());
+
+        assert_eq!(device::scan_addresses_sync().unwrap(), vec![0x80, 0x82]);
+        device::configure_address_sync(0x82).unwrap();
+        device::drive_pwm_sync(16384, Motor::M1).unwrap();
+        device::drive_pwm_sync(16384, Motor::M2).unwrap();
+        // step the board's plant 300 ms ahead instead of waiting for its wall clock
+        with_controller(0x82, |emu| sim::sim_advance(emu.state_mut(), 0.3)).unwrap();
+        let speed = device::read_speed_sync(Motor::M1).unwrap();
+        assert!((speed - 200).abs() < 10, "{}", speed);
+        assert_eq!(device::read_speed_sync(Motor::M2).unwrap(), 0); // stalled
+        // the board at 0x80 never got a command
+        device::configure_address_sync(0x80).unwrap();
+        assert_eq!(device::read_speed_sync(Motor::M1).unwrap(), 0);
+        assert_eq!(get_sim_bus_snapshot_sync(0x82).unwrap().state[Motor::M1].pwm, 16384);
+
+        // emergency stop reaches every board that was addressed
+        crate::estop::emergency_stop_sync().unwrap();
+        assert_eq!(get_sim_bus_snapshot_sync(0x82).unwrap().state[Motor::M1].pwm, 0);
+        crate::estop::clear_estop_sync().unwrap();
+
+        {
+            let mut rc = ROBOCLAW.lock().unwrap_or_else(|e| e.into_inner());
+            let rc = rc.as_mut().unwrap();
+            rc.port = None;
+            rc.known_addrs.clear();
+        }
+        configure_sim_bus_sync(&[]).unwrap();
+    }
+}
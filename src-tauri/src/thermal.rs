@@ -0,0 +1,188 @@
+use serde::{Serialize, Deserialize};
+
+use crate::motor::Motor;
+use crate::plant;
+use crate::sim::MotorSim;
+
+// Lumped thermal model of each motor's driver (H-bridge) and winding. Each is one heat
+// capacity behind a thermal resistance to ambient, heated by I²R losses of the motor
+// current. Like the controller, the driver temperature raises a warning and derates the
+// drive above `warn_c`, and shuts the channel down above `shutdown_c` until it has cooled
+// back below `warn_c`. The winding temperature is tracked only; the controller cannot see it.
+
+// Status bits as reported by Read All Status (M1 driver = sensor 1, M2 driver = sensor 2)
+pub const ERR_TEMP: u32 = 0x0000_0002;
+pub const ERR_TEMP2: u32 = 0x0000_0004;
+pub const WARN_TEMP: u32 = 0x0010_0000;
+pub const WARN_TEMP2: u32 = 0x0020_0000;
+
+// Drive left just below the shutdown temperature
+const MIN_DERATE: f32 = 0.25;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThermalConfig {
+    pub enabled: bool, // off: everything stays at ambient
+    pub ambient_c: f32,
+    pub driver_on_resistance_ohm: f32,   // bridge conduction loss
+    pub driver_thermal_resistance: f32,  // °C/W to ambient
+    pub driver_heat_capacity: f32,       // J/°C
+    pub motor_winding_resistance_ohm: f32,
+    pub motor_thermal_resistance: f32,
+    pub motor_heat_capacity: f32,
+    pub warn_c: f32,     // driver warning, derating starts
+    pub shutdown_c: f32, // driver shuts the channel down
+}
+
+impl Default for ThermalConfig {
+    fn default() -> Self {
+        ThermalConfig {
+            enabled: false,
+            ambient_c: 25.0,
+            driver_on_resistance_ohm: 0.01,
+            driver_thermal_resistance: 4.0,
+            driver_heat_capacity: 10.0,
+            motor_winding_resistance_ohm: 0.2,
+            motor_thermal_resistance: 3.0,
+            motor_heat_capacity: 100.0,
+            warn_c: 85.0,
+            shutdown_c: 100.0,
+        }
+    }
+}
+
+impl ThermalConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.ambient_c.is_finite() { return Err(format!("ambient_c must be finite (got {})", self.ambient_c)); }
+        for (name, v) in [("driver_on_resistance_ohm", self.driver_on_resistance_ohm), ("motor_winding_resistance_ohm", self.motor_winding_resistance_ohm)] {
+            if !v.is_finite() || v < 0.0 { return Err(format!("{} must be >= 0 (got {})", name, v)); }
+        }
+        for (name, v) in [
+            ("driver_thermal_resistance", self.driver_thermal_resistance),
+            ("driver_heat_capacity", self.driver_heat_capacity),
+            ("motor_thermal_resistance", self.motor_thermal_resistance),
+            ("motor_heat_capacity", self.motor_heat_capacity),
+        ] {
+            if !v.is_finite() || v <= 0.0 { return Err(format!("{} must be > 0 (got {})", name, v)); }
+        }
+        if !self.warn_c.is_finite() || !self.shutdown_c.is_finite() || self.shutdown_c <= self.warn_c {
+            return Err(format!("shutdown_c {} must be above warn_c {}", self.shutdown_c, self.warn_c));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MotorThermal {
+    pub driver_c: f32,
+    pub motor_c: f32,
+    pub shutdown: bool, // driver over temperature, channel off until it cools below warn_c
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Thermal {
+    pub config: ThermalConfig,
+    pub motors: [MotorThermal; 2],
+}
+
+impl Default for Thermal {
+    fn default() -> Self {
+        Thermal::new(ThermalConfig::default())
+    }
+}
+
+impl Thermal {
+    // Everything at ambient
+    pub fn new(config: ThermalConfig) -> Thermal {
+        let at_ambient = MotorThermal { driver_c: config.ambient_c, motor_c: config.ambient_c, shutdown: false };
+        Thermal { motors: [at_ambient; 2], config }
+    }
+
+    // Share of the drive the driver lets through: full up to warn_c, then falling linearly
+    // to MIN_DERATE at shutdown_c; none while shut down
+    pub fn derate(&self, motor: Motor) -> f32 {
+        let (c, t) = (&self.config, &self.motors[motor.index()]);
+        if t.shutdown { return 0.0; }
+        let over = ((t.driver_c - c.warn_c) / (c.shutdown_c - c.warn_c)).clamp(0.0, 1.0);
+        1.0 - (1.0 - MIN_DERATE) * over
+    }
+
+    // One sim step with the motor currents of `motors`
+    pub fn update(&mut self, motors: &[MotorSim; 2], h: f32) {
+        let c = &self.config;
+        if !c.enabled { return; }
+        for m in Motor::ALL {
+            let i_sq = (motors[m.index()].current() as f32 / 100.0).powi(2);
+            let t = &mut self.motors[m.index()];
+            // each node settles at ambient + loss * thermal resistance, with time constant R * C
+            let driver_target = c.ambient_c + i_sq * c.driver_on_resistance_ohm * c.driver_thermal_resistance;
+            t.driver_c = plant::lag(t.driver_c, driver_target, c.driver_thermal_resistance * c.driver_heat_capacity, h);
+            let motor_target = c.ambient_c + i_sq * c.motor_winding_resistance_ohm * c.motor_thermal_resistance;
+            t.motor_c = plant::lag(t.motor_c, motor_target, c.motor_thermal_resistance * c.motor_heat_capacity, h);
+            let shutdown = if t.shutdown { t.driver_c >= c.warn_c } else { t.driver_c >= c.shutdown_c };
+            if shutdown != t.shutdown {
+                t.shutdown = shutdown;
+                match shutdown {
+                    true => eprintln!("[SIM] {} driver {:.1} °C: over-temperature shutdown", m, t.driver_c),
+                    false => println!("[SIM] {} driver {:.1} °C: cooled down, re-enabled", m, t.driver_c),
+                }
+            }
+        }
+    }
+
+    // Temperature warning and error bits
+    pub fn status_bits(&self) -> u32 {
+        let mut bits = 0;
+        for (t, warn, err) in [(&self.motors[0], WARN_TEMP, ERR_TEMP), (&self.motors[1], WARN_TEMP2, ERR_TEMP2)] {
+            if t.shutdown { bits |= err; }
+            if t.driver_c > self.config.warn_c { bits |= warn; }
+        }
+        bits
+    }
+
+    // Driver temperature in 0.1 °C units, as the controller reports it
+    pub fn driver_tenths(&self, motor: Motor) -> i16 {
+        (self.motors[motor.index()].driver_c * 10.0).round() as i16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{initial_sim_state, sim_advance};
+
+    #[test]
+    fn hot_driver_derates_then_shuts_down() {
+        let mut sim = initial_sim_state();
+        assert_eq!(sim.thermal.driver_tenths(Motor::M1), 250);
+        // M1 pushes against a locked rotor (stall current); M2 idles
+        sim[Motor::M1].mode_pwm = true;
+        sim[Motor::M1].pwm = 32767;
+        sim[Motor::M1].fault = crate::sim::SimFault::Stall;
+        let config = ThermalConfig { enabled: true, driver_on_resistance_ohm: 0.2, driver_heat_capacity: 0.5, ..Default::default() };
+        assert!(ThermalConfig { shutdown_c: 80.0, ..config.clone() }.validate().is_err());
+        sim.thermal = Thermal::new(config);
+
+        // 20 A stall: 80 W into the driver would settle at 345 °C; derating holds it below shutdown
+        sim_advance(&mut sim, 5.0);
+        let t = sim.thermal.motors[0];
+        assert!(t.driver_c > 85.0 && t.driver_c < 100.0 && !t.shutdown, "{:?}", t);
+        let derate = sim.thermal.derate(Motor::M1);
+        assert!(derate > MIN_DERATE && derate < 1.0, "{}", derate);
+        assert_eq!(sim.thermal.status_bits(), WARN_TEMP);
+        assert!(sim.thermal.motors[0].motor_c > 25.0);
+        assert_eq!(sim.thermal.motors[1].driver_c, 25.0);
+
+        // in a hot enclosure even the derated drive overheats: the channel shuts down
+        sim.thermal.config.ambient_c = 90.0;
+        sim_advance(&mut sim, 5.0);
+        assert!(sim.thermal.motors[0].shutdown);
+        assert_eq!(sim.thermal.status_bits(), WARN_TEMP | ERR_TEMP);
+        assert_eq!(sim[Motor::M1].applied_duty(), 32767); // the command stands, the bridge is off
+
+        // back below the warning temperature it restarts
+        sim.thermal.config.ambient_c = 25.0;
+        sim_advance(&mut sim, 5.0);
+        assert!(!sim.thermal.motors[0].shutdown);
+    }
+}
@@ -0,0 +1,391 @@
+use once_cell::sync::Lazy;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::device::calc_crc;
+
+// Optional recorder for every packet-serial frame exchanged with the controller.
+// Disabled by default; when enabled it keeps the last `capacity` frames in memory.
+
+pub const DEFAULT_TRAFFIC_CAPACITY: usize = 2000;
+
+pub static TRAFFIC_ENABLED: AtomicBool = AtomicBool::new(false);
+pub static TRAFFIC_LOG: Lazy<Mutex<TrafficLog>> = Lazy::new(|| Mutex::new(TrafficLog::new(DEFAULT_TRAFFIC_CAPACITY)));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Tx,
+    Rx,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrcStatus {
+    Ok,
+    Mismatch,
+    Ack,     // single 0xFF acknowledge byte, carries no CRC
+    NoCrc,   // read requests are sent without CRC
+    Missing, // nothing received (timeout / error)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrafficEntry {
+    pub seq: u64,
+    pub timestamp_us: u64, // microseconds since UNIX epoch
+    pub direction: Direction,
+    pub addr: u8,
+    pub cmd: u8,
+    pub command: String,
+    pub bytes: Vec<u8>,
+    pub crc: CrcStatus,
+    pub latency_us: Option<u64>, // RX only: time from TX write to reply
+    pub error: Option<String>,
+}
+
+pub struct TrafficLog {
+    pub capacity: usize,
+    pub entries: VecDeque<TrafficEntry>,
+    next_seq: u64,
+}
+
+impl TrafficLog {
+    pub fn new(capacity: usize) -> Self {
+        TrafficLog { capacity: capacity.max(1), entries: VecDeque::new(), next_seq: 0 }
+    }
+
+    pub fn push(&mut self, mut entry: TrafficEntry) {
+        entry.seq = self.next_seq;
+        self.next_seq += 1;
+        while self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+// Human readable name of a packet-serial command number
+pub fn command_name(cmd: u8) -> &'static str {
+    match cmd {
+        0 => "Drive Forward M1",
+        1 => "Drive Backwards M1",
+        4 => "Drive Forward M2",
+        5 => "Drive Backwards M2",
+        6 => "Drive M1 (7 Bit)",
+        7 => "Drive M2 (7 Bit)",
+        16 => "Read Encoder Count M1",
+        17 => "Read Encoder Count M2",
+        18 => "Read Encoder Speed M1",
+        19 => "Read Encoder Speed M2",
+        20 => "Reset Encoders",
+        21 => "Read Firmware Version",
+        24 => "Read Main Battery Voltage",
+        25 => "Read Logic Battery Voltage",
+        28 => "Set Velocity PID M1",
+        29 => "Set Velocity PID M2",
+        32 => "Drive M1 Duty",
+        33 => "Drive M2 Duty",
+        34 => "Drive M1/M2 Duty",
+        35 => "Drive M1 Speed",
+        36 => "Drive M2 Speed",
+        37 => "Drive M1/M2 Speed",
+        38 => "Drive M1 Duty Accel",
+        39 => "Drive M2 Duty Accel",
+        40 => "Drive M1/M2 Duty Accel",
+        48 => "Read Motor PWMs",
+        49 => "Read Motor Currents",
+        55 => "Read Velocity PID M1",
+        56 => "Read Velocity PID M2",
+        57 => "Set Main Battery Voltages",
+        58 => "Set Logic Battery Voltages",
+        59 => "Read Main Battery Voltage Settings",
+        60 => "Read Logic Battery Voltage Settings",
+        61 => "Set Position PID M1",
+        62 => "Set Position PID M2",
+        63 => "Read Position PID M1",
+        64 => "Read Position PID M2",
+        65 => "Drive M1 Speed Accel Decel Position",
+        66 => "Drive M2 Speed Accel Decel Position",
+        73 => "Read All Status",
+        74 => "Set S3/S4/S5 Modes",
+        91 => "Read Encoder Modes",
+        92 => "Set M1 Encoder Mode",
+        93 => "Set M2 Encoder Mode",
+        94 => "Write Settings to EEPROM",
+        98 => "Set Config",
+        99 => "Read Config",
+        133 => "Set M1 Max Current",
+        134 => "Set M2 Max Current",
+        135 => "Read M1 Max Current",
+        136 => "Read M2 Max Current",
+        _ => "Unknown",
+    }
+}
+
+// Classify the CRC of an outgoing frame (addr, cmd, payload.., crc_hi, crc_lo)
+pub fn tx_crc_status(frame: &[u8]) -> CrcStatus {
+    if frame.len() <= 2 { return CrcStatus::NoCrc; }
+    let n = frame.len() - 2;
+    let crc = ((frame[n] as u16) << 8) | (frame[n + 1] as u16);
+    if calc_crc(&frame[..n]) == crc { CrcStatus::Ok } else { CrcStatus::Mismatch }
+}
+
+// Classify the CRC of a reply; the CRC covers addr + cmd of the request plus the reply payload
+pub fn rx_crc_status(addr: u8, cmd: u8, resp: &[u8]) -> CrcStatus {
+    if resp.is_empty() { return CrcStatus::Missing; }
+    if resp == [0xFF] { return CrcStatus::Ack; }
+    if resp.len() < 3 { return CrcStatus::Mismatch; }
+    let n = resp.len() - 2;
+    let crc = ((resp[n] as u16) << 8) | (resp[n + 1] as u16);
+    let mut full = vec![addr, cmd];
+    full.extend_from_slice(&resp[..n]);
+    if calc_crc(&full) == crc { CrcStatus::Ok } else { CrcStatus::Mismatch }
+}
+
+pub fn is_traffic_logging_enabled() -> bool {
+    TRAFFIC_ENABLED.load(Ordering::Relaxed)
+}
+
+fn now_us() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros() as u64).unwrap_or(0)
+}
+
+fn push_entry(entry: TrafficEntry) {
+    if let Ok(mut log) = TRAFFIC_LOG.lock() {
+        log.push(entry);
+    }
+}
+
+// Record an outgoing frame (no-op when logging is disabled)
+pub fn record_tx(frame: &[u8]) {
+    if !is_traffic_logging_enabled() { return; }
+    let addr = frame.first().copied().unwrap_or(0);
+    let cmd = frame.get(1).copied().unwrap_or(0);
+    push_entry(TrafficEntry {
+        seq: 0,
+        timestamp_us: now_us(),
+        direction: Direction::Tx,
+        addr,
+        cmd,
+        command: command_name(cmd).to_string(),
+        bytes: frame.to_vec(),
+        crc: tx_crc_status(frame),
+        latency_us: None,
+        error: None,
+    });
+}
+
+// Record the reply (or read error) to `request`
+pub fn record_rx(request: &[u8], reply: &Result<Vec<u8>, String>, latency: Duration) {
+    if !is_traffic_logging_enabled() { return; }
+    let addr = request.first().copied().unwrap_or(0);
+    let cmd = request.get(1).copied().unwrap_or(0);
+    let (bytes, error) = match reply {
+        Ok(b) => (b.clone(), None),
+        Err(e) => (Vec::new(), Some(e.clone())),
+    };
+    push_entry(TrafficEntry {
+        seq: 0,
+        timestamp_us: now_us(),
+        direction: Direction::Rx,
+        addr,
+        cmd,
+        command: command_name(cmd).to_string(),
+        crc: rx_crc_status(addr, cmd, &bytes),
+        bytes,
+        latency_us: Some(latency.as_micros() as u64),
+        error,
+    });
+}
+
+pub fn set_traffic_logging_sync(enabled: bool, capacity: Option<usize>) -> Result<(), String> {
+    if let Some(cap) = capacity {
+        let mut log = TRAFFIC_LOG.lock().map_err(|e| format!("Failed to lock traffic log: {}", e))?;
+        log.set_capacity(cap);
+    }
+    TRAFFIC_ENABLED.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn get_traffic_log_sync() -> Result<Vec<TrafficEntry>, String> {
+    let log = TRAFFIC_LOG.lock().map_err(|e| format!("Failed to lock traffic log: {}", e))?;
+    Ok(log.entries.iter().cloned().collect())
+}
+
+pub fn clear_traffic_log_sync() -> Result<(), String> {
+    let mut log = TRAFFIC_LOG.lock().map_err(|e| format!("Failed to lock traffic log: {}", e))?;
+    log.clear();
+    Ok(())
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+// Text capture: one header line per frame followed by a 16-byte-per-row hex dump
+pub fn format_text(entries: &[TrafficEntry]) -> String {
+    let mut out = String::new();
+    for e in entries {
+        let dir = match e.direction { Direction::Tx => "TX", Direction::Rx => "RX" };
+        let _ = write!(out, "#{} t={}.{:06} {} addr=0x{:02X} cmd={} ({}) len={} crc={:?}",
+            e.seq, e.timestamp_us / 1_000_000, e.timestamp_us % 1_000_000, dir, e.addr, e.cmd, e.command, e.bytes.len(), e.crc);
+        if let Some(l) = e.latency_us { let _ = write!(out, " latency={}us", l); }
+        if let Some(err) = &e.error { let _ = write!(out, " error=\"{}\"", err); }
+        out.push('\n');
+        for (i, chunk) in e.bytes.chunks(16).enumerate() {
+            let _ = writeln!(out, "  {:04X}  {}", i * 16, hex(chunk));
+        }
+    }
+    out
+}
+
+// pcap capture (LINKTYPE_USER0). Each record is [direction (0 = TX, 1 = RX), addr, cmd, frame..]
+// so the capture can be opened in Wireshark with a simple user DLT dissector.
+pub fn format_pcap(entries: &[TrafficEntry]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes()); // magic (microsecond timestamps)
+    out.extend_from_slice(&2u16.to_le_bytes());
+    out.extend_from_slice(&4u16.to_le_bytes());
+    out.extend_from_slice(&0i32.to_le_bytes()); // thiszone
+    out.extend_from_slice(&0u32.to_le_bytes()); // sigfigs
+    out.extend_from_slice(&65535u32.to_le_bytes()); // snaplen
+    out.extend_from_slice(&147u32.to_le_bytes()); // LINKTYPE_USER0
+    for e in entries {
+        let mut rec = vec![if e.direction == Direction::Tx { 0 } else { 1 }, e.addr, e.cmd];
+        rec.extend_from_slice(&e.bytes);
+        out.extend_from_slice(&((e.timestamp_us / 1_000_000) as u32).to_le_bytes());
+        out.extend_from_slice(&((e.timestamp_us % 1_000_000) as u32).to_le_bytes());
+        out.extend_from_slice(&(rec.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(rec.len() as u32).to_le_bytes());
+        out.extend_from_slice(&rec);
+    }
+    out
+}
+
+// Replay session: the capture as request/reply pairs, which the replay transport (test
+// builds only) feeds back to the device layer. The sessions under captures/ are synthetic
+// fixtures built from the protocol spec, not recordings from hardware.
+
+pub const REPLAY_SESSION_VERSION: u32 = 1;
+
+// One request and the reply the controller sent back (empty = no reply / timeout).
+// Frames are hex strings ("80 49 ...") so captures stay readable and diffable.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exchange {
+    pub tx: String,
+    pub rx: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplaySession {
+    pub version: u32,
+    #[serde(default)]
+    pub description: String,
+    pub exchanges: Vec<Exchange>,
+}
+
+impl ReplaySession {
+    // Build a session from a traffic capture by pairing each TX frame with the RX that follows it
+    pub fn from_traffic(entries: &[TrafficEntry], description: &str) -> Self {
+        let mut exchanges: Vec<Exchange> = Vec::new();
+        let mut iter = entries.iter().peekable();
+        while let Some(e) = iter.next() {
+            if e.direction != Direction::Tx { continue; }
+            let rx = match iter.peek() {
+                Some(next) if next.direction == Direction::Rx => hex(&iter.next().unwrap().bytes),
+                _ => String::new(),
+            };
+            exchanges.push(Exchange { tx: hex(&e.bytes), rx });
+        }
+        ReplaySession { version: REPLAY_SESSION_VERSION, description: description.to_string(), exchanges }
+    }
+}
+
+// Export the current buffer to `path`; format is "text" (default), "pcap",
+// or "replay" (a session file the replay transport can play back in tests)
+pub fn export_traffic_log_sync(path: String, format: Option<String>) -> Result<usize, String> {
+    let entries = get_traffic_log_sync()?;
+    let data = match format.as_deref().unwrap_or("text") {
+        "text" | "txt" => format_text(&entries).into_bytes(),
+        "pcap" => format_pcap(&entries),
+        "replay" => {
+            let session = ReplaySession::from_traffic(&entries, "Exported from traffic log");
+            serde_json::to_vec_pretty(&session).map_err(|e| format!("Failed to serialize session: {}", e))?
+        }
+        other => return Err(format!("Unknown export format: {} (expected text, pcap or replay)", other)),
+    };
+    let mut file = std::fs::File::create(&path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    file.write_all(&data).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame_with_crc(mut data: Vec<u8>) -> Vec<u8> {
+        let crc = calc_crc(&data);
+        data.push((crc >> 8) as u8);
+        data.push((crc & 0xFF) as u8);
+        data
+    }
+
+    #[test]
+    fn ring_buffer_is_bounded() {
+        let mut log = TrafficLog::new(3);
+        for i in 0..5u8 {
+            log.push(TrafficEntry {
+                seq: 0, timestamp_us: i as u64, direction: Direction::Tx, addr: 0x80, cmd: i,
+                command: String::new(), bytes: vec![i], crc: CrcStatus::NoCrc, latency_us: None, error: None,
+            });
+        }
+        assert_eq!(log.entries.len(), 3);
+        assert_eq!(log.entries.front().unwrap().seq, 2);
+        assert_eq!(log.entries.back().unwrap().seq, 4);
+    }
+
+    #[test]
+    fn crc_classification() {
+        let tx = frame_with_crc(vec![0x80, 32, 0x40, 0x00]);
+        assert_eq!(tx_crc_status(&tx), CrcStatus::Ok);
+        assert_eq!(tx_crc_status(&[0x80, 18]), CrcStatus::NoCrc);
+
+        // read speed reply: crc over addr, cmd, payload
+        let mut full = frame_with_crc(vec![0x80, 18, 0, 0, 0x01, 0x00, 0]);
+        let reply = full.split_off(2);
+        assert_eq!(rx_crc_status(0x80, 18, &reply), CrcStatus::Ok);
+        let mut bad = reply.clone();
+        bad[0] ^= 1;
+        assert_eq!(rx_crc_status(0x80, 18, &bad), CrcStatus::Mismatch);
+        assert_eq!(rx_crc_status(0x80, 32, &[0xFF]), CrcStatus::Ack);
+        assert_eq!(rx_crc_status(0x80, 32, &[]), CrcStatus::Missing);
+    }
+
+    #[test]
+    fn failed_write_is_logged_with_its_frame() {
+        let _guard = crate::sim::tests::TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        set_traffic_logging_sync(true, None).unwrap();
+        let mut rc = crate::device::Roboclaw { addr: 0x87, baud_rate: 115_200, port_name: String::new(), port: None, known_addrs: Vec::new() };
+        assert!(crate::device::send_and_read(&[0x87, 21], &mut rc).is_err());
+        let log = get_traffic_log_sync().unwrap();
+        set_traffic_logging_sync(false, None).unwrap();
+        let rx = log.iter().rev().find(|e| e.addr == 0x87 && matches!(e.direction, Direction::Rx)).expect("no entry for the failed write");
+        assert!(rx.error.as_deref().is_some_and(|e| e.contains("not opened")), "{:?}", rx.error);
+    }
+}
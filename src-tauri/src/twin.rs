@@ -0,0 +1,603 @@
+use std::f64::consts::TAU;
+use std::time::{Duration, Instant};
+use num_complex::Complex64;
+use serde::{Serialize, Deserialize};
+
+use crate::device;
+use crate::disturbance::Load;
+use crate::estimators::FrfPoint;
+use crate::motor::Motor;
+use crate::plant::{self, DcMotorParams, Nonlinearities, PlantModel, PlantState, MAX_OMEGA_N};
+use crate::safety::{self, AllowedDirection};
+use crate::settings::{self, SimPlant};
+use crate::sim::{is_simulation_enabled, sim_update, sim_wait, SIM_STATE};
+
+// Digital twin: identify each motor of the connected controller with open-loop duty
+// experiments (step, deadband ramp in both directions, sine sweep) and fit the simulator's
+// plant models and nonlinearities to the results, so tuning can continue offline.
+// The simulator runs the richest model, the DC motor; the linear fits are reported
+// alongside it. Speed alone cannot separate R, Kt and inertia, so the step also
+// records the motor current.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TwinOptions {
+    pub motors: Vec<Motor>,
+    pub sample_ms: u32,
+    pub settle_s: f64,         // zero duty before each experiment
+    pub step_duty: f32,        // step size (0..1); also the sweep's operating point
+    pub step_s: f64,
+    pub ramp_rate: f32,        // deadband ramp slope (duty per second)
+    pub ramp_max: f32,         // duty at the end of the ramp
+    pub sweep_hz: Vec<f64>,    // empty skips the sweep
+    pub sweep_amplitude: f32,  // duty
+    pub sweep_cycles: u32,     // measured cycles per frequency, after one settling cycle
+}
+
+impl Default for TwinOptions {
+    fn default() -> Self {
+        TwinOptions {
+            motors: Motor::ALL.to_vec(),
+            sample_ms: 10,
+            settle_s: 0.5,
+            step_duty: 0.5,
+            step_s: 1.5,
+            ramp_rate: 0.1,
+            ramp_max: 0.5,
+            sweep_hz: vec![0.5, 1.0, 2.0, 4.0],
+            sweep_amplitude: 0.15,
+            sweep_cycles: 4,
+        }
+    }
+}
+
+impl TwinOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.motors.is_empty() { return Err("No motors selected".into()); }
+        if !(1..=1000).contains(&self.sample_ms) { return Err(format!("sample_ms must be within 1..1000 (got {})", self.sample_ms)); }
+        if !self.settle_s.is_finite() || self.settle_s < 0.0 || !self.step_s.is_finite() || self.step_s <= 0.0 {
+            return Err("settle_s must be >= 0 and step_s > 0".into());
+        }
+        for (name, v) in [("step_duty", self.step_duty), ("ramp_max", self.ramp_max)] {
+            if !v.is_finite() || v <= 0.0 || v > 1.0 { return Err(format!("{} must be within 0..1 (got {})", name, v)); }
+        }
+        if !self.ramp_rate.is_finite() || self.ramp_rate <= 0.0 { return Err(format!("ramp_rate must be > 0 (got {})", self.ramp_rate)); }
+        if self.sweep_hz.is_empty() { return Ok(()); }
+        let (lo, hi) = (self.step_duty - self.sweep_amplitude, self.step_duty + self.sweep_amplitude);
+        if !self.sweep_amplitude.is_finite() || self.sweep_amplitude <= 0.0 || lo <= 0.0 || hi > 1.0 {
+            return Err(format!("sweep_amplitude {} must keep the duty within 0..1 around step_duty {}", self.sweep_amplitude, self.step_duty));
+        }
+        if self.sweep_cycles == 0 { return Err("sweep_cycles must be > 0".into()); }
+        let max_hz = 1000.0 / (8.0 * self.sample_ms as f64);
+        if let Some(f) = self.sweep_hz.iter().find(|f| !f.is_finite() || **f <= 0.0 || **f > max_hz) {
+            return Err(format!("Sweep frequency {} Hz must be within 0..{} Hz (8 samples per cycle)", f, max_hz));
+        }
+        Ok(())
+    }
+
+    fn max_duty(&self) -> f32 {
+        let sweep = if self.sweep_hz.is_empty() { 0.0 } else { self.step_duty + self.sweep_amplitude };
+        self.step_duty.max(self.ramp_max).max(sweep)
+    }
+}
+
+// Two-point (28%/63%) first-order-plus-dead-time estimate from the step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepFit {
+    pub final_pps: f32,
+    pub tau_s: f32,
+    pub dead_time_s: f32,
+}
+
+// One direction of the deadband ramp, corrected for the plant lag
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RampFit {
+    pub breakaway: f32, // duty at which the motor started to turn
+    pub deadband: f32,  // duty where the steady-state speed line crosses zero
+    pub slope: f32,     // pps per unit duty above the deadband
+}
+
+// A fitted plant structure and its error against the step and sweep, plus the step's
+// current for the DC motor (normalized, lower is better)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelFit {
+    pub model: PlantModel,
+    pub tau: f32,
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MotorTwin {
+    pub motor: Motor,
+    pub plant: SimPlant, // what the simulator will run
+    pub step: StepFit,
+    pub forward: RampFit,
+    pub reverse: RampFit,
+    pub frf: Vec<FrfPoint>, // gain in pps per unit duty
+    pub candidates: Vec<ModelFit>, // the linear models, then the DC motor
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TwinReport {
+    pub motors: Vec<MotorTwin>,
+    pub profile: Option<String>, // where the twin was saved
+}
+
+/// Run the identification experiments on each selected motor and fit the simulator to them.
+/// With `profile`, the fitted plants are saved as that profile's sim plant (a new profile
+/// runs on the simulator). The motors are left stopped, also on error.
+pub fn build_twin_sync(options: &TwinOptions, profile: Option<&str>) -> Result<TwinReport, String> {
+    options.validate()?;
+    let limits = safety::get_safety_config_sync()?;
+    let needed = (options.max_duty() * 32767.0).round() as i16;
+    for &m in &options.motors {
+        let s = &limits[m.index()];
+        if s.max_duty < needed || s.max_slew_per_s.is_some() || s.direction != AllowedDirection::Both {
+            return Err(format!("The {} safety limits would alter the test duty (needs max_duty >= {}, no slew limit, both directions)", m, needed));
+        }
+    }
+    let mut motors = Vec::new();
+    for &m in &options.motors {
+        let result = identify_motor(m, options);
+        let stopped = device::drive_pwm_sync(0, m);
+        motors.push(result?);
+        stopped?;
+    }
+    if let Some(name) = profile {
+        let plants: Vec<(Motor, SimPlant)> = motors.iter().map(|t| (t.motor, t.plant.clone())).collect();
+        settings::store_sim_twin_sync(name, &plants)?;
+    }
+    Ok(TwinReport { motors, profile: profile.map(str::to_string) })
+}
+
+fn identify_motor(motor: Motor, opts: &TwinOptions) -> Result<MotorTwin, String> {
+    println!("[TWIN] identifying {}", motor);
+    let supply_v = supply_voltage()?;
+    let mut currents = Vec::new();
+    let step_samples = run_duty(motor, opts, opts.step_s, |_| opts.step_duty, Some(&mut currents))?;
+    let step = fit_step(motor, &step_samples)?;
+    let lag_s = step.tau_s + step.dead_time_s;
+    let threshold = (0.02 * step.final_pps.abs()).max(3.0);
+
+    let ramp_s = (opts.ramp_max / opts.ramp_rate) as f64;
+    let mut ramps = Vec::new();
+    for sign in [1.0_f32, -1.0] {
+        let samples = run_duty(motor, opts, ramp_s, |t| sign * (opts.ramp_rate * t as f32).min(opts.ramp_max), None)?;
+        ramps.push(fit_ramp(motor, &samples, sign, threshold, lag_s, opts.ramp_rate)?);
+    }
+    let (forward, reverse) = (ramps[0].clone(), ramps[1].clone());
+
+    let mut frf = Vec::new();
+    for &f in &opts.sweep_hz {
+        let cycles = (1 + opts.sweep_cycles) as f64;
+        let samples = run_duty(motor, opts, cycles / f, |t| opts.step_duty + opts.sweep_amplitude * (TAU * f * t).sin() as f32, None)?;
+        frf.push(fit_sine(&samples, f, opts.sweep_amplitude)?);
+    }
+
+    // normalized responses: the step by its final value, the sweep by the ramp's slope
+    let step_norm: Vec<(f64, f64)> = step_samples.iter().map(|&(t, _, v)| (t, v as f64 / step.final_pps as f64)).collect();
+    let frf_norm: Vec<(f64, Complex64)> = frf
+        .iter()
+        .map(|p| (TAU * p.freq_hz, Complex64::from_polar(p.gain / forward.slope as f64, p.phase_deg.to_radians())))
+        .collect();
+    let hold_s = opts.sample_ms as f64 / 2000.0;
+    let mut candidates = fit_models(&step, &step_norm, &frf_norm, hold_s);
+
+    let deadband = ((forward.deadband + reverse.deadband) / 2.0).min(0.95);
+    let reverse_gain = reverse.slope / forward.slope;
+    // static friction acts on the shaped duty: the breakaway margin above the deadband
+    let margin = |r: &RampFit, gain: f32| ((r.breakaway - deadband) * gain).max(0.0);
+    let static_friction = (margin(&forward, 1.0) + margin(&reverse, reverse_gain)) / 2.0;
+    // below a few samples' worth of ramp the margin is measurement noise
+    let resolution = opts.ramp_rate * opts.sample_ms as f32 / 1000.0 * 3.0 + 0.01;
+    let nonlinear = Nonlinearities {
+        deadband,
+        static_friction: if static_friction > resolution { static_friction.min(0.95) } else { 0.0 },
+        reverse_gain,
+        ..Default::default()
+    };
+    // the step drove the plant with the duty left after the deadband
+    let u = ((opts.step_duty - deadband) as f64).max(1e-3);
+    let dc = fit_dc_motor(&step, u, supply_v, &currents, &step_norm, &frf_norm, hold_s).map_err(|e| format!("{}: {}", motor, e))?;
+    candidates.push(dc.clone());
+    let plant = SimPlant { tau: dc.tau, gain: forward.slope, model: dc.model, nonlinear };
+    println!("[TWIN] {} fitted {:?} tau={} gain={} {:?}", motor, plant.model, plant.tau, plant.gain, plant.nonlinear);
+    Ok(MotorTwin { motor, plant, step, forward, reverse, frf, candidates })
+}
+
+// Experiment time: simulated when the simulator is on, wall time otherwise
+struct Clock {
+    start: Instant,
+    sim_start: f64,
+}
+
+impl Clock {
+    fn start() -> Result<Self, String> {
+        Ok(Clock { start: Instant::now(), sim_start: sim_time()? })
+    }
+
+    fn elapsed_s(&self) -> Result<f64, String> {
+        if is_simulation_enabled() { Ok(sim_time()? - self.sim_start) } else { Ok(self.start.elapsed().as_secs_f64()) }
+    }
+}
+
+fn sim_time() -> Result<f64, String> {
+    if !is_simulation_enabled() { return Ok(0.0); }
+    let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+    sim_update(&mut sim);
+    Ok(sim.time_s)
+}
+
+fn pause(dt: Duration) -> Result<(), String> {
+    if is_simulation_enabled() {
+        let mut sim = SIM_STATE.lock().map_err(|e| format!("Failed to lock sim: {}", e))?;
+        sim_wait(&mut sim, dt);
+    } else {
+        std::thread::sleep(dt);
+    }
+    Ok(())
+}
+
+// Main battery voltage the experiments run on (the DC motor's supply)
+fn supply_voltage() -> Result<f64, String> {
+    let tenths = device::read_all_status_sync()?.get("main_batt").and_then(|v| v.as_i64()).unwrap_or(0);
+    Ok(if tenths > 0 { tenths as f64 / 10.0 } else { DcMotorParams::default().supply_v as f64 })
+}
+
+// Stop and settle, then drive `duty(t)` for `duration_s`. Returns (t, duty, speed) samples;
+// each speed is read right after the duty for that sample was sent. With `currents`, the
+// motor current is read too, as (t, amps). Lost replies are skipped.
+fn run_duty(
+    motor: Motor,
+    opts: &TwinOptions,
+    duration_s: f64,
+    duty: impl Fn(f64) -> f32,
+    mut currents: Option<&mut Vec<(f64, f64)>>,
+) -> Result<Vec<(f64, f32, i32)>, String> {
+    device::drive_pwm_sync(0, motor)?;
+    pause(Duration::from_secs_f64(opts.settle_s))?;
+    let sample = Duration::from_millis(opts.sample_ms as u64);
+    let clock = Clock::start()?;
+    let mut samples = Vec::new();
+    loop {
+        let t = clock.elapsed_s()?;
+        if t > duration_s { break; }
+        let d = duty(t).clamp(-1.0, 1.0);
+        device::drive_pwm_sync((d * 32767.0).round() as i16, motor)?;
+        match device::read_speed_sync(motor) {
+            Ok(v) => samples.push((t, d, v)),
+            Err(e) => eprintln!("[TWIN] {} speed read failed: {}", motor, e),
+        }
+        if let Some(currents) = currents.as_mut() {
+            match device::read_motor_currents_sync() {
+                Ok((m1, m2)) => currents.push((t, motor.pick(m1, m2) as f64 / 100.0)), // 10 mA units
+                Err(e) => eprintln!("[TWIN] {} current read failed: {}", motor, e),
+            }
+        }
+        pause(sample)?;
+    }
+    device::drive_pwm_sync(0, motor)?;
+    Ok(samples)
+}
+
+// First time the normalized response `ys` reaches `level`, interpolated between samples
+fn crossing(samples: &[(f64, f64)], level: f64) -> Option<f64> {
+    samples.windows(2).find(|w| w[1].1 >= level).map(|w| {
+        let ((t0, y0), (t1, y1)) = (w[0], w[1]);
+        if y1 > y0 && y0 < level { t0 + (t1 - t0) * (level - y0) / (y1 - y0) } else { t1 }
+    })
+}
+
+fn fit_step(motor: Motor, samples: &[(f64, f32, i32)]) -> Result<StepFit, String> {
+    let tail = &samples[samples.len() * 3 / 4..];
+    if tail.is_empty() { return Err(format!("No speed samples from {}", motor)); }
+    let final_pps = tail.iter().map(|s| s.2 as f64).sum::<f64>() / tail.len() as f64;
+    if final_pps.abs() < 5.0 {
+        return Err(format!("{} did not turn during the step (final speed {:.1} pps); check wiring or raise step_duty", motor, final_pps));
+    }
+    let norm: Vec<(f64, f64)> = samples.iter().map(|&(t, _, v)| (t, v as f64 / final_pps)).collect();
+    let (t28, t63) = match (crossing(&norm, 0.283), crossing(&norm, 0.632)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return Err(format!("{} step response did not rise; lengthen step_s", motor)),
+    };
+    let tau = (1.5 * (t63 - t28)).max(0.001);
+    let dead_time = (t63 - tau).max(0.0);
+    Ok(StepFit { final_pps: final_pps as f32, tau_s: tau as f32, dead_time_s: dead_time as f32 })
+}
+
+// Fit speed = slope * (duty - deadband) to the upper half of the moving part of a ramp.
+// The speed trails the ramp by about `lag_s`, which would read as extra deadband.
+fn fit_ramp(motor: Motor, samples: &[(f64, f32, i32)], sign: f32, threshold: f32, lag_s: f32, rate: f32) -> Result<RampFit, String> {
+    let pts: Vec<(f64, f64)> = samples.iter().map(|&(_, d, v)| ((d * sign) as f64, (v as f32 * sign) as f64)).collect();
+    let start = pts.iter().position(|p| p.1 > threshold as f64).ok_or_else(|| {
+        format!("{} did not turn {} during the deadband ramp; raise ramp_max", motor, if sign > 0.0 { "forward" } else { "in reverse" })
+    })?;
+    let breakaway = pts[start].0;
+    let top = pts.iter().map(|p| p.0).fold(0.0, f64::max);
+    let upper: Vec<&(f64, f64)> = pts[start..].iter().filter(|p| p.0 >= (breakaway + top) / 2.0).collect();
+    if upper.len() < 3 { return Err(format!("Too few {} ramp samples above breakaway; slow ramp_rate or raise ramp_max", motor)); }
+    let n = upper.len() as f64;
+    let (mx, my) = (upper.iter().map(|p| p.0).sum::<f64>() / n, upper.iter().map(|p| p.1).sum::<f64>() / n);
+    let sxy: f64 = upper.iter().map(|p| (p.0 - mx) * (p.1 - my)).sum();
+    let sxx: f64 = upper.iter().map(|p| (p.0 - mx).powi(2)).sum();
+    let slope = sxy / sxx;
+    if !slope.is_finite() || slope <= 0.0 { return Err(format!("{} speed did not grow with duty during the ramp", motor)); }
+    let shift = (rate * lag_s) as f64;
+    let deadband = (mx - my / slope - shift).max(0.0);
+    // without static friction the speed crosses the threshold threshold/slope above the deadband
+    let breakaway = (breakaway - shift - threshold as f64 / slope).max(0.0);
+    Ok(RampFit { breakaway: breakaway as f32, deadband: deadband as f32, slope: slope as f32 })
+}
+
+// Gain and phase of the speed at `freq_hz` relative to the commanded sine, skipping the first cycle
+fn fit_sine(samples: &[(f64, f32, i32)], freq_hz: f64, amplitude: f32) -> Result<FrfPoint, String> {
+    let w = TAU * freq_hz;
+    let used: Vec<&(f64, f32, i32)> = samples.iter().filter(|s| s.0 >= 1.0 / freq_hz).collect();
+    if used.len() < 8 { return Err(format!("Too few samples at {} Hz", freq_hz)); }
+    let n = used.len() as f64;
+    let mean = used.iter().map(|s| s.2 as f64).sum::<f64>() / n;
+    let a = 2.0 / n * used.iter().map(|s| (s.2 as f64 - mean) * (w * s.0).sin()).sum::<f64>();
+    let b = 2.0 / n * used.iter().map(|s| (s.2 as f64 - mean) * (w * s.0).cos()).sum::<f64>();
+    Ok(FrfPoint { freq_hz, gain: a.hypot(b) / amplitude as f64, phase_deg: b.atan2(a).to_degrees() })
+}
+
+// Model and tau for a candidate structure and its parameter vector
+fn candidate(kind: usize, p: &[f64], tau_two_point: f32) -> (PlantModel, f32) {
+    match kind {
+        0 => (PlantModel::FirstOrder, p[0] as f32),
+        1 => (PlantModel::FirstOrderDeadTime { dead_time_s: p[1] as f32 }, p[0] as f32),
+        2 => (PlantModel::TwoStageLag { tau2: p[1] as f32 }, p[0] as f32),
+        // tau is unused by the second-order model; keep the step estimate for reference
+        _ => (PlantModel::SecondOrder { zeta: p[0] as f32, omega_n: p[1] as f32 }, tau_two_point),
+    }
+}
+
+// Fit every linear structure to the normalized step and sweep; the first-order lag comes first
+fn fit_models(step: &StepFit, step_norm: &[(f64, f64)], frf_norm: &[(f64, Complex64)], hold_s: f64) -> Vec<ModelFit> {
+    let lag = (step.tau_s + step.dead_time_s) as f64;
+    let starts = [
+        vec![lag],
+        vec![step.tau_s as f64, (step.dead_time_s as f64).max(0.005)],
+        vec![0.7 * lag, 0.3 * lag],
+        vec![0.8, (2.0 / lag).min(MAX_OMEGA_N as f64 / 2.0)],
+    ];
+    starts
+        .into_iter()
+        .enumerate()
+        .map(|(kind, x0)| {
+            let cost = |p: &[f64]| {
+                let (model, tau) = candidate(kind, p, step.tau_s);
+                let tau_ok = kind == 3 || tau > 0.0;
+                if !tau_ok || p.iter().any(|v| v.is_nan() || *v < 0.0) || model.validate().is_err() { return f64::INFINITY; }
+                fit_cost(&model, tau, step_norm, frf_norm, hold_s)
+            };
+            let (p, cost) = minimize(x0, cost);
+            let (model, tau) = candidate(kind, &p, step.tau_s);
+            ModelFit { model, tau, cost }
+        })
+        .collect()
+}
+
+// DC motor behind the step. For a given resistance the steady state fixes the torque
+// constant (equal to the back-EMF constant in SI units) and the viscous friction; the
+// resistance and inertia are then fitted to the step's speed and current and to the sweep.
+// L keeps the default electrical time constant, far below the sample period. The encoder,
+// gearing and load inertia keep their defaults: the speed in counts only shows their
+// products with the fitted values.
+fn fit_dc_motor(
+    step: &StepFit,
+    u: f64,
+    supply_v: f64,
+    currents: &[(f64, f64)],
+    step_norm: &[(f64, f64)],
+    frf_norm: &[(f64, Complex64)],
+    hold_s: f64,
+) -> Result<ModelFit, String> {
+    let base = DcMotorParams::default();
+    let omega_ss = step.final_pps as f64 / (base.encoder_cpr as f64 / TAU);
+    let tail = &currents[currents.len() * 3 / 4..];
+    let i_ss = if tail.is_empty() { 0.0 } else { tail.iter().map(|c| c.1).sum::<f64>() / tail.len() as f64 };
+    let i_peak = currents.iter().map(|c| c.1).fold(0.0, f64::max);
+    let drive_v = supply_v * u;
+    let params = |r: f64, j: f64| {
+        let k = (drive_v - r * i_ss) / omega_ss;
+        let p = DcMotorParams {
+            resistance_ohm: r as f32,
+            inductance_h: r as f32 * base.inductance_h / base.resistance_ohm,
+            torque_constant: k as f32,
+            back_emf_constant: k as f32,
+            rotor_inertia: j as f32,
+            viscous_friction: (k * i_ss / omega_ss) as f32,
+            supply_v: supply_v as f32,
+            ..base.clone()
+        };
+        p.validate().ok().map(|_| p)
+    };
+    // at the step edge there is no back-EMF yet: the current is the drive voltage over R.
+    // R must leave a positive torque constant at the steady-state current.
+    let r_max = if i_ss > 0.0 { drive_v / i_ss } else { f64::INFINITY };
+    let r0 = if i_peak > 0.0 { drive_v / i_peak } else { base.resistance_ohm as f64 }.min(0.5 * r_max);
+    // inertia from the mechanical time constant R J / (R b + k²), taken as the step's lag
+    let lag = (step.tau_s + step.dead_time_s) as f64;
+    let p0 = params(r0, 1.0).ok_or_else(|| format!("step gives no valid DC motor (final speed {} pps)", step.final_pps))?;
+    let j0 = lag * (r0 * p0.viscous_friction as f64 + (p0.torque_constant as f64).powi(2)) / r0;
+
+    // parameters relative to the start, so the search steps suit both scales
+    let cost = |x: &[f64]| {
+        let Some(p) = params(x[0] * r0, x[1] * j0) else { return f64::INFINITY };
+        let i_model = dc_current(&p, u, currents.iter().map(|c| c.0));
+        let current_err = currents.iter().zip(&i_model).map(|(c, i)| ((c.1 - i) / i_peak.max(1e-3)).powi(2)).sum::<f64>()
+            / currents.len().max(1) as f64;
+        fit_cost(&PlantModel::DcMotor(p), 0.0, step_norm, frf_norm, hold_s) + current_err
+    };
+    let (x, cost) = minimize(vec![1.0, 1.0], cost);
+    let p = params(x[0] * r0, x[1] * j0).ok_or("DC motor fit diverged")?;
+    let (r, k, b) = (p.resistance_ohm, p.torque_constant, p.viscous_friction);
+    // tau is unused by the DC motor model; report its mechanical time constant
+    let tau = r * p.inertia() / (r * b + k * k);
+    Ok(ModelFit { model: PlantModel::DcMotor(p), tau, cost })
+}
+
+// Armature current (A) of the DC motor after a step to duty `u` from rest, at the times `ts`
+fn dc_current(p: &DcMotorParams, u: f64, ts: impl Iterator<Item = f64>) -> Vec<f64> {
+    let h = 0.001_f32;
+    let (mut vel, mut current_a, mut t) = (0.0_f32, 0.0_f32, 0.0_f64);
+    ts.map(|target| {
+        while t + 1e-9 < target {
+            (vel, current_a) = p.step(vel, current_a, u as f32, 0.0, Load::default(), h);
+            t += h as f64;
+        }
+        current_a as f64
+    })
+    .collect()
+}
+
+// Mean squared error of the unit step response plus that of the normalized frequency response
+fn fit_cost(model: &PlantModel, tau: f32, step_norm: &[(f64, f64)], frf_norm: &[(f64, Complex64)], hold_s: f64) -> f64 {
+    let ys = unit_step(model, tau, step_norm.iter().map(|s| s.0));
+    let step_err = step_norm.iter().zip(&ys).map(|(s, y)| (s.1 - y).powi(2)).sum::<f64>() / step_norm.len().max(1) as f64;
+    let frf_err = frf_norm
+        .iter()
+        .map(|&(w, h)| {
+            // the duty is held between samples: half a sample of extra delay
+            let model_h = frequency_response(model, tau, w) * Complex64::from_polar(1.0, -w * hold_s);
+            (model_h - h).norm_sqr()
+        })
+        .sum::<f64>()
+        / frf_norm.len().max(1) as f64;
+    step_err + frf_err
+}
+
+// Unit-gain step response at the times `ts`, integrated like the simulator does
+fn unit_step(model: &PlantModel, tau: f32, ts: impl Iterator<Item = f64>) -> Vec<f64> {
+    let h = 0.001_f32;
+    let mut st = PlantState::default();
+    let (mut y, mut t) = (0.0_f32, 0.0_f64);
+    ts.map(|target| {
+        while t + 1e-9 < target {
+            y = match *model {
+                PlantModel::FirstOrderDeadTime { dead_time_s } => {
+                    let u = st.delayed(1.0, dead_time_s, h);
+                    plant::lag(y, u, tau, h)
+                }
+                PlantModel::SecondOrder { zeta, omega_n } => plant::second_order(&mut st, y, 1.0, zeta, omega_n, h),
+                PlantModel::TwoStageLag { tau2 } => {
+                    st.x1 = plant::lag(st.x1, 1.0, tau, h);
+                    plant::lag(y, st.x1, tau2, h)
+                }
+                PlantModel::DcMotor(ref p) => {
+                    let full = p.full_scale_pps();
+                    let (vel, current_a) = p.step(y * full, st.current_a, 1.0, 0.0, Load::default(), h);
+                    st.current_a = current_a;
+                    vel / full
+                }
+                PlantModel::FirstOrder => plant::lag(y, 1.0, tau, h),
+            };
+            t += h as f64;
+        }
+        y as f64
+    })
+    .collect()
+}
+
+fn frequency_response(model: &PlantModel, tau: f32, w: f64) -> Complex64 {
+    let s = Complex64::new(0.0, w);
+    let lag = |tau: f32| 1.0 / (1.0 + s * tau as f64);
+    match *model {
+        PlantModel::FirstOrderDeadTime { dead_time_s } => lag(tau) * (-s * dead_time_s as f64).exp(),
+        PlantModel::SecondOrder { zeta, omega_n } => {
+            let (z, wn) = (zeta as f64, omega_n as f64);
+            wn * wn / (s * s + 2.0 * z * wn * s + wn * wn)
+        }
+        PlantModel::TwoStageLag { tau2 } => lag(tau) * lag(tau2),
+        // (L s + R)(J s + b) + Kt Ke over its DC value
+        PlantModel::DcMotor(ref p) => {
+            let (r, l, b, j) = (p.resistance_ohm as f64, p.inductance_h as f64, p.viscous_friction as f64, p.inertia() as f64);
+            let kk = p.torque_constant as f64 * p.back_emf_constant as f64;
+            (r * b + kk) / ((s * l + r) * (s * j + b) + kk)
+        }
+        PlantModel::FirstOrder => lag(tau),
+    }
+}
+
+// Compass search: move one parameter at a time by its step while that lowers the cost,
+// halving the steps whenever no move helps
+fn minimize(x0: Vec<f64>, cost: impl Fn(&[f64]) -> f64) -> (Vec<f64>, f64) {
+    let mut x = x0;
+    let mut best = cost(&x);
+    let mut steps: Vec<f64> = x.iter().map(|v| v.abs().max(0.01) * 0.5).collect();
+    for _ in 0..300 {
+        let mut improved = false;
+        for i in 0..x.len() {
+            for dir in [1.0, -1.0] {
+                let mut trial = x.clone();
+                trial[i] += dir * steps[i];
+                let c = cost(&trial);
+                if c < best {
+                    (x, best, improved) = (trial, c, true);
+                    break;
+                }
+            }
+        }
+        if !improved {
+            steps.iter_mut().for_each(|s| *s *= 0.5);
+            if steps.iter().zip(&x).all(|(s, v)| *s < 1e-3 * v.abs().max(0.01)) { break; }
+        }
+    }
+    (x, best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{self, tests::{with_fast_clock, TEST_MUTEX}};
+
+    #[test]
+    fn model_fit_recovers_dead_time() {
+        let truth = PlantModel::FirstOrderDeadTime { dead_time_s: 0.04 };
+        let ts: Vec<f64> = (0..150).map(|i| i as f64 * 0.01).collect();
+        let step_norm: Vec<(f64, f64)> = ts.iter().copied().zip(unit_step(&truth, 0.12, ts.iter().copied())).collect();
+        // rough two-point start, as from a coarsely sampled step
+        let step = StepFit { final_pps: 1.0, tau_s: 0.1, dead_time_s: 0.06 };
+        let fits = fit_models(&step, &step_norm, &[], 0.0);
+        assert_eq!(fits.len(), 4);
+        let fodt = &fits[1];
+        assert!(fodt.cost < fits[0].cost * 0.5, "{:?}", fits);
+        match fodt.model {
+            PlantModel::FirstOrderDeadTime { dead_time_s } => assert!((dead_time_s - 0.04).abs() < 0.005, "{:?}", fodt),
+            _ => unreachable!(),
+        }
+        assert!((fodt.tau - 0.12).abs() < 0.01, "{:?}", fodt);
+    }
+
+    #[test]
+    fn twin_of_the_simulator_matches_its_plant() {
+        let _guard = TEST_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        sim::set_simulation_mode_sync(true).unwrap();
+        let truth = DcMotorParams { rotor_inertia: 4.0e-5, ..Default::default() };
+        let nonlinear = Nonlinearities { deadband: 0.1, reverse_gain: 0.8, ..Default::default() };
+        sim::set_sim_plant_sync(Motor::M1, PlantModel::DcMotor(truth.clone())).unwrap();
+        sim::set_sim_nonlinearities_sync(Motor::M1, nonlinear).unwrap();
+
+        let options = TwinOptions { motors: vec![Motor::M1], sweep_hz: vec![0.5, 1.0, 2.0], ramp_rate: 0.05, ..Default::default() };
+        let report = with_fast_clock(|| build_twin_sync(&options, None));
+
+        sim::set_sim_plant_sync(Motor::M1, PlantModel::FirstOrder).unwrap();
+        sim::set_sim_nonlinearities_sync(Motor::M1, Nonlinearities::default()).unwrap();
+        sim::set_simulation_mode_sync(false).unwrap();
+
+        let twin = &report.unwrap().motors[0];
+        let p = &twin.plant;
+        assert!((p.nonlinear.deadband - 0.1).abs() < 0.02, "{:?}", twin);
+        assert!((p.nonlinear.reverse_gain - 0.8).abs() < 0.05, "{:?}", twin);
+        assert_eq!(p.nonlinear.static_friction, 0.0, "{:?}", twin);
+        let PlantModel::DcMotor(dc) = &p.model else { panic!("expected the DC motor model: {:?}", twin) };
+        let close = |fit: f32, want: f32, tol: f32| ((fit - want) / want).abs() < tol;
+        assert!(close(dc.full_scale_pps(), truth.full_scale_pps(), 0.05), "{:?}", twin);
+        assert!(close(dc.resistance_ohm, truth.resistance_ohm, 0.05), "{:?}", twin);
+        assert!(close(dc.torque_constant, truth.torque_constant, 0.05), "{:?}", twin);
+        assert!(close(dc.inertia(), truth.inertia(), 0.05), "{:?}", twin);
+        assert_eq!(twin.candidates.len(), 5);
+        assert_eq!(twin.frf.len(), 3);
+    }
+}
@@ -21,6 +21,18 @@ const PWM_MAX = 32767;
 const SIMULATED_PORT = "SIMULATED";
 const BAUD_OPTIONS = [9600, 19200, 38400, 57600, 115200, 230400, 460800, 921600];
 
+// Mirrors SerialPortEntry in src-tauri/src/device.rs
+type SerialPortEntry = {
+  path: string;
+  by_id: string | null;
+  vid: number | null;
+  pid: number | null;
+  serial_number: string | null;
+  manufacturer: string | null;
+  product: string | null;
+  likely_roboclaw: boolean;
+};
+
 function App() {
   //const [count, setCount] = useState<number>(0);
   //const increment = () => setCount(count + 1);
@@ -130,6 +142,23 @@ function App() {
     await invoke("drive_simply_async", { speed, motorIndex });
   }
 
+  // Emergency stop: halts both motors and latches until cleared
+  const [estopLatched, setEstopLatched] = useState<boolean>(false);
+
+  const handleEmergencyStop = async () => {
+    setEstopLatched(true);
+    try {
+      await invoke("emergency_stop");
+    } catch (error) {
+      alert(`Emergency stop reported an error: ${error}`);
+    }
+  }
+
+  const handleClearEstop = async () => {
+    await invoke("clear_estop");
+    setEstopLatched(false);
+  }
+
   // Stop motors
   const handleStopM1 = async () => {
     await handlePresetSpeed(1, SPEED_STOP as number);
@@ -198,7 +227,9 @@ function App() {
 
     setIsPortRefreshing(true);
     try {
-      const ports = await invoke("list_serial_ports") as string[];
+      const entries = await invoke("list_serial_ports", { filter: null }) as SerialPortEntry[];
+      // Prefer the stable by-id link so the selection survives re-enumeration
+      const ports = entries.map((p) => p.by_id ?? p.path);
       setAvailablePorts((prev) => {
         if (prev.length === ports.length && prev.every((value, index) => value === ports[index])) {
           return prev;
@@ -274,6 +305,21 @@ function App() {
     }
   }
 
+  // Pick up the connection the backend made from the last-used profile at startup
+  useEffect(() => {
+    (async () => {
+      try {
+        const info = await invoke("get_connection_info") as { port_name: string; baud_rate: number; connected: boolean; simulation: boolean };
+        if (!info.connected) return;
+        setIsConnected(true);
+        setConnectedPort(info.port_name);
+        setPortName(info.port_name);
+        setBaud(info.baud_rate);
+        setIsSimulation(info.simulation);
+      } catch {}
+    })();
+  }, []);
+
   useEffect(() => {
     refreshPorts();
     const interval = setInterval(refreshPorts, 2000);
@@ -457,6 +503,36 @@ function App() {
 	return () => clearInterval(interval);
   }, []);
   
+  // Latest safety-envelope intervention (clamp/reject) reported by the backend
+  const [safetyMessage, setSafetyMessage] = useState<string>("");
+
+  useEffect(() => {
+	const interval = setInterval(async () => {
+		try {
+			const events = await invoke("get_safety_events") as { timestamp_ms: number; message: string }[];
+			const last = events[events.length - 1];
+			setSafetyMessage(last ? last.message : "");
+		} catch {}
+	}, 1000);
+
+	return () => clearInterval(interval);
+  }, []);
+
+  // Motors cut by the backend fault monitor (stall / runaway / encoder inversion)
+  type FaultTrip = { motor_index: number; kind: string; message: string };
+  const [faultTrips, setFaultTrips] = useState<FaultTrip[]>([]);
+
+  useEffect(() => {
+	const interval = setInterval(async () => {
+		try {
+			const status = await invoke("get_monitor_status") as { trips: (FaultTrip | null)[] };
+			setFaultTrips(status.trips.filter((t): t is FaultTrip => t !== null));
+		} catch {}
+	}, 1000);
+
+	return () => clearInterval(interval);
+  }, []);
+
   // ====== HTML ===========
   return (
     <main className="mx-auto flex min-h-screen max-w-6xl flex-col gap-10 px-6 py-10">
@@ -464,12 +540,27 @@ function App() {
         isSimulation={isSimulation}
         isConnected={isConnected}
         connectedPort={connectedPort}
+        estopLatched={estopLatched}
+        onEmergencyStop={handleEmergencyStop}
+        onClearEstop={handleClearEstop}
       />
       {!driveEnabled && (
         <div className={styles.bannerWarning}>
           Serial port is not connected. Connect first to enable drive control.
         </div>
       )}
+      {safetyMessage !== "" && (
+        <div className={styles.bannerError}>
+          Safety limit: {safetyMessage}{" "}
+          <button className="underline" onClick={() => { void invoke("clear_safety_events"); setSafetyMessage(""); }}>Dismiss</button>
+        </div>
+      )}
+      {faultTrips.map((trip) => (
+        <div key={trip.motor_index} className={styles.bannerError}>
+          {trip.message}{" "}
+          <button className="underline" onClick={() => { void invoke("clear_motor_fault", { motorIndex: trip.motor_index }); }}>Re-enable M{trip.motor_index}</button>
+        </div>
+      ))}
       {isSimulation && (
         <div className={styles.bannerSimulation}>
           Simulation mode enabled. Drive commands use a virtual device and no serial port.